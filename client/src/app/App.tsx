@@ -1,11 +1,11 @@
-import React, { ReactElement, createContext, useEffect, useMemo, useState } from "react";
+import React, { ReactElement, createContext, useEffect, useMemo, useRef, useState } from "react";
 import Nav from "./Nav.tsx";
 import "./app.css";
 import CodeInput from "./CodeInput.tsx";
 import { saveFile } from "../index.tsx";
 import ImagePreview from "./ImagePreview.tsx";
-import { defaultGraph, defaultPlacements, Graph, GridPlacements } from "./graph.ts";
-import { generate_graph as generateGraph } from "../generator/server";
+import { defaultGraph, defaultPlacements, Graph, GridPlacements, PlacerOptions, placementsByName, Vec2 } from "./graph.ts";
+import { generate_graph_recovering as generateGraphRecovering } from "../generator/server";
 
 type Editor = {
     textContent: [string, (text: string) => void],
@@ -16,6 +16,8 @@ type Editor = {
 export const EditorContext = createContext<Editor | null>(null);
 export const GraphContext = createContext<Graph | null>(null);
 export const PlacementsContext = createContext<GridPlacements | null>(null);
+/** The size of each entity's box as it was last drawn, by name */
+export const MeasuredSizesContext = createContext<Record<string, Vec2>>({});
 
 export default function App(): ReactElement {
     const [textContent, setTextContent] = useState("");
@@ -23,6 +25,9 @@ export default function App(): ReactElement {
     const [fileName, setFileName] = useState("Untitled");
     const [graph, setGraph] = useState(defaultGraph());
     const [placements, setPlacements] = useState(defaultPlacements());
+    // Entities stay where they were while typing, so only new entities get placed
+    const previousPlacements = useRef<Record<string, Vec2>>({});
+    const measuredSizes = useRef<Record<string, Vec2>>({});
 
     const editor = useMemo(() => {
         return {
@@ -45,25 +50,30 @@ export default function App(): ReactElement {
     }, [textContent, fileName]);
 
     useEffect(() => {
-        const graphResult = generateGraph(textContent);
-        console.log(JSON.stringify(graphResult));
+        const options: PlacerOptions = { previous: previousPlacements.current, sizes: measuredSizes.current };
+        const [graph, placements, diagnostics] = generateGraphRecovering(textContent, options);
+        previousPlacements.current = placementsByName(graph, placements);
+        console.log(JSON.stringify(graph));
 
-        if ("Ok" in graphResult && graphResult.Ok) {
-            setGraph(graphResult.Ok[0]);
-            setPlacements(graphResult.Ok[1]);
-        } else {
+        setGraph(graph);
+        setPlacements(placements);
+
+        if (diagnostics.length > 0) {
             // TODO: Popup (or something else?)
+            console.warn(diagnostics);
         }
     }, [textContent])
 
     return <EditorContext.Provider value={editor}>
         <PlacementsContext.Provider value={placements}>
             <GraphContext.Provider value={graph} >
-                <Nav />
-                <main>
-                    <CodeInput />
-                    <ImagePreview />
-                </main>
+                <MeasuredSizesContext.Provider value={measuredSizes.current}>
+                    <Nav />
+                    <main>
+                        <CodeInput />
+                        <ImagePreview />
+                    </main>
+                </MeasuredSizesContext.Provider>
             </GraphContext.Provider>
         </PlacementsContext.Provider>
     </EditorContext.Provider>
@@ -1,7 +1,7 @@
 import React, { ReactElement, useContext, useEffect, useRef } from "react";
 import "./imagePreview.css";
-import { EditorContext, GraphContext, PlacementsContext } from "./App";
-import { Entity, Graph, GridPlacements, Multiplicity, Relation } from "./graph";
+import { EditorContext, GraphContext, MeasuredSizesContext, PlacementsContext } from "./App";
+import { Arrow, Attribute, Entity, Graph, GridPlacements, Multiplicity, Relation, Style, Vec2, Visibility } from "./graph";
 import MaterialIcon from "./Symbol";
 import { ReactZoomPanPinchContentRef, TransformComponent, TransformWrapper } from "react-zoom-pan-pinch";
 
@@ -14,6 +14,7 @@ export default function ImagePreview(): ReactElement {
     const zoomPanPinchRef = useRef<ReactZoomPanPinchContentRef>(null);
 
     const ARROW_HEAD_SIZE = 5;
+    const DIAMOND_SIZE = 8;
 
     return <div className="preview-container" ref={containerRef}>
         {hideUI || <div className="floating-buttons">
@@ -75,6 +76,35 @@ export default function ImagePreview(): ReactElement {
                                 L ${ARROW_HEAD_SIZE + 1} ${ARROW_HEAD_SIZE + 1}
                             `} stroke="black" fill="none" />
                         </marker>
+                        <marker id="triangle" markerWidth={DIAMOND_SIZE + 2} markerHeight={DIAMOND_SIZE + 2}
+                                refX={DIAMOND_SIZE + 1} refY={DIAMOND_SIZE / 2 + 1} orient="auto-start-reverse">
+                            <path d={`
+                                M 1 1
+                                L ${DIAMOND_SIZE + 1} ${DIAMOND_SIZE / 2 + 1}
+                                L 1 ${DIAMOND_SIZE + 1}
+                                Z
+                            `} stroke="black" fill="white" />
+                        </marker>
+                        <marker id="hollow-diamond" markerWidth={2 * DIAMOND_SIZE + 2} markerHeight={DIAMOND_SIZE + 2}
+                                refX={2 * DIAMOND_SIZE + 1} refY={DIAMOND_SIZE / 2 + 1} orient="auto-start-reverse">
+                            <path d={`
+                                M 1 ${DIAMOND_SIZE / 2 + 1}
+                                L ${DIAMOND_SIZE + 1} 1
+                                L ${2 * DIAMOND_SIZE + 1} ${DIAMOND_SIZE / 2 + 1}
+                                L ${DIAMOND_SIZE + 1} ${DIAMOND_SIZE + 1}
+                                Z
+                            `} stroke="black" fill="white" />
+                        </marker>
+                        <marker id="filled-diamond" markerWidth={2 * DIAMOND_SIZE + 2} markerHeight={DIAMOND_SIZE + 2}
+                                refX={2 * DIAMOND_SIZE + 1} refY={DIAMOND_SIZE / 2 + 1} orient="auto-start-reverse">
+                            <path d={`
+                                M 1 ${DIAMOND_SIZE / 2 + 1}
+                                L ${DIAMOND_SIZE + 1} 1
+                                L ${2 * DIAMOND_SIZE + 1} ${DIAMOND_SIZE / 2 + 1}
+                                L ${DIAMOND_SIZE + 1} ${DIAMOND_SIZE + 1}
+                                Z
+                            `} stroke="black" fill="black" />
+                        </marker>
                         {graph.relations.map((relation, index) => <RelationComponent key={index} index={index} relation={relation} parentRef={ref}/>)}
                     </svg>
                 </div>
@@ -128,6 +158,19 @@ export function lockAllEntities(textContent: string, setTextContent: (text: stri
     }
 }
 
+const ARROW_MARKERS: Record<Exclude<Arrow, "none" | "arrow">, string> = {
+    inheritance: "triangle",
+    aggregation: "hollow-diamond",
+    composition: "filled-diamond"
+};
+
+const LINE_DASHES: Record<Style, string | undefined> = {
+    regular: undefined,
+    dotted: "2 4",
+    dashed: "8 6",
+    bold: undefined
+};
+
 function RelationComponent(props: Readonly<{
     relation: Relation,
     index: number,
@@ -139,6 +182,10 @@ function RelationComponent(props: Readonly<{
 
     const entity1Pos = placements.nodes[relation.entity1].position;
     const entity2Pos = placements.nodes[relation.entity2].position;
+    const edge = placements.edges.find(edge => edge.relation === props.index);
+    const toPixels = (point: Vec2) => [50 * point.x + (ref.current?.clientWidth ?? 0) / 2, 50 * point.y + (ref.current?.clientHeight ?? 0) / 2];
+    const route = edge?.route.map(toPixels);
+    const labels = edge?.labels;
 
     let [x1, y1, x2, y2] = [
         50 * entity1Pos.x + (ref.current?.clientWidth ?? 0) / 2,
@@ -157,73 +204,79 @@ function RelationComponent(props: Readonly<{
     ]
     const slope = (y2 - y1) / (x2 - x1);
 
-    // First, truncate the start of the line
-    if (x2 - x1 === 0) {
-        if (y2 > y1) {
-            y1 = y1 + entity1Height / 2;
-        } else {
-            y1 = y1 - entity1Height / 2;
-        }
+    if (route !== undefined && route.length >= 2) {
+        // The route already starts and ends at the borders of the boxes
+        [x1, y1] = route[0];
+        [x2, y2] = route[route.length - 1];
     } else {
-        const entity1AspectRatio = entity1Height / entity1Width;
-
-        if (
-            (slope > entity1AspectRatio && x2 > x1) ||
-            (slope < -entity1AspectRatio && x2 < x1)
-        ) {
-            // Intersecting with top
-            y1 = y1 + entity1Height / 2;
-            x1 = x1 + entity1Height / 2 / slope;
-        } else if (
-            (slope < -entity1AspectRatio && x2 > x1) ||
-            (slope > entity1AspectRatio && x2 < x1)
-        ) {
-            // Intersecting with bottom
-            y1 = y1 - entity1Height / 2;
-            x1 = x1 - entity1Height / 2 / slope;
-        } else if (x2 > x1) {
-            // Intersecting with right
-            x1 = x1 + entity1Width / 2;
-            y1 = y1 + entity1Width / 2 * slope;
+        // First, truncate the start of the line
+        if (x2 - x1 === 0) {
+            if (y2 > y1) {
+                y1 = y1 + entity1Height / 2;
+            } else {
+                y1 = y1 - entity1Height / 2;
+            }
         } else {
-            // Intersecting with left
-            x1 = x1 - entity1Width / 2;
-            y1 = y1 - entity1Width / 2 * slope;
+            const entity1AspectRatio = entity1Height / entity1Width;
+
+            if (
+                (slope > entity1AspectRatio && x2 > x1) ||
+                (slope < -entity1AspectRatio && x2 < x1)
+            ) {
+                // Intersecting with top
+                y1 = y1 + entity1Height / 2;
+                x1 = x1 + entity1Height / 2 / slope;
+            } else if (
+                (slope < -entity1AspectRatio && x2 > x1) ||
+                (slope > entity1AspectRatio && x2 < x1)
+            ) {
+                // Intersecting with bottom
+                y1 = y1 - entity1Height / 2;
+                x1 = x1 - entity1Height / 2 / slope;
+            } else if (x2 > x1) {
+                // Intersecting with right
+                x1 = x1 + entity1Width / 2;
+                y1 = y1 + entity1Width / 2 * slope;
+            } else {
+                // Intersecting with left
+                x1 = x1 - entity1Width / 2;
+                y1 = y1 - entity1Width / 2 * slope;
+            }
         }
-    }
 
-    // Then, truncate the end of the line
-    if (x2 - x1 === 0) {
-        if (y2 > y1) {
-            y2 = y2 - entity2Height / 2;
-        } else {
-            y2 = y2 + entity2Height / 2;
-        }
-    } else {
-        const entity2AspectRatio = entity2Height / entity2Width;
-
-        if (
-            (slope > entity2AspectRatio && x2 > x1) ||
-            (slope < -entity2AspectRatio && x2 < x1)
-        ) {
-            // Intersecting with top
-            y2 = y2 - entity2Height / 2;
-            x2 = x2 - entity2Height / 2 / slope;
-        } else if (
-            (slope < -entity2AspectRatio && x2 > x1) ||
-            (slope > entity2AspectRatio && x2 < x1)
-        ) {
-            // Intersecting with bottom
-            y2 = y2 + entity2Height / 2;
-            x2 = x2 + entity2Height / 2 / slope;
-        } else if (x2 > x1) {
-            // Intersecting with right
-            x2 = x2 - entity2Width / 2;
-            y2 = y2 - entity2Width / 2 * slope;
+        // Then, truncate the end of the line
+        if (x2 - x1 === 0) {
+            if (y2 > y1) {
+                y2 = y2 - entity2Height / 2;
+            } else {
+                y2 = y2 + entity2Height / 2;
+            }
         } else {
-            // Intersecting with left
-            x2 = x2 + entity2Width / 2;
-            y2 = y2 + entity2Width / 2 * slope;
+            const entity2AspectRatio = entity2Height / entity2Width;
+
+            if (
+                (slope > entity2AspectRatio && x2 > x1) ||
+                (slope < -entity2AspectRatio && x2 < x1)
+            ) {
+                // Intersecting with top
+                y2 = y2 - entity2Height / 2;
+                x2 = x2 - entity2Height / 2 / slope;
+            } else if (
+                (slope < -entity2AspectRatio && x2 > x1) ||
+                (slope > entity2AspectRatio && x2 < x1)
+            ) {
+                // Intersecting with bottom
+                y2 = y2 + entity2Height / 2;
+                x2 = x2 + entity2Height / 2 / slope;
+            } else if (x2 > x1) {
+                // Intersecting with right
+                x2 = x2 - entity2Width / 2;
+                y2 = y2 - entity2Width / 2 * slope;
+            } else {
+                // Intersecting with left
+                x2 = x2 + entity2Width / 2;
+                y2 = y2 + entity2Width / 2 * slope;
+            }
         }
     }
 
@@ -231,46 +284,48 @@ function RelationComponent(props: Readonly<{
 
     if (relation.arrow1 === "arrow") {
         markerStart = "url(#reverse-arrowhead)";
+    } else if (relation.arrow1 !== "none") {
+        markerStart = `url(#${ARROW_MARKERS[relation.arrow1]})`;
     }
 
     if (relation.arrow2 === "arrow") {
         markerEnd = "url(#arrowhead)";
+    } else if (relation.arrow2 !== "none") {
+        markerEnd = `url(#${ARROW_MARKERS[relation.arrow2]})`;
     }
 
-    const [textX1, textY1] = [
-        x1 < x2 ? x1 : x2,
-        x1 < x2 ? y1 : y2
-    ]
-
-    const [textX2, textY2] = [
-        x1 < x2 ? x2 : x1,
-        x1 < x2 ? y2 : y1
-    ]
-
     const [mult1, mult2] = [
         x1 < x2 ? relation.mult1 : relation.mult2,
         x1 < x2 ? relation.mult2 : relation.mult1
     ]
 
+    const points = route !== undefined && route.length >= 2 ? route : [[x1, y1], [x2, y2]];
+    // The text goes left to right, so it's never upside down
+    const textPoints = x1 < x2 ? points : [...points].reverse();
+    const textPath = "M " + textPoints.map(([x, y]) => `${x} ${y}`).join(" L ");
+
     return <g key={`relation-${props.index}`}>
-        <line
-            x1={x1}
-            y1={y1}
-            x2={x2}
-            y2={y2}
+        <polyline
+            points={points.map(([x, y]) => `${x},${y}`).join(" ")}
+            fill="none"
             stroke="black"
-            strokeWidth="2"
+            strokeWidth={relation.style === "bold" ? 4 : 2}
+            strokeDasharray={LINE_DASHES[relation.style]}
             style={{zIndex: -1}}
             markerStart={markerStart}
             markerEnd={markerEnd}
         />
-        <text>
+        {labels !== undefined ? <>
+            {relation.text !== null && labels.text != null && <HighlightedText position={toPixels(labels.text)}>{relation.text}</HighlightedText>}
+            {labels.mult1 != null && <HighlightedText position={toPixels(labels.mult1)}><MultiplicityIndicator multiplicity={relation.mult1} /></HighlightedText>}
+            {labels.mult2 != null && <HighlightedText position={toPixels(labels.mult2)}><MultiplicityIndicator multiplicity={relation.mult2} /></HighlightedText>}
+        </> : <text>
             <HighlightedTextPath
                 startOffset="50%"
                 style={{ fontSize: "12px" }}
                 textAnchor="middle"
                 dominantBaseline="middle"
-                path={`M ${textX1} ${textY1} L ${textX2} ${textY2}`}
+                path={textPath}
             >
                 {relation.text}
             </HighlightedTextPath>
@@ -279,7 +334,7 @@ function RelationComponent(props: Readonly<{
                 style={{ fontSize: "12px" }}
                 textAnchor="start"
                 dominantBaseline="middle"
-                path={`M ${textX1} ${textY1} L ${textX2} ${textY2}`}
+                path={textPath}
             >
                 <MultiplicityIndicator multiplicity={mult1} />
             </HighlightedTextPath>
@@ -288,11 +343,11 @@ function RelationComponent(props: Readonly<{
                 style={{ fontSize: "12px" }}
                 textAnchor="end"
                 dominantBaseline="middle"
-                path={`M ${textX1} ${textY1} L ${textX2} ${textY2}`}
+                path={textPath}
             >
                 <tspan dx="-20"><MultiplicityIndicator multiplicity={mult2} /></tspan>
             </HighlightedTextPath>
-        </text>
+        </text>}
     </g>;
 }
 
@@ -305,10 +360,18 @@ function EntityComponent(props: Readonly<{
 }>): ReactElement {
     const { textContent: [textContent, setTextContent], hideUI: [hideUI] } = useContext(EditorContext)!;
     const placements = useContext(PlacementsContext)!;
+    const measuredSizes = useContext(MeasuredSizesContext);
     const [entity, index] = [props.entity, props.index];
     const ref = useRef<HTMLDivElement>(null);
     const dragRef = useRef<HTMLDivElement>(null);
 
+    useEffect(() => {
+        // Unscaled by the zoom, so the next layout keeps the boxes apart
+        if (ref.current != null) {
+            measuredSizes[entity.name] = { x: ref.current.offsetWidth, y: ref.current.offsetHeight };
+        }
+    });
+
     useEffect(() => {
         const handleDragOver = (e: DragEvent) => {
             if (dragRef.current == null) {
@@ -336,7 +399,14 @@ function EntityComponent(props: Readonly<{
         ref={ref}
         id={`entity-${index}`}
         className="entity"
-        style={{ top: props.top, left: props.left, zIndex: 1 }}
+        style={{
+            top: props.top,
+            left: props.left,
+            zIndex: 1,
+            borderColor: "#" + entity.color.toString(16).padStart(6, "0"),
+            borderStyle: entity.style === "regular" || entity.style === "bold" ? "solid" : entity.style,
+            borderWidth: entity.style === "bold" ? 4 : undefined
+        }}
     >
         {hideUI || <MaterialIcon
             icon="drag_handle"
@@ -370,7 +440,12 @@ function EntityComponent(props: Readonly<{
                 setTextContent(newTextContent);
             }}
         />}
-        {entity.name}
+        <div className="entity-name">{entity.name}</div>
+        {entity.attributes.length > 0 && <ul className="entity-attributes">
+            {entity.attributes.map(attribute => <li key={attribute.name}>
+                <AttributeIndicator attribute={attribute} />
+            </li>)}
+        </ul>}
         {hideUI || <button 
             onClick={() => {
                 if (pinInText(textContent, entity.name)) {
@@ -385,6 +460,24 @@ function EntityComponent(props: Readonly<{
     </div>
 }
 
+const VISIBILITY_MARKERS: Record<Visibility, string> = {
+    public: "+",
+    private: "-",
+    protected: "#",
+    package: "~"
+};
+
+function AttributeIndicator(props: Readonly<{ attribute: Attribute }>): ReactElement {
+    const attribute = props.attribute;
+
+    return <>
+        {attribute.visibility && VISIBILITY_MARKERS[attribute.visibility]}
+        {attribute.name}
+        {attribute.type && `: ${attribute.type}`}
+        {attribute.multiplicity.type !== "none" && <> [<MultiplicityIndicator multiplicity={attribute.multiplicity} />]</>}
+    </>;
+}
+
 function HighlightedTextPath(props: Readonly<React.SVGProps<SVGTextPathElement>>): ReactElement {
     return <>
         <textPath
@@ -400,6 +493,24 @@ function HighlightedTextPath(props: Readonly<React.SVGProps<SVGTextPathElement>>
     </>
 }
 
+/** Text centered on a label's anchor, with the same outline as the text along relations */
+function HighlightedText(props: Readonly<{ position: number[], children: React.ReactNode }>): ReactElement {
+    const [x, y] = props.position;
+    return <text
+        x={x}
+        y={y}
+        style={{ fontSize: "12px" }}
+        textAnchor="middle"
+        dominantBaseline="middle"
+        stroke="white"
+        strokeWidth={6}
+        strokeLinejoin="round"
+        paintOrder="stroke"
+    >
+        {props.children}
+    </text>
+}
+
 function truncate(number: number): string {
     return (Number.parseFloat((number * 2 / 10).toFixed(1)) * 10 / 2).toFixed(2);
 }
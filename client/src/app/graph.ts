@@ -1,19 +1,56 @@
 
 export type Style = 'regular' | 'dotted' | 'dashed' | 'bold';
 
-export type Arrow = 'none' | 'arrow';
+export type Arrow = 'none' | 'arrow' | 'inheritance' | 'aggregation' | 'composition';
+
+export type Visibility = 'public' | 'private' | 'protected' | 'package';
 
 export type EntityIndex = number;
 
+export type Layout = 'forceDirected' | 'hierarchical';
+
+export type SimConfig = {
+    iterations: number,
+    warmUp: number,
+    neighborSpringK: number,
+    expansionPush: number,
+    expansionSpringK: number,
+    orthogonalFactor: number,
+    deltaTime: number,
+    damping: number,
+    hierarchyGap: number,
+    hierarchySpringK: number
+}
+
+/** How the unrelated parts of a diagram are packed together, whatever the layout */
+export type PackingConfig = {
+    componentGap: number,
+    aspectRatio: number
+}
+
+export type PlacerOptions = {
+    layout?: Layout,
+    simConfig?: Partial<SimConfig>,
+    packing?: Partial<PackingConfig>,
+    /** Where each entity was placed last time, by name, so it stays there */
+    previous?: Record<string, Vec2>,
+    /** How big each entity's box is in pixels, by name, so they don't overlap */
+    sizes?: Record<string, Vec2>,
+    /** Scatters where entities start out; the same seed always gives the same layout */
+    seed?: number
+}
+
 export type Relation = {
     text: string | null,
     weight: number,
+    style: Style,
     entity1: EntityIndex,
     entity2: EntityIndex,
     arrow1: Arrow,
     arrow2: Arrow,
     mult1: Multiplicity,
-    mult2: Multiplicity
+    mult2: Multiplicity,
+    span: Span
 }
 
 export type Multiplicity = ({
@@ -36,15 +73,35 @@ export type Multiplicity = ({
 
 export type ColorHexValue = number;
 
+export type Attribute = {
+    name: string,
+    type: string | null,
+    visibility: Visibility | null,
+    multiplicity: Multiplicity,
+    span: Span
+}
+
 export type Entity = {
     name: string,
     color: ColorHexValue,
-    style: Style
+    style: Style,
+    attributes: Attribute[],
+    mentions: Span[]
+}
+
+/** A `config` statement's setting, as it was written */
+export type Setting = {
+    name: string,
+    value: number,
+    span: Span
 }
 
 export type Graph = {
     entities: Entity[],
     relations: Relation[],
+    /** The name of the layout the last `layout` statement picked, as it was written */
+    layout: { value: string, span: Span } | null,
+    config: Setting[],
     raw: string
 }
 
@@ -52,18 +109,57 @@ export function defaultGraph(): Graph {
     return {
         entities: [],
         relations: [],
+        layout: null,
+        config: [],
         raw: ""
     }
 }
 
+/** Where each entity was placed, by name, to pass back as `PlacerOptions.previous` */
+export function placementsByName(graph: Graph, placements: GridPlacements): Record<string, Vec2> {
+    const byName: Record<string, Vec2> = {};
+    for (const node of placements.nodes) {
+        byName[graph.entities[node.entity].name] = node.position;
+    }
+    return byName;
+}
+
 export function defaultPlacements(): GridPlacements {
     return {
-        nodes: []
+        nodes: [],
+        edges: []
     }
 }
 
 export type GridPlacements = {
-    nodes: GridNode[]
+    nodes: GridNode[],
+    edges: GridEdge[]
+}
+
+export type GridEdge = {
+    relation: number,
+    /** The corners of the line, from the first entity's box to the second's */
+    route: Vec2[],
+    labels: EdgeLabels
+}
+
+/** Where the centers of a relation's labels go, if it has them */
+export type EdgeLabels = {
+    text?: Vec2 | null,
+    mult1?: Vec2 | null,
+    mult2?: Vec2 | null
+}
+
+/** How readable a layout is, from `layout_metrics`. Lengths and areas are in grid units */
+export type LayoutMetrics = {
+    edgeCrossings: number,
+    totalEdgeLength: number,
+    maxEdgeLength: number,
+    nodeOverlaps: number,
+    boundingBoxArea: number,
+    aspectRatio: number,
+    /** The smallest angle between two relations of the same entity, in radians */
+    angularResolution: number | null
 }
 
 export type GridNode = {
@@ -71,7 +167,23 @@ export type GridNode = {
     position: Vec2,
 }
 
+export type Position = {
+    offset: number,
+    line: number,
+    column: number
+}
+
+export type Span = {
+    start: Position,
+    end: Position
+}
+
 export type Vec2 = {
     x: number,
     y: number
-}
\ No newline at end of file
+}
+
+export type Diagnostic = {
+    error: unknown,
+    span: Span
+}
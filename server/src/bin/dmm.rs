@@ -0,0 +1,273 @@
+//! Renders diagram source files from the command line, without a browser.
+//!
+//! ```text
+//! dmm [--format json|ascii|svg|dot|mermaid|plantuml|dmm] [--out-dir <directory>] [--seed <number>] [<file>...]
+//! ```
+//!
+//! With no files, the diagram is read from stdin. Without an output directory,
+//! everything is written to stdout; with one, each file gets its own output
+//! file named after it (e.g. `orders.dmm` becomes `orders.svg`). The same files and
+//! seed always give exactly the same output.
+
+use std::{collections::HashMap, fs, io::{self, Read}, path::{Path, PathBuf}, process::ExitCode, str::FromStr};
+
+use server::{domain_model::{export::{dot, mermaid, plantuml}, graph::Graph, parser::{formatter, parse_graph_recovering, Diagnostic}}, image_generation::{placers::{self, GridPlacements, PlacerOptions}, render::svg}};
+
+const USAGE: &str = "Usage: dmm [--format json|ascii|svg|dot|mermaid|plantuml|dmm] [--out-dir <directory>] [--seed <number>] [<file>...]";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Format {
+    /// The graph and its placements, the same way the web app gets them
+    Json,
+    /// The grid the entities were placed on, with each entity shown by its index
+    Ascii,
+    Svg,
+    /// Graphviz DOT, with pinned entities kept in place
+    Dot,
+    /// A Mermaid class diagram
+    Mermaid,
+    /// A PlantUML class diagram
+    PlantUml,
+    /// The source itself, formatted the canonical way
+    Dmm
+}
+
+impl Format {
+    fn extension(&self) -> &'static str {
+        match self {
+            Format::Json => "json",
+            Format::Ascii => "txt",
+            Format::Svg => "svg",
+            Format::Dot => "dot",
+            Format::Mermaid => "mmd",
+            Format::PlantUml => "puml",
+            Format::Dmm => "dmm"
+        }
+    }
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(Format::Json),
+            "ascii" => Ok(Format::Ascii),
+            "svg" => Ok(Format::Svg),
+            "dot" => Ok(Format::Dot),
+            "mermaid" => Ok(Format::Mermaid),
+            "plantuml" => Ok(Format::PlantUml),
+            "dmm" => Ok(Format::Dmm),
+            _ => Err(format!("Unknown format \"{}\"", s))
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+struct Options {
+    format: Format,
+    out_dir: Option<PathBuf>,
+    /// Scatters where the entities start out, for a different (but just as reproducible) layout
+    seed: Option<u64>,
+    files: Vec<PathBuf>
+}
+
+fn parse_args(args: impl IntoIterator<Item=String>) -> Result<Options, String> {
+    let mut options = Options { format: Format::Svg, out_dir: None, seed: None, files: Vec::new() };
+
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-f" | "--format" => {
+                let Some(format) = args.next() else {
+                    return Err(format!("{} needs a format", arg));
+                };
+                options.format = format.parse()?;
+            },
+            "-o" | "--out-dir" => {
+                let Some(out_dir) = args.next() else {
+                    return Err(format!("{} needs a directory", arg));
+                };
+                options.out_dir = Some(PathBuf::from(out_dir));
+            },
+            "-s" | "--seed" => {
+                let Some(seed) = args.next() else {
+                    return Err(format!("{} needs a number", arg));
+                };
+                options.seed = Some(seed.parse().map_err(|_| format!("\"{}\" isn't a seed", seed))?);
+            },
+            _ if arg.starts_with('-') && arg != "-" => return Err(format!("Unknown option \"{}\"", arg)),
+            _ => options.files.push(PathBuf::from(arg))
+        }
+    }
+
+    Ok(options)
+}
+
+/// Parses the diagram (laying it out if the format needs it), then writes it in the given format
+fn render(input: &str, format: Format, seed: Option<u64>) -> Result<String, Vec<Diagnostic>> {
+    let (graph, mut diagnostics) = parse_graph_recovering(input);
+    diagnostics.extend(placers::check_directives(&graph));
+    diagnostics.sort_by_key(|diagnostic| diagnostic.span.start.offset);
+    if !diagnostics.is_empty() {
+        return Err(diagnostics);
+    }
+
+    Ok(match format {
+        Format::Json => serde_json::to_string_pretty(&(&graph, place(&graph, seed))).unwrap() + "\n",
+        Format::Ascii => place(&graph, seed).to_string(),
+        Format::Svg => svg::render(&graph, &place(&graph, seed)),
+        Format::Dot => dot::export(&graph, &dot::DotOptions { pins: true }),
+        Format::Mermaid => mermaid::export(&graph),
+        Format::PlantUml => plantuml::export(&graph),
+        Format::Dmm => return formatter::format(input)
+    })
+}
+
+/// Places the graph with the layout its source picked
+fn place(graph: &Graph, seed: Option<u64>) -> GridPlacements {
+    placers::place(graph, &PlacerOptions { seed, ..PlacerOptions::default() })
+}
+
+fn read_input(path: &Path) -> io::Result<String> {
+    if path == Path::new("-") {
+        let mut input = String::new();
+        io::stdin().read_to_string(&mut input)?;
+        Ok(input)
+    } else {
+        fs::read_to_string(path)
+    }
+}
+
+/// Where the output of the file goes, if there's an output directory
+fn output_path(path: &Path, options: &Options) -> Option<PathBuf> {
+    let name = if path == Path::new("-") {
+        Path::new("stdin")
+    } else {
+        Path::new(path.file_stem().unwrap_or(path.as_os_str()))
+    };
+    Some(options.out_dir.as_ref()?.join(name).with_extension(options.format.extension()))
+}
+
+/// The files whose output would overwrite the output of an earlier file, along with that file
+fn collisions(options: &Options) -> Vec<(&Path, &Path)> {
+    let mut written: HashMap<PathBuf, &Path> = HashMap::new();
+    let mut collisions = Vec::new();
+    for path in options.files.iter() {
+        let Some(out_path) = output_path(path, options) else { continue };
+        match written.get(&out_path) {
+            Some(earlier) => collisions.push((path.as_path(), *earlier)),
+            None => { written.insert(out_path, path); }
+        }
+    }
+    collisions
+}
+
+/// Renders one file, printing any errors. Returns whether it succeeded.
+fn run_file(path: &Path, options: &Options) -> bool {
+    let is_stdin = path == Path::new("-");
+    let display_name = if is_stdin { "<stdin>".into() } else { path.display().to_string() };
+
+    let input = match read_input(path) {
+        Ok(input) => input,
+        Err(error) => {
+            eprintln!("{}: {}", display_name, error);
+            return false;
+        }
+    };
+
+    let output = match render(&input, options.format, options.seed) {
+        Ok(output) => output,
+        Err(diagnostics) => {
+            for diagnostic in diagnostics {
+                eprintln!("{}: {}", display_name, diagnostic);
+            }
+            return false;
+        }
+    };
+
+    let (Some(out_dir), Some(out_path)) = (&options.out_dir, output_path(path, options)) else {
+        print!("{}", output);
+        return true;
+    };
+
+    if let Err(error) = fs::create_dir_all(out_dir).and_then(|_| fs::write(&out_path, output)) {
+        eprintln!("{}: {}", out_path.display(), error);
+        return false;
+    }
+    true
+}
+
+fn main() -> ExitCode {
+    let mut options = match parse_args(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(error) => {
+            eprintln!("{}\n{}", error, USAGE);
+            return ExitCode::from(2);
+        }
+    };
+
+    if options.files.is_empty() {
+        options.files.push(PathBuf::from("-"));
+    }
+
+    // Files named the same in different directories would overwrite each other's output
+    let collisions = collisions(&options);
+    for (path, earlier) in collisions.iter() {
+        eprintln!("{}: its output would overwrite the output of {}, so it was skipped", path.display(), earlier.display());
+    }
+
+    // Keep going after a failure, so every broken file gets reported at once
+    let mut succeeded = collisions.is_empty();
+    for path in options.files.iter() {
+        if collisions.iter().any(|(colliding, _)| colliding == path) { continue }
+        succeeded &= run_file(path, &options);
+    }
+
+    if succeeded { ExitCode::SUCCESS } else { ExitCode::FAILURE }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn arguments() {
+        assert_eq!(
+            parse_args(args(&["--format", "ascii", "a.dmm", "-o", "out", "b.dmm"])),
+            Ok(Options { format: Format::Ascii, out_dir: Some(PathBuf::from("out")), seed: None, files: vec![PathBuf::from("a.dmm"), PathBuf::from("b.dmm")] })
+        );
+        assert_eq!(
+            parse_args(args(&["-"])),
+            Ok(Options { format: Format::Svg, out_dir: None, seed: None, files: vec![PathBuf::from("-")] })
+        );
+        assert!(parse_args(args(&["--format", "png"])).is_err());
+        assert!(parse_args(args(&["--format"])).is_err());
+        assert!(parse_args(args(&["--verbose"])).is_err());
+        assert_eq!(parse_args(args(&["--seed", "42"])).map(|options| options.seed), Ok(Some(42)));
+        assert!(parse_args(args(&["--seed", "-1"])).is_err());
+    }
+
+    #[test]
+    fn outputs_cant_overwrite_each_other() {
+        let Ok(options) = parse_args(args(&["-o", "out", "a/orders.dmm", "b/orders.dmm", "customers.dmm"])) else { panic!("Parsing failed") };
+        assert_eq!(collisions(&options), vec![(Path::new("b/orders.dmm"), Path::new("a/orders.dmm"))]);
+
+        let Ok(options) = parse_args(args(&["a/orders.dmm", "b/orders.dmm"])) else { panic!("Parsing failed") };
+        assert!(collisions(&options).is_empty(), "Everything goes to stdout without an output directory");
+    }
+
+    #[test]
+    fn failures_report_every_diagnostic() {
+        let Err(diagnostics) = render("a-b\na-<b\nc\n", Format::Svg, None) else {
+            panic!("Expected the input to fail");
+        };
+        assert_eq!(diagnostics.iter().map(|diagnostic| diagnostic.line()).collect::<Vec<_>>(), vec![2, 3]);
+
+        assert!(render("a-b", Format::Json, None).is_ok_and(|output| output.contains("\"entities\"")));
+    }
+}
\ No newline at end of file
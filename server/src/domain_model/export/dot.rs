@@ -0,0 +1,154 @@
+use std::fmt::Write;
+
+use crate::{domain_model::{export::cardinality, graph::{Arrow, DEFAULT_COLOR, Entity, Graph, Relation, Style}}, image_generation::render::GRID_SIZE};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct DotOptions {
+    /// Whether pinned entities get a fixed `pos`. Only the neato and fdp layouts respect it.
+    pub pins: bool
+}
+
+/// Writes the graph as a Graphviz DOT digraph
+pub fn export(graph: &Graph, options: &DotOptions) -> String {
+    let mut dot = String::new();
+
+    // Writing to a String never fails, so the results are safe to ignore
+    dot.push_str("digraph {\n");
+    dot.push_str("    node [shape=box];\n");
+
+    for (index, entity) in graph.entities.iter().enumerate() {
+        let mut attributes = vec![format!("label={}", entity_label(entity))];
+
+        if entity.color != DEFAULT_COLOR {
+            attributes.push(format!("color=\"#{:06x}\"", entity.color));
+        }
+        if let Some(style) = style_name(entity.style) {
+            attributes.push(format!("style={}", style));
+        }
+        if options.pins && let Some(pin) = graph.pins.get(&index) {
+            // DOT's y axis points up, but ours points down
+            attributes.push(format!("pos=\"{},{}!\"", pin.x * GRID_SIZE, -pin.y * GRID_SIZE));
+        }
+
+        let _ = writeln!(dot, "    {} [{}];", quote(&entity.name), attributes.join(", "));
+    }
+
+    for relation in graph.relations.iter() {
+        let _ = writeln!(
+            dot,
+            "    {} -> {} [{}];",
+            quote(&graph.entities[relation.entity_1].name),
+            quote(&graph.entities[relation.entity_2].name),
+            relation_attributes(relation).join(", ")
+        );
+    }
+
+    dot.push_str("}\n");
+    dot
+}
+
+/// The entity's name, followed by its attributes (left-aligned, one per line)
+fn entity_label(entity: &Entity) -> String {
+    let mut label = format!("\"{}", escape(&entity.name));
+    if !entity.attributes.is_empty() {
+        label.push_str("\\n");
+        for attribute in entity.attributes.iter() {
+            label.push_str(&escape(&attribute.to_string()));
+            label.push_str("\\l");
+        }
+    }
+    label.push('"');
+    label
+}
+
+fn relation_attributes(relation: &Relation) -> Vec<String> {
+    // Both ends are set explicitly, so the direction of the edge doesn't change how it looks
+    let mut attributes = vec![
+        "dir=both".to_string(),
+        format!("arrowtail={}", arrow_name(relation.arrow_1)),
+        format!("arrowhead={}", arrow_name(relation.arrow_2))
+    ];
+
+    if let Some(text) = &relation.text {
+        attributes.push(format!("label={}", quote(text)));
+    }
+    if let Some(tail) = cardinality(&relation.mult_1) {
+        attributes.push(format!("taillabel={}", quote(&tail)));
+    }
+    if let Some(head) = cardinality(&relation.mult_2) {
+        attributes.push(format!("headlabel={}", quote(&head)));
+    }
+    if let Some(style) = style_name(relation.style) {
+        attributes.push(format!("style={}", style));
+    }
+
+    attributes
+}
+
+fn arrow_name(arrow: Arrow) -> &'static str {
+    match arrow {
+        Arrow::None => "none",
+        Arrow::Arrow => "vee",
+        Arrow::Inheritance => "onormal",
+        Arrow::Aggregation => "odiamond",
+        Arrow::Composition => "diamond"
+    }
+}
+
+/// The DOT style for lines drawn in the given style, if it isn't the default
+fn style_name(style: Style) -> Option<&'static str> {
+    match style {
+        Style::Regular => None,
+        Style::Dotted => Some("dotted"),
+        Style::Dashed => Some("dashed"),
+        Style::Bold => Some("bold")
+    }
+}
+
+fn quote(text: &str) -> String {
+    format!("\"{}\"", escape(text))
+}
+
+/// Escapes the characters that would end a DOT string or start an escape sequence
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain_model::parser::parse_graph;
+
+    use super::*;
+
+    #[test]
+    fn entities_and_relations() {
+        let input = "entity Customer: #3366ff dashed\nCustomer-places-1..>Order\nOrder<#>..Line\nOrder { +id: Uuid, lines: Line [1..] }";
+        let Ok(graph) = parse_graph(input) else { panic!("Parsing failed") };
+
+        let dot = export(&graph, &DotOptions::default());
+
+        assert!(dot.starts_with("digraph {\n"));
+        assert!(dot.contains("    \"Customer\" [label=\"Customer\", color=\"#3366ff\", style=dashed];\n"));
+        assert!(dot.contains("    \"Order\" [label=\"Order\\n+id: Uuid\\llines: Line [1..]\\l\"];\n"));
+        assert!(dot.contains("    \"Customer\" -> \"Order\" [dir=both, arrowtail=none, arrowhead=vee, label=\"places\", headlabel=\"1..*\"];\n"));
+        assert!(dot.contains("    \"Order\" -> \"Line\" [dir=both, arrowtail=diamond, arrowhead=none, style=dashed];\n"));
+        assert!(dot.ends_with("}\n"));
+    }
+
+    #[test]
+    fn pins_are_optional() {
+        let Ok(graph) = parse_graph("a-b\npin a: 2 ~1") else { panic!("Parsing failed") };
+
+        assert!(!export(&graph, &DotOptions::default()).contains("pos="));
+        assert!(export(&graph, &DotOptions { pins: true }).contains("    \"a\" [label=\"a\", pos=\"100,50!\"];\n"));
+    }
+
+    #[test]
+    fn names_are_escaped() {
+        let Ok(graph) = parse_graph("'say \"hi\"'-\\\\b") else { panic!("Parsing failed") };
+
+        let dot = export(&graph, &DotOptions::default());
+
+        assert!(dot.contains("\"say \\\"hi\\\"\" -> \"\\\\b\""));
+    }
+}
\ No newline at end of file
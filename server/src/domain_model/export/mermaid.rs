@@ -0,0 +1,194 @@
+use std::{collections::HashSet, fmt::Write};
+
+use crate::domain_model::{export::cardinality, graph::{Arrow, Attribute, DEFAULT_COLOR, Entity, Graph, Relation, Style}};
+
+/// Writes the graph as a Mermaid class diagram
+pub fn export(graph: &Graph) -> String {
+    let ids = class_ids(&graph.entities);
+
+    let mut mermaid = String::new();
+
+    // Writing to a String never fails, so the results are safe to ignore
+    mermaid.push_str("classDiagram\n");
+
+    for (entity, id) in graph.entities.iter().zip(ids.iter()) {
+        if *id == entity.name {
+            let _ = writeln!(mermaid, "    class {}", id);
+        } else {
+            let _ = writeln!(mermaid, "    class {}[\"{}\"]", id, escape(&entity.name));
+        }
+        for attribute in entity.attributes.iter() {
+            let _ = writeln!(mermaid, "    {} : {}", id, member(attribute));
+        }
+    }
+
+    for relation in graph.relations.iter() {
+        let _ = writeln!(mermaid, "    {}", relation_line(relation, &ids));
+    }
+
+    for (entity, id) in graph.entities.iter().zip(ids.iter()) {
+        if let Some(style) = entity_style(entity) {
+            let _ = writeln!(mermaid, "    style {} {}", id, style);
+        }
+    }
+
+    mermaid
+}
+
+/// Mermaid only allows letters, digits and underscores in class names, so other names
+/// are turned into an id and shown with a label instead
+fn class_ids(entities: &[Entity]) -> Vec<String> {
+    let mut taken = HashSet::new();
+
+    entities.iter().enumerate().map(|(index, entity)| {
+        let mut id: String = entity.name.chars()
+            .map(|c| if c.is_ascii_alphanumeric() || c == '_' { c } else { '_' })
+            .collect();
+        if id.is_empty() || id.starts_with(|c: char| c.is_ascii_digit()) {
+            id.insert(0, '_');
+        }
+        // The suffixed id can be taken as well, e.g. by an entity actually named that
+        let mut suffix = index;
+        let mut unique = id.clone();
+        while taken.contains(&unique) {
+            unique = format!("{}_{}", id, suffix);
+            suffix += 1;
+        }
+        let id = unique;
+        taken.insert(id.clone());
+        id
+    }).collect()
+}
+
+fn relation_line(relation: &Relation, ids: &[String]) -> String {
+    let left = match relation.arrow_1 {
+        Arrow::None => "",
+        Arrow::Arrow => "<",
+        Arrow::Inheritance => "<|",
+        Arrow::Aggregation => "o",
+        Arrow::Composition => "*"
+    };
+    let right = match relation.arrow_2 {
+        Arrow::None => "",
+        Arrow::Arrow => ">",
+        Arrow::Inheritance => "|>",
+        Arrow::Aggregation => "o",
+        Arrow::Composition => "*"
+    };
+    // Mermaid only has solid and dashed lines
+    let line = match relation.style {
+        Style::Regular | Style::Bold => "--",
+        Style::Dotted | Style::Dashed => ".."
+    };
+
+    let mut text = ids[relation.entity_1].clone();
+    if let Some(cardinality) = cardinality(&relation.mult_1) {
+        let _ = write!(text, " \"{}\"", cardinality);
+    }
+    let _ = write!(text, " {}{}{} ", left, line, right);
+    if let Some(cardinality) = cardinality(&relation.mult_2) {
+        let _ = write!(text, "\"{}\" ", cardinality);
+    }
+    text.push_str(&ids[relation.entity_2]);
+    if let Some(label) = &relation.text {
+        let _ = write!(text, " : {}", escape(label));
+    }
+    text
+}
+
+fn member(attribute: &Attribute) -> String {
+    let mut member = attribute.visibility.map(|visibility| visibility.to_string()).unwrap_or_default();
+    member.push_str(&escape(&attribute.name));
+    if let Some(ty) = &attribute.ty {
+        let _ = write!(member, " : {}", escape(ty));
+    }
+    if let Some(cardinality) = cardinality(&attribute.multiplicity) {
+        let _ = write!(member, " [{}]", cardinality);
+    }
+    member
+}
+
+fn entity_style(entity: &Entity) -> Option<String> {
+    let mut style = Vec::new();
+    if entity.color != DEFAULT_COLOR {
+        style.push(format!("stroke:#{:06x}", entity.color));
+    }
+    match entity.style {
+        Style::Regular => {},
+        Style::Dotted => style.push("stroke-dasharray:2 4".to_string()),
+        Style::Dashed => style.push("stroke-dasharray:8 6".to_string()),
+        Style::Bold => style.push("stroke-width:4px".to_string())
+    }
+    (!style.is_empty()).then(|| style.join(","))
+}
+
+/// Mermaid writes special characters as entity codes, like "#quot;"
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '"' => escaped.push_str("#quot;"),
+            '#' => escaped.push_str("#35;"),
+            '<' => escaped.push_str("#lt;"),
+            '>' => escaped.push_str("#gt;"),
+            c => escaped.push(c)
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain_model::parser::parse_graph;
+
+    use super::*;
+
+    #[test]
+    fn relations() {
+        let input = "Customer-places-1..>Order\nOrder<#>-1..5 Line Item\nManager-|>Employee\nController..>Service\nTeam<>-0..Player";
+        let Ok(graph) = parse_graph(input) else { panic!("Parsing failed") };
+
+        let mermaid = export(&graph);
+
+        assert!(mermaid.starts_with("classDiagram\n"));
+        assert!(mermaid.contains("    Customer --> \"1..*\" Order : places\n"));
+        assert!(mermaid.contains("    Order *-- \"1..5\" Line_Item\n"));
+        assert!(mermaid.contains("    Manager --|> Employee\n"));
+        assert!(mermaid.contains("    Controller ..> Service\n"));
+        assert!(mermaid.contains("    Team o-- \"0..*\" Player\n"));
+    }
+
+    #[test]
+    fn names_that_need_escaping() {
+        let input = "Line Item-\"Line-Item\"\n\"2FA\"-'Say \"hi\"'";
+        let Ok(graph) = parse_graph(input) else { panic!("Parsing failed") };
+
+        let mermaid = export(&graph);
+
+        assert!(mermaid.contains("    class Line_Item[\"Line Item\"]\n"));
+        assert!(mermaid.contains("    class Line_Item_1[\"Line-Item\"]\n"));
+        assert!(mermaid.contains("    class _2FA[\"2FA\"]\n"));
+        assert!(mermaid.contains("    class Say__hi_[\"Say #quot;hi#quot;\"]\n"));
+        assert!(mermaid.contains("    Line_Item -- Line_Item_1\n"));
+    }
+
+    #[test]
+    fn ids_never_collide() {
+        let Ok(graph) = parse_graph("\"A B\"-A_B\n\"A_B_1\"-\"A_B_2\"") else { panic!("Parsing failed") };
+
+        let ids = class_ids(&graph.entities);
+
+        assert_eq!(ids, ["A_B", "A_B_1", "A_B_1_2", "A_B_2"]);
+    }
+
+    #[test]
+    fn attributes_and_styles() {
+        let input = "Order { +id: Uuid, lines: Line [1..] }\nentity Order: #3366ff dashed";
+        let Ok(graph) = parse_graph(input) else { panic!("Parsing failed") };
+
+        let mermaid = export(&graph);
+
+        assert!(mermaid.contains("    class Order\n    Order : +id : Uuid\n    Order : lines : Line [1..*]\n"));
+        assert!(mermaid.contains("    style Order stroke:#3366ff,stroke-dasharray:8 6\n"));
+    }
+}
\ No newline at end of file
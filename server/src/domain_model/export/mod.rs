@@ -0,0 +1,17 @@
+//! Writes graphs in the formats of other diagramming tools
+
+use crate::domain_model::graph::Multiplicity;
+
+pub mod dot;
+pub mod mermaid;
+pub mod plantuml;
+
+/// The multiplicity the way UML writes it, e.g. "1..*"
+fn cardinality(multiplicity: &Multiplicity) -> Option<String> {
+    match multiplicity {
+        Multiplicity::None => None,
+        Multiplicity::Range(range) => Some(format!("{}..{}", range.start, range.end)),
+        Multiplicity::Number(n) => Some(n.to_string()),
+        Multiplicity::RangeFrom(range) => Some(format!("{}..*", range.start))
+    }
+}
\ No newline at end of file
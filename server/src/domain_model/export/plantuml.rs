@@ -0,0 +1,135 @@
+use std::fmt::Write;
+
+use crate::domain_model::{export::cardinality, graph::{Arrow, Attribute, DEFAULT_COLOR, Entity, Graph, Relation, Style}};
+
+/// Writes the graph as a PlantUML class diagram
+pub fn export(graph: &Graph) -> String {
+    let mut plantuml = String::new();
+
+    // Writing to a String never fails, so the results are safe to ignore
+    plantuml.push_str("@startuml\n");
+
+    for entity in graph.entities.iter() {
+        let _ = write!(plantuml, "class {}{}", quote(&entity.name), entity_style(entity));
+        if entity.attributes.is_empty() {
+            plantuml.push('\n');
+        } else {
+            plantuml.push_str(" {\n");
+            for attribute in entity.attributes.iter() {
+                let _ = writeln!(plantuml, "  {}", member(attribute));
+            }
+            plantuml.push_str("}\n");
+        }
+    }
+
+    for relation in graph.relations.iter() {
+        let _ = writeln!(plantuml, "{}", relation_line(graph, relation));
+    }
+
+    plantuml.push_str("@enduml\n");
+    plantuml
+}
+
+fn relation_line(graph: &Graph, relation: &Relation) -> String {
+    let left = match relation.arrow_1 {
+        Arrow::None => "",
+        Arrow::Arrow => "<",
+        Arrow::Inheritance => "<|",
+        Arrow::Aggregation => "o",
+        Arrow::Composition => "*"
+    };
+    let right = match relation.arrow_2 {
+        Arrow::None => "",
+        Arrow::Arrow => ">",
+        Arrow::Inheritance => "|>",
+        Arrow::Aggregation => "o",
+        Arrow::Composition => "*"
+    };
+    let line = match relation.style {
+        Style::Regular => "--",
+        Style::Dashed => "..",
+        Style::Dotted => "-[dotted]-",
+        Style::Bold => "-[bold]-"
+    };
+
+    let mut text = quote(&graph.entities[relation.entity_1].name);
+    if let Some(cardinality) = cardinality(&relation.mult_1) {
+        let _ = write!(text, " \"{}\"", cardinality);
+    }
+    let _ = write!(text, " {}{}{} ", left, line, right);
+    if let Some(cardinality) = cardinality(&relation.mult_2) {
+        let _ = write!(text, "\"{}\" ", cardinality);
+    }
+    text.push_str(&quote(&graph.entities[relation.entity_2].name));
+    if let Some(label) = &relation.text {
+        let _ = write!(text, " : {}", label);
+    }
+    text
+}
+
+fn member(attribute: &Attribute) -> String {
+    let mut member = attribute.visibility.map(|visibility| visibility.to_string()).unwrap_or_default();
+    member.push_str(&attribute.name);
+    if let Some(ty) = &attribute.ty {
+        let _ = write!(member, " : {}", ty);
+    }
+    if let Some(cardinality) = cardinality(&attribute.multiplicity) {
+        let _ = write!(member, " [{}]", cardinality);
+    }
+    member
+}
+
+/// The inline style of a class, like " #line:3366ff;line.dashed"
+fn entity_style(entity: &Entity) -> String {
+    let mut style = Vec::new();
+    if entity.color != DEFAULT_COLOR {
+        style.push(format!("line:{:06x}", entity.color));
+    }
+    match entity.style {
+        Style::Regular => {},
+        Style::Dotted => style.push("line.dotted".to_string()),
+        Style::Dashed => style.push("line.dashed".to_string()),
+        Style::Bold => style.push("line.bold".to_string())
+    }
+    if style.is_empty() {
+        String::new()
+    } else {
+        format!(" #{}", style.join(";"))
+    }
+}
+
+/// PlantUML names can only have letters, digits and underscores unless they're quoted.
+/// Quoted names can't contain double quotes, so those become single quotes.
+fn quote(name: &str) -> String {
+    if !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        name.to_string()
+    } else {
+        format!("\"{}\"", name.replace('"', "'"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain_model::parser::parse_graph;
+
+    use super::*;
+
+    #[test]
+    fn classes_and_relations() {
+        let input = "Customer-places-1..>Order\nOrder<#>-1..5 Line Item\nManager-|>Employee\nController..>Service\nTeam<>=0..Player\nOrder { +id: Uuid, lines: Line [1..] }\nentity Order: #3366ff dashed";
+        let Ok(graph) = parse_graph(input) else { panic!("Parsing failed") };
+
+        let plantuml = export(&graph);
+
+        assert!(plantuml.starts_with("@startuml\n"));
+        assert!(plantuml.contains("\nclass Customer\n"));
+        assert!(plantuml.contains("\nclass \"Line Item\"\n"));
+        assert!(plantuml.contains("\nclass Order #line:3366ff;line.dashed {\n  +id : Uuid\n  lines : Line [1..*]\n}\n"));
+        assert!(plantuml.contains("\nCustomer --> \"1..*\" Order : places\n"));
+        assert!(plantuml.contains("\nOrder *-- \"1..5\" \"Line Item\"\n"));
+        assert!(plantuml.contains("\nManager --|> Employee\n"));
+        assert!(plantuml.contains("\nController ..> Service\n"));
+        assert!(plantuml.contains("\nTeam o-[bold]- \"0..*\" Player\n"));
+        assert!(plantuml.ends_with("@enduml\n"));
+    }
+}
\ No newline at end of file
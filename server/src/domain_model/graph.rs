@@ -1,10 +1,10 @@
-use std::{collections::HashMap, num::NonZeroUsize, ops::{Range, RangeFrom}};
+use std::{collections::BTreeMap, fmt, num::NonZeroUsize, ops::{Range, RangeFrom}};
 
 use serde::{Deserialize, Serialize};
 
-use crate::image_generation::placers::Vec2;
+use crate::{domain_model::span::{Span, Spanned}, image_generation::placers::Vec2};
 
-#[derive(Serialize, Deserialize)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub enum Style {
     Regular,
@@ -13,29 +13,46 @@ pub enum Style {
     Bold
 }
 
-#[derive(Clone, Copy, Serialize, Deserialize)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Visibility {
+    Public,
+    Private,
+    Protected,
+    Package
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub enum Arrow {
     None,
-    Arrow
+    Arrow,
+    /// A hollow triangle, pointing at the supertype
+    Inheritance,
+    /// A hollow diamond, on the side of the whole
+    Aggregation,
+    /// A filled diamond, on the side of the owner
+    Composition
 }
 
 pub type EntityIndex = usize;
 
-#[derive(Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Relation {
     pub text: Option<String>,
     pub weight: NonZeroUsize,
+    pub style: Style,
     pub entity_1: EntityIndex,
     pub entity_2: EntityIndex,
     pub arrow_1: Arrow,
     pub arrow_2: Arrow,
     pub mult_1: Multiplicity,
-    pub mult_2: Multiplicity
+    pub mult_2: Multiplicity,
+    pub span: Span // the statement that declared the relation
 }
 
-#[derive(Clone, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase", tag = "type", content = "value")]
 pub enum Multiplicity {
     None,
@@ -47,20 +64,87 @@ pub enum Multiplicity {
     RangeFrom(RangeFrom<usize>)
 }
 
-type ColorHexValue = u32;
+impl fmt::Display for Multiplicity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Multiplicity::None => Ok(()),
+            Multiplicity::Range(range) => write!(f, "{}..{}", range.start, range.end),
+            Multiplicity::Number(n) => write!(f, "{}", n),
+            Multiplicity::RangeFrom(range) => write!(f, "{}..", range.start)
+        }
+    }
+}
+
+impl fmt::Display for Visibility {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Visibility::Public => write!(f, "+"),
+            Visibility::Private => write!(f, "-"),
+            Visibility::Protected => write!(f, "#"),
+            Visibility::Package => write!(f, "~")
+        }
+    }
+}
+
+pub type ColorHexValue = u32;
+
+/// The color of entities that weren't given one
+pub const DEFAULT_COLOR: ColorHexValue = 0xff0000;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Attribute {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub ty: Option<String>,
+    pub visibility: Option<Visibility>,
+    pub multiplicity: Multiplicity,
+    pub span: Span
+}
+
+impl fmt::Display for Attribute {
+    /// Writes the attribute the way it's shown in a diagram, e.g. "-lines: Line [1..]"
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(visibility) = self.visibility {
+            write!(f, "{}", visibility)?;
+        }
+        write!(f, "{}", self.name)?;
+        if let Some(ty) = &self.ty {
+            write!(f, ": {}", ty)?;
+        }
+        if self.multiplicity != Multiplicity::None {
+            write!(f, " [{}]", self.multiplicity)?;
+        }
+        Ok(())
+    }
+}
 
-#[derive(Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Entity {
     pub name: String,
     pub color: ColorHexValue,
-    pub style: Style
+    pub style: Style,
+    pub attributes: Vec<Attribute>,
+    pub mentions: Vec<Span> // every place the entity is named in the input
+}
+
+/// A `config` statement's setting, as it was written. Placing reads what it means.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Setting {
+    pub name: String,
+    pub value: f32,
+    pub span: Span
 }
 
 #[derive(Default, Serialize, Deserialize)]
 pub struct Graph {
     pub entities: Vec<Entity>,
     pub relations: Vec<Relation>,
-    pub pins: HashMap<EntityIndex, Vec2>,
+    pub pins: BTreeMap<EntityIndex, Vec2>,
+    /// The name of the layout picked with the last `layout` statement, as it was written
+    pub layout: Option<Spanned<String>>,
+    /// The settings of the `config` statements, in the order they were written
+    pub config: Vec<Setting>,
     pub raw: String, // the raw input that makes up the graph
 }
 
@@ -78,7 +162,9 @@ pub mod test {
         Entity {
             name: "".to_string(),
             color: 0xff00ffff,
-            style: Style::Regular
+            style: Style::Regular,
+            attributes: Vec::new(),
+            mentions: Vec::new()
         }
     }
 
@@ -86,12 +172,14 @@ pub mod test {
         Relation {
             text: None,
             weight: NonZeroUsize::new(1).unwrap(),
+            style: Style::Regular,
             entity_1,
             entity_2,
             arrow_1: Arrow::None,
             arrow_2: Arrow::Arrow,
             mult_1: Multiplicity::Number(1),
-            mult_2: Multiplicity::RangeFrom(1..)
+            mult_2: Multiplicity::RangeFrom(1..),
+            span: Span::default()
         }
     }
 }
\ No newline at end of file
@@ -0,0 +1,3 @@
+//! Reads graphs written for other diagramming tools
+
+pub mod plantuml;
\ No newline at end of file
@@ -0,0 +1,480 @@
+use std::{collections::HashMap, fmt, num::NonZeroUsize, ops::{Range, RangeFrom}};
+
+use serde::{Deserialize, Serialize};
+
+use crate::domain_model::{graph::{Arrow, Attribute, Graph, Multiplicity, Style, Visibility}, parser::{formatter::write_statement, parse_graph_recovering, statementizer::Statement, Diagnostic}, span::{Position, Span, Spanned}};
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub enum ImportError {
+    /// The line isn't part of the subset of PlantUML that can be imported, so it was skipped
+    UnsupportedLine(String),
+    /// The multiplicity can't be written in this language (e.g. "0..n"), so the relation was imported without it
+    UnsupportedMultiplicity(String),
+    /// A class body was opened with "{" but never closed with "}"
+    UnclosedClassBody(String),
+    /// The line was translated, but the translation doesn't parse (e.g. a field declared twice)
+    InvalidTranslation(String)
+}
+
+impl fmt::Display for ImportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImportError::UnsupportedLine(line) => write!(f, "Skipped \"{}\", since it isn't supported", line),
+            ImportError::UnsupportedMultiplicity(multiplicity) => write!(f, "The multiplicity \"{}\" isn't supported, so it was left out", multiplicity),
+            ImportError::UnclosedClassBody(class) => write!(f, "The body of \"{}\" was never closed", class),
+            ImportError::InvalidTranslation(error) => write!(f, "The translation of this line is invalid: {}", error)
+        }
+    }
+}
+
+/// Translates a PlantUML class diagram into this tool's language.
+///
+/// Only classes (with their fields) and relations between them are understood.
+/// Every other line is skipped and reported, rather than failing the import.
+/// The returned graph's `raw` is the translated source, so it can replace the
+/// PlantUML from then on.
+pub fn import(input: &str) -> (Graph, Vec<Diagnostic<ImportError>>) {
+    let mut translator = Translator::default();
+
+    for (line, span) in lines(input) {
+        translator.translate_line(line, span);
+    }
+
+    if let Some(class) = translator.class_body.take() {
+        translator.diagnostics.push(Diagnostic::new(ImportError::UnclosedClassBody(class.name.clone()), class.span));
+        translator.close_class_body(class);
+    }
+
+    let source = translator.output.join("\n");
+    let (graph, errors) = parse_graph_recovering(&source);
+
+    // The errors are about the translated source, so they're reported on the PlantUML lines it came from
+    let mut sources = Vec::new();
+    for (output, span) in translator.output.iter().zip(translator.sources.iter()) {
+        sources.extend(output.split('\n').map(|_| *span));
+    }
+    for error in errors {
+        let span = sources.get(error.line().saturating_sub(1)).or(sources.last()).copied().unwrap_or_default();
+        translator.diagnostics.push(Diagnostic::new(ImportError::InvalidTranslation(error.error.to_string()), span));
+    }
+
+    (graph, translator.diagnostics)
+}
+
+/// A class whose body is still being read
+struct ClassBody {
+    name: String,
+    span: Span,
+    attributes: Vec<Attribute>,
+    /// Where the class goes in the output, so it keeps its place among the other statements
+    output_index: usize
+}
+
+#[derive(Default)]
+struct Translator {
+    output: Vec<String>,
+    /// The PlantUML line each statement of the output was translated from
+    sources: Vec<Span>,
+    diagnostics: Vec<Diagnostic<ImportError>>,
+    /// Classes declared with "as", mapped to their full names
+    aliases: HashMap<String, String>,
+    class_body: Option<ClassBody>
+}
+
+impl Translator {
+    fn translate_line(&mut self, line: &str, span: Span) {
+        let line = line.trim();
+
+        if self.class_body.is_some() {
+            if line == "}" {
+                let class = self.class_body.take().unwrap();
+                self.close_class_body(class);
+            } else if !line.is_empty() {
+                self.translate_member(line, span);
+            }
+            return;
+        }
+
+        if line.is_empty() || line.starts_with("@startuml") || line.starts_with("@enduml") {
+            return;
+        }
+
+        if let Some(comment) = line.strip_prefix('\'') {
+            self.push(format!("// {}", comment.trim()), span);
+            return;
+        }
+
+        let result = match parse_class_keyword(line) {
+            Some(rest) => self.translate_class(rest, span),
+            None => self.translate_relation(line, span)
+        };
+
+        if result.is_none() {
+            self.diagnostics.push(Diagnostic::new(ImportError::UnsupportedLine(line.to_string()), span));
+        }
+    }
+
+    fn translate_class(&mut self, rest: &str, span: Span) -> Option<()> {
+        let (name, rest) = take_name(rest)?;
+        let (name, rest) = match rest.trim_start().strip_prefix("as ") {
+            Some(rest) => {
+                let (alias, rest) = take_name(rest.trim_start())?;
+                self.aliases.insert(alias, name.clone());
+                (name, rest)
+            },
+            None => (self.resolve(name), rest)
+        };
+
+        match rest.trim() {
+            "" | "{}" => self.push(write_statement(&entity_statement(name)), span),
+            "{" => {
+                self.push(String::new(), span); // Filled in once the body is closed
+                self.class_body = Some(ClassBody { name, span, attributes: Vec::new(), output_index: self.output.len() - 1 });
+            },
+            _ => return None
+        }
+
+        Some(())
+    }
+
+    fn close_class_body(&mut self, class: ClassBody) {
+        let statement = if class.attributes.is_empty() {
+            entity_statement(class.name)
+        } else {
+            Statement::Attributes { entity: Spanned::new(class.name, Span::default()), attributes: class.attributes, span: Span::default() }
+        };
+        self.output[class.output_index] = write_statement(&statement);
+    }
+
+    /// Fields like "+id : Uuid" are translated. Methods (and anything else) aren't.
+    fn translate_member(&mut self, line: &str, span: Span) {
+        let visibility = match line.chars().next() {
+            Some('+') => Some(Visibility::Public),
+            Some('-') => Some(Visibility::Private),
+            Some('#') => Some(Visibility::Protected),
+            Some('~') => Some(Visibility::Package),
+            _ => None
+        };
+        let rest = if visibility.is_some() { line[1..].trim_start() } else { line };
+
+        let (name, ty) = match rest.split_once(':') {
+            Some((name, ty)) => (name.trim(), Some(ty.trim())),
+            None => (rest.trim(), None)
+        };
+
+        let is_field = !name.is_empty() && !name.contains(['(', ')', '{', '}']) && ty.is_none_or(|ty| !ty.is_empty());
+        let Some(class) = self.class_body.as_mut().filter(|_| is_field) else {
+            self.diagnostics.push(Diagnostic::new(ImportError::UnsupportedLine(line.to_string()), span));
+            return;
+        };
+
+        class.attributes.push(Attribute {
+            name: name.to_string(),
+            ty: ty.map(String::from),
+            visibility,
+            multiplicity: Multiplicity::None,
+            span: Span::default()
+        });
+    }
+
+    fn translate_relation(&mut self, line: &str, span: Span) -> Option<()> {
+        let (entity_1, rest) = take_name(line)?;
+        let (mult_1, rest) = take_quoted(rest.trim_start());
+        let (arrow, rest) = take_arrow(rest.trim_start())?;
+        let (arrow_1, style, arrow_2) = parse_arrow(arrow)?;
+        let (mult_2, rest) = take_quoted(rest.trim_start());
+        let (entity_2, rest) = take_name(rest.trim_start())?;
+
+        let label = match rest.trim() {
+            "" => None,
+            rest => {
+                // A "<" or ">" in the label only shows which way to read it
+                let label = rest.strip_prefix(':')?.trim().trim_start_matches('<').trim_end_matches('>').trim();
+                (!label.is_empty()).then_some(label)
+            }
+        };
+
+        let mult_1 = mult_1.and_then(|mult| self.parse_multiplicity(mult, span));
+        let mult_2 = mult_2.and_then(|mult| self.parse_multiplicity(mult, span));
+        let (entity_1, entity_2) = (self.resolve(entity_1), self.resolve(entity_2));
+
+        let relation = Statement::NewRelation {
+            text: label.map(String::from),
+            weight: NonZeroUsize::MIN,
+            style,
+            entity_1: Spanned::new(entity_1, Span::default()),
+            entity_2: Spanned::new(entity_2, Span::default()),
+            arrow_1,
+            arrow_2,
+            mult_1: mult_1.unwrap_or(Multiplicity::None),
+            mult_2: mult_2.unwrap_or(Multiplicity::None),
+            span: Span::default()
+        };
+
+        self.push(write_statement(&relation), span);
+        Some(())
+    }
+
+    fn parse_multiplicity(&mut self, text: &str, span: Span) -> Option<Multiplicity> {
+        let multiplicity = parse_multiplicity(text);
+        if multiplicity.is_none() {
+            self.diagnostics.push(Diagnostic::new(ImportError::UnsupportedMultiplicity(text.to_string()), span));
+        }
+        multiplicity
+    }
+
+    fn push(&mut self, statement: String, span: Span) {
+        self.output.push(statement);
+        self.sources.push(span);
+    }
+
+    fn resolve(&self, name: String) -> String {
+        self.aliases.get(&name).cloned().unwrap_or(name)
+    }
+}
+
+/// A class without fields, which is just declared
+fn entity_statement(name: String) -> Statement {
+    Statement::Entity { entity: Spanned::new(name, Span::default()), color: None, style: None, span: Span::default() }
+}
+
+/// Splits the input into lines, along with where each line is
+fn lines(input: &str) -> impl Iterator<Item=(&str, Span)> {
+    let mut offset = 0;
+    input.split('\n').enumerate().map(move |(index, line)| {
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        let start = Position { offset, line: index + 1, column: 1 };
+        let end = Position { offset: offset + line.len(), line: index + 1, column: line.chars().count() + 1 };
+        offset += line.len() + 1;
+        (line, Span::new(start, end))
+    })
+}
+
+/// The rest of the line after a class keyword, if it starts with one
+fn parse_class_keyword(line: &str) -> Option<&str> {
+    ["abstract class ", "class ", "abstract ", "interface ", "enum "].iter()
+        .find_map(|keyword| line.strip_prefix(keyword))
+        .map(str::trim_start)
+}
+
+/// Reads a quoted name, or a name made of letters, digits, underscores and dots
+fn take_name(text: &str) -> Option<(String, &str)> {
+    if let (Some(name), rest) = take_quoted(text) {
+        return Some((name.to_string(), rest));
+    }
+
+    let end = text.find(|c: char| !(c.is_alphanumeric() || c == '_' || c == '.')).unwrap_or(text.len());
+    // Dots at the end are the start of a dotted arrow, like in "A..>B"
+    let end = text[..end].trim_end_matches('.').len();
+    if end == 0 {
+        return None;
+    }
+    Some((text[..end].to_string(), &text[end..]))
+}
+
+fn take_quoted(text: &str) -> (Option<&str>, &str) {
+    let Some(rest) = text.strip_prefix('"') else {
+        return (None, text);
+    };
+    match rest.split_once('"') {
+        Some((quoted, rest)) => (Some(quoted), rest),
+        None => (None, text)
+    }
+}
+
+/// The directions an arrow can have, like "-up-" or "-u-"
+const DIRECTIONS: [&str; 8] = ["up", "down", "left", "right", "u", "d", "l", "r"];
+
+/// Reads an arrow by the characters arrows are made of, so it doesn't need spaces
+/// around it, like in "A-->B". An "o" end only counts as one if it isn't the start
+/// or end of a name.
+fn take_arrow(text: &str) -> Option<(&str, &str)> {
+    let is_line = |c: char| c == '-' || c == '.';
+    let starts_line = |text: &str| text.starts_with(is_line);
+
+    let mut end = ["<|", "<", "*", "o"].iter()
+        .find(|head| text.strip_prefix(**head).is_some_and(starts_line))
+        .map_or(0, |head| head.len());
+
+    let mut line_length = 0;
+    loop {
+        let rest = &text[end..];
+        if rest.starts_with(is_line) {
+            end += 1;
+        } else if rest.starts_with('[') {
+            end += rest.find(']')? + 1;
+        } else if let Some(direction) = DIRECTIONS.iter().find(|direction| rest.strip_prefix(**direction).is_some_and(starts_line)) {
+            end += direction.len();
+        } else {
+            break;
+        }
+        line_length += 1;
+    }
+    if line_length == 0 {
+        return None;
+    }
+
+    let rest = &text[end..];
+    let tail = ["|>", ">", "*"].iter().find(|tail| rest.starts_with(**tail)).copied()
+        .or_else(|| rest.strip_prefix('o').is_some_and(|after| after.is_empty() || after.starts_with(|c: char| c.is_whitespace() || c == '"')).then_some("o"));
+    end += tail.map_or(0, str::len);
+
+    Some((&text[..end], &text[end..]))
+}
+
+/// Splits an arrow like "<|--" or "*-[bold]->" into its ends and the style of its line
+fn parse_arrow(arrow: &str) -> Option<(Arrow, Style, Arrow)> {
+    let (arrow_1, rest) = [("<|", Arrow::Inheritance), ("<", Arrow::Arrow), ("*", Arrow::Composition), ("o", Arrow::Aggregation)].into_iter()
+        .find_map(|(end, kind)| arrow.strip_prefix(end).map(|rest| (kind, rest)))
+        .unwrap_or((Arrow::None, arrow));
+    let (arrow_2, line) = [("|>", Arrow::Inheritance), (">", Arrow::Arrow), ("*", Arrow::Composition), ("o", Arrow::Aggregation)].into_iter()
+        .find_map(|(end, kind)| rest.strip_suffix(end).map(|line| (kind, line)))
+        .unwrap_or((Arrow::None, rest));
+
+    // The line can have a style in brackets, like "-[dotted]-"
+    let (line, bracketed) = match line.split_once('[') {
+        Some((before, after)) => {
+            let (bracketed, after) = after.split_once(']')?;
+            (format!("{}{}", before, after), Some(bracketed))
+        },
+        None => (line.to_string(), None)
+    };
+
+    // It can also have a direction, like "-up-" or "-u-", which doesn't matter here
+    let direction: String = line.chars().filter(|c| c.is_alphabetic()).collect();
+    if !direction.is_empty() && !DIRECTIONS.contains(&direction.as_str()) {
+        return None;
+    }
+    let line: String = line.chars().filter(|c| !c.is_alphabetic()).collect();
+
+    if line.is_empty() || !line.chars().all(|c| c == '-' || c == '.') {
+        return None;
+    }
+
+    let style = match bracketed {
+        Some("dotted") => Style::Dotted,
+        Some("dashed") => Style::Dashed,
+        Some("bold") => Style::Bold,
+        Some(_) => return None,
+        None if line.contains('.') => Style::Dashed,
+        None => Style::Regular
+    };
+
+    Some((arrow_1, style, arrow_2))
+}
+
+/// Reads multiplicities like "1", "0..5", "*" and "1..*"
+fn parse_multiplicity(text: &str) -> Option<Multiplicity> {
+    let text = text.trim();
+    if text == "*" {
+        return Some(Multiplicity::RangeFrom(RangeFrom { start: 0 }));
+    }
+
+    match text.split_once("..") {
+        None => text.parse().ok().map(Multiplicity::Number),
+        Some((start, "*")) => start.trim().parse().ok().map(|start| Multiplicity::RangeFrom(RangeFrom { start })),
+        Some((start, end)) => Some(Multiplicity::Range(Range { start: start.trim().parse().ok()?, end: end.trim().parse().ok()? }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn relations() {
+        let input = "@startuml\nCustomer \"1\" --> \"*\" Order : places >\nOrder *-- \"1..*\" LineItem\nEmployee <|-- Manager\nTeam o.. Player\nA -[bold]-> B\n@enduml\n";
+
+        let (graph, diagnostics) = import(input);
+
+        assert!(diagnostics.is_empty());
+        assert_eq!(graph.raw, "Customer 1-places-0..>Order\nOrder<#>-1..LineItem\nEmployee<|-Manager\nTeam<>..Player\nA=>B");
+        assert_eq!(graph.relations.len(), 5);
+
+        let places = &graph.relations[0];
+        assert_eq!(places.text.as_deref(), Some("places"));
+        assert_eq!((places.mult_1.clone(), places.mult_2.clone()), (Multiplicity::Number(1), Multiplicity::RangeFrom(0..)));
+        assert_eq!(places.arrow_2, Arrow::Arrow);
+
+        assert_eq!(graph.relations[2].arrow_1, Arrow::Inheritance);
+        assert_eq!(graph.relations[3].style, Style::Dashed);
+        assert_eq!(graph.relations[4].style, Style::Bold);
+    }
+
+    #[test]
+    fn relations_without_spaces() {
+        let input = "A-->B\nFoo<|--Bar\nTeam o..Player\nOrder*--\"1..*\"LineItem\nC..>D";
+
+        let (graph, diagnostics) = import(input);
+
+        assert!(diagnostics.is_empty());
+        assert_eq!(graph.raw, "A->B\nFoo<|-Bar\nTeam<>..Player\nOrder<#>-1..LineItem\nC..>D");
+    }
+
+    #[test]
+    fn directions_are_ignored() {
+        let input = "A -up-> B\nA -u-> C\nA-d-D\nA -l-|> E\nA *-r- F\nA -right.> G";
+
+        let (graph, diagnostics) = import(input);
+
+        assert!(diagnostics.is_empty());
+        assert_eq!(graph.raw, "A->B\nA->C\nA-D\nA-|>E\nA<#>-F\nA..>G");
+    }
+
+    #[test]
+    fn classes_and_aliases() {
+        let input = "class \"Line Item\" as LI\nabstract class Order {\n  +id : Uuid\n  -total: Money\n  +place()\n}\nOrder *-- LI\ninterface Shipper";
+
+        let (graph, diagnostics) = import(input);
+
+        assert_eq!(diagnostics.iter().map(|diagnostic| diagnostic.line()).collect::<Vec<_>>(), vec![5]);
+        assert_eq!(graph.raw, "entity Line Item\nOrder {\n    +id: Uuid\n    -total: Money\n}\nOrder<#>-Line Item\nentity Shipper");
+
+        let order = &graph.entities[1];
+        assert_eq!(order.name, "Order");
+        assert_eq!(order.attributes[0].visibility, Some(Visibility::Public));
+        assert_eq!(order.attributes[1].ty.as_deref(), Some("Money"));
+    }
+
+    #[test]
+    fn unsupported_lines_are_reported() {
+        let input = "skinparam monochrome true\nA -- B\nnote left of A: hi\nA \"0..n\" -- B\nclass C <<Entity>>";
+
+        let (graph, diagnostics) = import(input);
+
+        assert_eq!(
+            diagnostics.iter().map(|diagnostic| (diagnostic.line(), &diagnostic.error)).collect::<Vec<_>>(),
+            vec![
+                (1, &ImportError::UnsupportedLine("skinparam monochrome true".to_string())),
+                (3, &ImportError::UnsupportedLine("note left of A: hi".to_string())),
+                (4, &ImportError::UnsupportedMultiplicity("0..n".to_string())),
+                (5, &ImportError::UnsupportedLine("class C <<Entity>>".to_string()))
+            ]
+        );
+        assert_eq!(graph.relations.len(), 2);
+    }
+
+    #[test]
+    fn dashed_relations_keep_their_multiplicities() {
+        let (graph, diagnostics) = import("A \"1\" ..> \"0..1\" B");
+
+        assert!(diagnostics.is_empty());
+        assert_eq!(graph.raw, "A 1 ..0..1>B");
+        let relation = &graph.relations[0];
+        assert_eq!(graph.entities[relation.entity_1].name, "A");
+        assert_eq!((relation.mult_1.clone(), relation.mult_2.clone()), (Multiplicity::Number(1), Multiplicity::Range(0..1)));
+        assert_eq!((relation.arrow_1, relation.arrow_2), (Arrow::None, Arrow::Arrow));
+    }
+
+    #[test]
+    fn invalid_translations_are_reported_on_their_line() {
+        let input = "class Order {\n  +id : Uuid\n}\nOrder -- Customer\nclass Order {\n  +id : Text\n}";
+
+        let (graph, diagnostics) = import(input);
+
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].line(), 5);
+        assert!(matches!(&diagnostics[0].error, ImportError::InvalidTranslation(error) if error.contains("already has an attribute named \"id\"")));
+        assert_eq!(graph.relations.len(), 1);
+    }
+}
\ No newline at end of file
@@ -1,2 +1,5 @@
+pub mod export;
 pub mod graph;
-pub mod parser;
\ No newline at end of file
+pub mod import;
+pub mod parser;
+pub mod span;
\ No newline at end of file
@@ -0,0 +1,343 @@
+use std::collections::HashSet;
+
+use itertools::Itertools;
+
+use crate::{domain_model::{graph::{Arrow, Attribute, Multiplicity, Style}, parser::{Diagnostic, build_graph, statementizer::{Statement, StatementParsingIterator}, tokenizer::{escape_identifier, Token, TokenParsingIterator}}, span::{Span, Spanned}}};
+
+/// A line (or, for attribute blocks, a few lines) of the formatted output
+struct Line {
+    text: String,
+    /// Where the line came from in the input
+    span: Span
+}
+
+/// Rewrites the input the canonical way. Spacing is normalized, names are only
+/// quoted when they have to be, and every pin is moved into one block at the
+/// end, sorted by entity. Comments and single blank lines are kept.
+///
+/// The formatted input always parses into the same graph as the original.
+pub fn format(input: &str) -> Result<String, Vec<Diagnostic>> {
+    let (statements, mut diagnostics): (Vec<Statement>, Vec<Diagnostic>) = {
+        StatementParsingIterator::new(TokenParsingIterator::new(input))
+            .partition_result()
+    };
+    diagnostics.extend(build_graph(input, &statements).1);
+
+    if !diagnostics.is_empty() {
+        diagnostics.sort_by_key(|diagnostic| diagnostic.span.start.offset);
+        return Err(diagnostics);
+    }
+
+    let mut comments = {
+        TokenParsingIterator::new(input)
+            .filter_map(|token| match token {
+                Ok(Spanned { value: Token::Comment(text), span }) => Some(Spanned::new(text, span)),
+                _ => None
+            })
+            .peekable()
+    };
+
+    let mut lines = Vec::new();
+    let mut pins = Vec::new();
+    let mut seen_entities = HashSet::new();
+
+    for statement in statements.iter() {
+        let span = statement.span();
+
+        while let Some(comment) = comments.next_if(|comment| comment.span.start.offset < span.start.offset) {
+            lines.push(Line { text: write_comment(&comment.value), span: comment.span });
+        }
+        let inner_comments: Vec<Spanned<String>> = comments.peeking_take_while(|comment| comment.span.start.offset < span.end.offset).collect();
+        let trailing_comment = comments.next_if(|comment| comment.span.start.line == span.end.line);
+
+        let mut text = write_statement_with_comments(statement, &inner_comments);
+        if let Some(comment) = trailing_comment {
+            text.push(' ');
+            text.push_str(&write_comment(&comment.value));
+        }
+
+        match statement {
+            Statement::Pin { entity, .. } => {
+                // Entities are numbered by where they first appear, so moving the pin
+                // can't move where its entity first appears
+                if seen_entities.insert(entity.value.clone()) {
+                    lines.push(Line { text: format!("entity {}", escape_identifier(&entity.value)), span });
+                }
+                pins.push((entity.value.clone(), text));
+            },
+            Statement::NewRelation { entity_1, entity_2, .. } => {
+                seen_entities.insert(entity_1.value.clone());
+                seen_entities.insert(entity_2.value.clone());
+                lines.push(Line { text, span });
+            },
+            Statement::Entity { entity, .. } | Statement::Attributes { entity, .. } => {
+                seen_entities.insert(entity.value.clone());
+                lines.push(Line { text, span });
+            },
+            Statement::Layout { .. } | Statement::Config { .. } => lines.push(Line { text, span })
+        }
+    }
+
+    for comment in comments {
+        lines.push(Line { text: write_comment(&comment.value), span: comment.span });
+    }
+
+    let mut output = String::new();
+
+    for (index, line) in lines.iter().enumerate() {
+        if index > 0 && has_blank_line_between(input, lines[index - 1].span, line.span) {
+            output.push('\n');
+        }
+        output.push_str(&line.text);
+        output.push('\n');
+    }
+
+    // A stable sort, so that if an entity is pinned twice, the last pin still wins
+    pins.sort_by(|(entity_1, _), (entity_2, _)| entity_1.cmp(entity_2));
+
+    if !pins.is_empty() && !lines.is_empty() {
+        output.push('\n');
+    }
+    for (_, pin) in pins {
+        output.push_str(&pin);
+        output.push('\n');
+    }
+
+    Ok(output)
+}
+
+/// Writes one statement the canonical way, without a trailing new line
+pub fn write_statement(statement: &Statement) -> String {
+    write_statement_with_comments(statement, &[])
+}
+
+/// Comments can only be inside a statement if it's an attribute block, since that's
+/// the only statement that can span more than one line
+fn write_statement_with_comments(statement: &Statement, comments: &[Spanned<String>]) -> String {
+    match statement {
+        Statement::NewRelation { text, weight, style, entity_1, entity_2, arrow_1, arrow_2, mult_1, mult_2, .. } => {
+            let segment = match style {
+                Style::Regular => "-",
+                Style::Dashed => "..",
+                Style::Dotted => "~",
+                Style::Bold => "="
+            }.repeat(weight.get());
+
+            let mut relation = escape_identifier(&entity_1.value);
+            if *arrow_1 == Arrow::None && *mult_1 != Multiplicity::None {
+                relation.push(' '); // Reads better than a number right after the name
+            }
+            relation.push_str(match arrow_1 {
+                Arrow::None => "",
+                Arrow::Arrow => "<",
+                Arrow::Inheritance => "<|",
+                Arrow::Aggregation => "<>",
+                Arrow::Composition => "<#>"
+            });
+            relation.push_str(&mult_1.to_string());
+            if *style == Style::Dashed && let Multiplicity::Number(_) = mult_1 {
+                relation.push(' '); // Keeps the dots from being read as a range
+            }
+            relation.push_str(&segment);
+            if let Some(text) = text {
+                relation.push_str(&escape_identifier(text));
+                relation.push_str(&segment);
+            }
+            if *style == Style::Dotted && *mult_2 != Multiplicity::None {
+                relation.push(' '); // Keeps the tilde from being read as a minus sign
+            }
+            relation.push_str(&mult_2.to_string());
+            relation.push_str(match arrow_2 {
+                Arrow::None => "",
+                Arrow::Arrow => ">",
+                Arrow::Inheritance => "|>",
+                Arrow::Aggregation => "<>",
+                Arrow::Composition => "<#>"
+            });
+            relation.push_str(&escape_identifier(&entity_2.value));
+            relation
+        },
+        Statement::Pin { entity, x, y, .. } => {
+            format!("pin {}: {} {}", escape_identifier(&entity.value), write_number(*x), write_number(*y))
+        },
+        Statement::Config { setting, value, .. } => {
+            format!("config {}: {}", escape_identifier(&setting.value), write_number(*value))
+        },
+        Statement::Entity { entity, color, style, .. } => {
+            let mut customizations = Vec::new();
+            if let Some(color) = color {
+                customizations.push(format!("#{:06x}", color));
+            }
+            if let Some(style) = style {
+                customizations.push(match style {
+                    Style::Regular => "regular",
+                    Style::Dotted => "dotted",
+                    Style::Dashed => "dashed",
+                    Style::Bold => "bold"
+                }.to_string());
+            }
+
+            if customizations.is_empty() {
+                format!("entity {}", escape_identifier(&entity.value))
+            } else {
+                format!("entity {}: {}", escape_identifier(&entity.value), customizations.join(" "))
+            }
+        },
+        Statement::Layout { layout, .. } => format!("layout {}", escape_identifier(&layout.value)),
+        Statement::Attributes { entity, attributes, .. } => {
+            if attributes.is_empty() && comments.is_empty() {
+                return format!("{} {{}}", escape_identifier(&entity.value));
+            }
+
+            let mut block = format!("{} {{\n", escape_identifier(&entity.value));
+            let mut comments = comments.iter().peekable();
+
+            for attribute in attributes.iter() {
+                while let Some(comment) = comments.next_if(|comment| comment.span.start.offset < attribute.span.start.offset) {
+                    block.push_str(&format!("    {}\n", write_comment(&comment.value)));
+                }
+                block.push_str("    ");
+                block.push_str(&write_attribute(attribute));
+                if let Some(comment) = comments.next_if(|comment| comment.span.start.line == attribute.span.end.line) {
+                    block.push(' ');
+                    block.push_str(&write_comment(&comment.value));
+                }
+                block.push('\n');
+            }
+            for comment in comments {
+                block.push_str(&format!("    {}\n", write_comment(&comment.value)));
+            }
+
+            block.push('}');
+            block
+        }
+    }
+}
+
+fn write_attribute(attribute: &Attribute) -> String {
+    let mut text = attribute.visibility.map(|visibility| visibility.to_string()).unwrap_or_default();
+    text.push_str(&escape_identifier(&attribute.name));
+    if let Some(ty) = &attribute.ty {
+        text.push_str(": ");
+        text.push_str(&escape_identifier(ty));
+    }
+    if attribute.multiplicity != Multiplicity::None {
+        text.push_str(&format!(" [{}]", attribute.multiplicity));
+    }
+    text
+}
+
+/// Negative numbers are written with a tilde, since a dash would start a relation
+fn write_number(number: f32) -> String {
+    number.to_string().replace('-', "~")
+}
+
+fn write_comment(text: &str) -> String {
+    format!("//{}", text)
+}
+
+/// Whether the input has an empty line between the two spans
+fn has_blank_line_between(input: &str, before: Span, after: Span) -> bool {
+    if after.start.offset <= before.end.offset {
+        return false;
+    }
+
+    let between = &input[before.end.offset..after.start.offset];
+    let lines: Vec<&str> = between.split('\n').collect();
+
+    // The first and last pieces are the ends of the lines the spans are on
+    lines.len() > 2 && lines[1..lines.len() - 1].iter().any(|line| line.trim().is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::Value;
+
+    use crate::domain_model::parser::parse_graph;
+
+    use super::*;
+
+    /// The graph as JSON, without anything that depends on where things are in the input
+    fn graph_without_spans(input: &str) -> Value {
+        fn strip(value: &mut Value) {
+            match value {
+                Value::Object(map) => {
+                    map.remove("span");
+                    map.remove("mentions");
+                    map.remove("raw");
+                    map.values_mut().for_each(strip);
+                },
+                Value::Array(values) => values.iter_mut().for_each(strip),
+                _ => {}
+            }
+        }
+
+        let Ok(graph) = parse_graph(input) else { panic!("Parsing failed") };
+        let mut value = serde_json::to_value(&graph).unwrap();
+        strip(&mut value);
+        value
+    }
+
+    const INPUTS: [&str; 6] = [
+        "layout  hierarchical\nconfig delta time:0.05\na - knows ->b\nb<-c",
+        "pin Jill: 1 ~2.5\nJack-fetches-1>Pail of water\n\n\n// Characters\nPeter Piper --picked-- 1..>Pickled Peppers // twice\npin Jack: 0 0",
+        "entity Customer: bold #36f\nCustomer -places-0..5 > Order\nOrder { +id: Uuid, -lines: Line [1..*] }",
+        "Order {\n  id // the id\n  // totals\n  total: Money\n}\n",
+        "a~~b\nc ..uses.. >d\ne==f\ng 1..~ 2>h\nManager -|> Employee\nTeam <> - Player\nOrder <#>-Line\ni 1 ....>j\nk 2..>l",
+        "\"Vec2\"-\"pin cushion\"\n'First-Person Camera'-`say \"hi\"`",
+    ];
+
+    #[test]
+    fn canonical_output() {
+        let input = "pin Jill: 1 ~2.5\nJack - fetches - 1 > Pail of water\n\n\n// Characters\nPeter Piper --picked-- 1..>Pickled Peppers // twice\nentity Jack: bold #36f\npin Jack: 0 0\nOrder { +id: Uuid, lines: Line [1..*] }";
+
+        assert_eq!(format(input).ok().as_deref(), Some(concat!(
+            "entity Jill\n",
+            "Jack-fetches-1>Pail of water\n",
+            "\n",
+            "// Characters\n",
+            "Peter Piper--picked--1..>Pickled Peppers // twice\n",
+            "entity Jack: #3366ff bold\n",
+            "Order {\n",
+            "    +id: Uuid\n",
+            "    lines: Line [1..]\n",
+            "}\n",
+            "\n",
+            "pin Jack: 0 0\n",
+            "pin Jill: 1 ~2.5\n",
+        )));
+    }
+
+    #[test]
+    fn names_are_only_quoted_when_needed() {
+        assert_eq!(format(INPUTS[5]).ok().as_deref(), Some("\"Vec2\"-\"pin cushion\"\n\"First-Person Camera\"-\"say \\\"hi\\\"\"\n"));
+        assert_eq!(format("'Customer'-`Order`").ok().as_deref(), Some("Customer-Order\n"));
+    }
+
+    #[test]
+    fn comments_in_attribute_blocks() {
+        assert_eq!(format(INPUTS[3]).ok().as_deref(), Some("Order {\n    id // the id\n    // totals\n    total: Money\n}\n"));
+    }
+
+    #[test]
+    fn formatting_is_idempotent() {
+        for input in INPUTS {
+            let Ok(once) = format(input) else { panic!("Formatting failed for {:?}", input) };
+            assert_eq!(format(&once).ok(), Some(once.clone()), "{:?}", input);
+        }
+    }
+
+    #[test]
+    fn formatting_keeps_the_graph() {
+        for input in INPUTS {
+            let Ok(formatted) = format(input) else { panic!("Formatting failed for {:?}", input) };
+            assert_eq!(graph_without_spans(&formatted), graph_without_spans(input), "{:?}", formatted);
+        }
+    }
+
+    #[test]
+    fn errors_are_reported() {
+        let Err(diagnostics) = format("a-b\na-<b\nc") else { panic!("Expected formatting to fail") };
+        assert_eq!(diagnostics.iter().map(Diagnostic::line).collect::<Vec<_>>(), vec![2, 3]);
+    }
+}
\ No newline at end of file
@@ -1,18 +1,36 @@
-mod tokenizer;
-mod statementizer;
+pub mod tokenizer;
+pub mod statementizer;
+pub mod formatter;
 
 use core::fmt;
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap};
 
 use serde::{Deserialize, Serialize};
 
-use crate::{domain_model::{graph::{Entity, Graph, Style}, parser::{statementizer::{ParseStatementError, Statement, StatementParsingIterator}, tokenizer::{ParseTokenError, Token, TokenParsingIterator}}}, image_generation::placers::Vec2};
+use itertools::Itertools;
+
+use crate::{domain_model::{graph::{ColorHexValue, DEFAULT_COLOR, Entity, EntityIndex, Graph, Setting, Style}, parser::{statementizer::{ParseStatementError, Statement, StatementParsingIterator}, tokenizer::{ParseTokenError, TokenParsingIterator}}, span::Span}, image_generation::placers::Vec2};
 
 
 #[derive(Serialize, Deserialize)]
 pub enum ParseGraphError {
     TokenizationFailed(ParseTokenError),
     StatementizationFailed(ParseStatementError),
+    /// An entity was declared twice with different customizations
+    ConflictingDeclaration {
+        entity: String,
+        first_declared: Span
+    },
+    /// An entity was given two attributes with the same name
+    DuplicateAttribute {
+        entity: String,
+        attribute: String,
+        first_declared: Span
+    },
+    /// A `layout` or `config` statement that placing can't make sense of, and why
+    InvalidLayout(String),
+    /// The placer options passed along with the source couldn't be read, e.g. because of an unknown layout
+    InvalidOptions(String),
 }
 
 impl fmt::Display for ParseGraphError {
@@ -20,82 +38,175 @@ impl fmt::Display for ParseGraphError {
         match self {
             ParseGraphError::TokenizationFailed(e) => write!(f, "Tokenization failed: {}", e),
             ParseGraphError::StatementizationFailed(e) => write!(f, "Statementization failed: {}", e),
+            ParseGraphError::ConflictingDeclaration { entity, first_declared } => write!(f, "\"{}\" was already declared differently on line {}", entity, first_declared.start.line),
+            ParseGraphError::DuplicateAttribute { entity, attribute, first_declared } => write!(f, "\"{}\" already has an attribute named \"{}\" (on line {})", entity, attribute, first_declared.start.line),
+            ParseGraphError::InvalidLayout(e) => write!(f, "{}", e),
+            ParseGraphError::InvalidOptions(e) => write!(f, "Invalid options: {}", e),
         }
     }
 }
 
-pub fn parse_graph(input: &str) -> Result<Graph, ParseGraphError> {
-    let raw = input.to_string();
-    
-    let tokens = {
-        TokenParsingIterator::new(input)
-            .collect::<Result<Vec<Token>, ParseTokenError>>()
-            .map_err(ParseGraphError::TokenizationFailed)
-    }?;
+/// An error found while parsing, along with where in the input it was found
+#[derive(Serialize, Deserialize)]
+pub struct Diagnostic<E = ParseGraphError> {
+    pub error: E,
+    pub span: Span
+}
+
+impl<E> Diagnostic<E> {
+    pub fn new(error: E, span: Span) -> Self {
+        Diagnostic { error, span }
+    }
+
+    pub fn line(&self) -> usize {
+        self.span.start.line
+    }
+}
+
+impl<E: fmt::Display> fmt::Display for Diagnostic<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Line {}: {}", self.line(), self.error)
+    }
+}
 
+/// Parses the input, failing on the first error
+pub fn parse_graph(input: &str) -> Result<Graph, ParseGraphError> {
     let statements = {
-        StatementParsingIterator::new(tokens.iter())
-            .collect::<Result<Vec<Statement>, ParseStatementError>>()
-            .map_err(ParseGraphError::StatementizationFailed)
+        StatementParsingIterator::new(TokenParsingIterator::new(input))
+            .collect::<Result<Vec<Statement>, Diagnostic>>()
+            .map_err(|diagnostic| diagnostic.error)
     }?;
 
-    // Add entities as they appear, so that the entity ids are in a deterministic order
-    let entities = {
-        let mut entity_appearances = HashMap::new();
-        let mut entity_names = HashSet::new();
+    let (graph, diagnostics) = build_graph(input, &statements);
+
+    match diagnostics.into_iter().next() {
+        Some(diagnostic) => Err(diagnostic.error),
+        None => Ok(graph)
+    }
+}
+
+/// Parses the input, skipping any statements that fail to parse. The graph is
+/// built from the statements that did parse, and every error is returned
+/// alongside it.
+pub fn parse_graph_recovering(input: &str) -> (Graph, Vec<Diagnostic>) {
+    let (statements, mut diagnostics): (Vec<Statement>, Vec<Diagnostic>) = {
+        StatementParsingIterator::new(TokenParsingIterator::new(input))
+            .partition_result()
+    };
 
-        let mut appearance = 0;
+    let (graph, graph_diagnostics) = build_graph(input, &statements);
+    diagnostics.extend(graph_diagnostics);
+    diagnostics.sort_by_key(|diagnostic| diagnostic.span.start.offset);
+
+    (graph, diagnostics)
+}
+
+fn build_graph(input: &str, statements: &[Statement]) -> (Graph, Vec<Diagnostic>) {
+    let raw = input.to_string();
+    let mut diagnostics = Vec::new();
+
+    // Add entities in the order they first appear, so that the entity ids only depend on the source
+    let entities = {
+        let mut entities: Vec<Entity> = Vec::new();
+        let mut entity_ids: HashMap<&String, EntityIndex> = HashMap::new();
 
         for statement in statements.iter() {
-            match statement {
-                Statement::NewRelation { entity_1, entity_2, .. } => {
-                    entity_appearances.entry(entity_1).or_insert(appearance);
-                    appearance += 1;
-                    entity_appearances.entry(entity_2).or_insert(appearance);
-                    appearance += 1;
-                    entity_names.insert(entity_1);
-                    entity_names.insert(entity_2);
-                },
-                Statement::Pin { entity, .. } => {
-                    entity_appearances.entry(entity).or_insert(appearance);
-                    appearance += 1;
-                    entity_names.insert(entity);
-                }
+            let names = match statement {
+                Statement::NewRelation { entity_1, entity_2, .. } => vec![entity_1, entity_2],
+                Statement::Pin { entity, .. } | Statement::Entity { entity, .. } | Statement::Attributes { entity, .. } => vec![entity],
+                Statement::Layout { .. } | Statement::Config { .. } => Vec::new()
+            };
+
+            for name in names {
+                let id = *entity_ids.entry(&name.value).or_insert_with(|| {
+                    entities.push(Entity {
+                        name: name.value.clone(),
+                        color: DEFAULT_COLOR,
+                        style: Style::Regular,
+                        attributes: Vec::new(),
+                        mentions: Vec::new()
+                    });
+                    entities.len() - 1
+                });
+                entities[id].mentions.push(name.span);
             }
         }
 
-        let mut entities = Vec::new();
+        entities
+    };
+
+    let entity_name_to_id: HashMap<String, usize> = entities.iter().enumerate().map(|(id, entity)| (entity.name.clone(), id)).collect();
+
+    // Customize entities from their declarations. Declarations can be repeated, but can't disagree.
+    let entities = {
+        let mut entities = entities;
+        let mut color_declarations: BTreeMap<EntityIndex, (ColorHexValue, Span)> = BTreeMap::new();
+        let mut style_declarations: BTreeMap<EntityIndex, (Style, Span)> = BTreeMap::new();
 
-        for entity_name in entity_names.iter() {
-            // TODO: Handle customization
-            entities.push(Entity {
-                name: (*entity_name).clone(),
-                color: 0xff0000,
-                style: Style::Regular
-            });
+        for statement in statements.iter() {
+            let Statement::Entity { entity, color, style, span } = statement else { continue };
+            let id = *entity_name_to_id.get(&entity.value).unwrap();
+
+            let conflict = {
+                let color_conflict = color.and_then(|color| declare(&mut color_declarations, id, color, *span));
+                let style_conflict = style.and_then(|style| declare(&mut style_declarations, id, style, *span));
+                color_conflict.or(style_conflict)
+            };
+
+            if let Some(first_declared) = conflict {
+                diagnostics.push(Diagnostic::new(ParseGraphError::ConflictingDeclaration { entity: entity.value.clone(), first_declared }, *span));
+            }
         }
 
-        entities.sort_by_key(|e| entity_appearances.get(&e.name).unwrap());
+        for (id, (color, _)) in color_declarations {
+            entities[id].color = color;
+        }
+        for (id, (style, _)) in style_declarations {
+            entities[id].style = style;
+        }
 
         entities
     };
 
-    let entity_name_to_id: HashMap<String, usize> = entities.iter().enumerate().map(|(id, entity)| (entity.name.clone(), id)).collect();
+    // Attribute blocks add to the attributes the entity already has
+    let entities = {
+        let mut entities = entities;
+
+        for statement in statements.iter() {
+            let Statement::Attributes { entity, attributes, .. } = statement else { continue };
+            let id = *entity_name_to_id.get(&entity.value).unwrap();
+
+            for attribute in attributes.iter() {
+                if let Some(existing) = entities[id].attributes.iter().find(|existing| existing.name == attribute.name) {
+                    diagnostics.push(Diagnostic::new(
+                        ParseGraphError::DuplicateAttribute { entity: entity.value.clone(), attribute: attribute.name.clone(), first_declared: existing.span },
+                        attribute.span
+                    ));
+                    continue;
+                }
+                entities[id].attributes.push(attribute.clone());
+            }
+        }
+
+        entities
+    };
 
     let relations = {
         let mut relations = Vec::new();
 
         for statement in statements.iter() {
-            if let Statement::NewRelation { text, weight, entity_1, entity_2, arrow_1, arrow_2, mult_1, mult_2 } = statement {
+            if let Statement::NewRelation { text, weight, style, entity_1, entity_2, arrow_1, arrow_2, mult_1, mult_2, span } = statement {
                 relations.push(crate::domain_model::graph::Relation {
                     text: text.clone(),
                     weight: *weight,
-                    entity_1: *entity_name_to_id.get(entity_1).unwrap(),
-                    entity_2: *entity_name_to_id.get(entity_2).unwrap(),
+                    style: *style,
+                    entity_1: *entity_name_to_id.get(&entity_1.value).unwrap(),
+                    entity_2: *entity_name_to_id.get(&entity_2.value).unwrap(),
                     arrow_1: *arrow_1,
                     arrow_2: *arrow_2,
                     mult_1: mult_1.clone(),
-                    mult_2: mult_2.clone()
+                    mult_2: mult_2.clone(),
+                    span: *span
                 });
             }
         }
@@ -104,16 +215,258 @@ pub fn parse_graph(input: &str) -> Result<Graph, ParseGraphError> {
     };
 
     let pins = {
-        let mut pins = HashMap::new();
+        let mut pins = BTreeMap::new();
 
         for statement in statements.iter() {
-            if let Statement::Pin { entity, x, y } = statement {
-                pins.insert(*entity_name_to_id.get(entity).unwrap(), Vec2 { x: *x, y: *y });
+            if let Statement::Pin { entity, x, y, .. } = statement {
+                pins.insert(*entity_name_to_id.get(&entity.value).unwrap(), Vec2 { x: *x, y: *y });
             }
         }
 
         pins
     };
 
-    Ok(Graph { entities, relations, pins, raw })
-}
\ No newline at end of file
+    // Like pins, the last layout statement wins. What the layout and the settings
+    // mean is up to placing, which reads them with `placers::read_directives`.
+    let layout = statements.iter().rev().find_map(|statement| match statement {
+        Statement::Layout { layout, .. } => Some(layout.clone()),
+        _ => None
+    });
+
+    let config = statements.iter().filter_map(|statement| match statement {
+        Statement::Config { setting, value, span } => Some(Setting { name: setting.value.clone(), value: *value, span: *span }),
+        _ => None
+    }).collect();
+
+    (Graph { entities, relations, pins, layout, config, raw }, diagnostics)
+}
+
+/// Records a customization for an entity. If the entity was already given a
+/// different value, returns the span of the declaration that gave it.
+fn declare<T: PartialEq>(declarations: &mut BTreeMap<EntityIndex, (T, Span)>, id: EntityIndex, value: T, span: Span) -> Option<Span> {
+    match declarations.get(&id) {
+        Some((declared, first_declared)) if *declared != value => Some(*first_declared),
+        Some(_) => None,
+        None => {
+            declarations.insert(id, (value, span));
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain_model::graph::Multiplicity;
+
+    use super::*;
+
+    #[test]
+    fn relations_point_back_to_their_statement() {
+        let input = "Jack-fetches-1>Pail\nJill<-knows-Jack";
+        let Ok(graph) = parse_graph(input) else { panic!("Parsing failed") };
+
+        assert_eq!(graph.relations[0].span.slice(input), "Jack-fetches-1>Pail");
+        assert_eq!(graph.relations[1].span.slice(input), "Jill<-knows-Jack");
+        assert_eq!(graph.relations[1].span.start.line, 2);
+    }
+
+    #[test]
+    fn recovering_skips_bad_statements() {
+        let input = "Jack-Jill\nJill 1.2.3 Jack\nJill-fetches-Pail\nPail\n\"Water-Jill";
+        let (graph, diagnostics) = parse_graph_recovering(input);
+
+        assert_eq!(graph.entities.iter().map(|entity| entity.name.as_str()).collect::<Vec<_>>(), vec!["Jack", "Jill", "Pail"]);
+        assert_eq!(graph.relations.len(), 2);
+        assert_eq!(diagnostics.iter().map(Diagnostic::line).collect::<Vec<_>>(), vec![2, 4, 5]);
+        assert!(matches!(diagnostics[0].error, ParseGraphError::TokenizationFailed(ParseTokenError::TwoDotsInNumber)));
+        assert!(matches!(diagnostics[1].error, ParseGraphError::StatementizationFailed(..)));
+        assert!(matches!(diagnostics[2].error, ParseGraphError::TokenizationFailed(ParseTokenError::UnterminatedIdentifier)));
+    }
+
+    #[test]
+    fn unclosed_blocks_end_before_the_next_statement() {
+        let input = "Order { id: Uuid,\n  +total: Money [1]\nCustomer-places->Order\nA, B-C\nentity D: bold";
+        let (graph, diagnostics) = parse_graph_recovering(input);
+
+        assert_eq!(graph.entities.iter().map(|entity| entity.name.as_str()).collect::<Vec<_>>(), vec!["Customer", "Order", "A, B", "C", "D"]);
+        assert_eq!(graph.relations.len(), 2);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].line(), 1);
+        assert_eq!(diagnostics[0].span.slice(input), "Order { id: Uuid,\n  +total: Money [1]");
+        assert!(matches!(diagnostics[0].error, ParseGraphError::StatementizationFailed(ParseStatementError::UnclosedBrace)));
+    }
+
+    #[test]
+    fn errors_in_unclosed_blocks_dont_hide_the_rest() {
+        let (graph, diagnostics) = parse_graph_recovering("Order { id: 1.2.3\nA-B\nC-D");
+
+        assert_eq!(graph.relations.len(), 2);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].line(), 1);
+    }
+
+    #[test]
+    fn names_can_start_with_keywords() {
+        let input = "entity store-has-Item\nconfig value-Setting\nlayout engine-Car\npin cushion-Issue #abc\nentity Item: #36f\nconfig iterations: 200";
+        let Ok(graph) = parse_graph(input) else { panic!("Parsing failed") };
+
+        let names = graph.entities.iter().map(|entity| entity.name.as_str()).collect::<Vec<_>>();
+        assert_eq!(names, vec!["entity store", "Item", "config value", "Setting", "layout engine", "Car", "pin cushion", "Issue #abc"]);
+        assert_eq!(graph.relations.len(), 4);
+        assert_eq!(graph.relations[0].text.as_deref(), Some("has"));
+        assert_eq!(graph.entities[1].color, 0x3366ff);
+        assert_eq!(graph.config.len(), 1);
+    }
+
+    #[test]
+    fn dotted_lines_take_multiplicities() {
+        let input = "Jack~fetches~1>Pail\nJill 2~~Jack\nJack ~~3..4 Hill\npin Jill: 1 ~2";
+        let Ok(graph) = parse_graph(input) else { panic!("Parsing failed") };
+
+        let multiplicities = graph.relations.iter().map(|relation| (relation.mult_1.clone(), relation.mult_2.clone())).collect::<Vec<_>>();
+        assert_eq!(multiplicities, vec![
+            (Multiplicity::None, Multiplicity::Number(1)),
+            (Multiplicity::Number(2), Multiplicity::None),
+            (Multiplicity::None, Multiplicity::Range(3..4))
+        ]);
+        assert!(graph.relations.iter().all(|relation| relation.style == Style::Dotted));
+        // Where a number can be negative, the tilde is still a minus sign
+        assert_eq!(graph.pins.values().collect::<Vec<_>>(), vec![&Vec2 { x: 1.0, y: -2.0 }]);
+    }
+
+    #[test]
+    fn unterminated_quotes_end_at_their_line() {
+        let (graph, diagnostics) = parse_graph_recovering("A-B\n\"oops-C\nC-D\nE-F");
+
+        assert_eq!(graph.relations.len(), 3);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].line(), 2);
+        assert!(matches!(diagnostics[0].error, ParseGraphError::TokenizationFailed(ParseTokenError::UnterminatedIdentifier)));
+    }
+
+    #[test]
+    fn comments_are_ignored() {
+        let input = "// The nursery rhyme\nJack-Jill // siblings\n// pin Jack: 0 0\n";
+        let Ok(graph) = parse_graph(input) else { panic!("Parsing failed") };
+
+        assert_eq!(graph.entities.len(), 2);
+        assert_eq!(graph.relations.len(), 1);
+        assert_eq!(graph.relations[0].span.slice(input), "Jack-Jill");
+        assert!(graph.pins.is_empty());
+        assert_eq!(graph.raw, input);
+    }
+
+    #[test]
+    fn entities_are_numbered_by_first_appearance() {
+        let input = "pin Delta: 0 0\nCharlie-Alpha\nBravo-Delta\nentity Echo: #000000\nAlpha-Echo";
+        let Ok(graph) = parse_graph(input) else { panic!("Parsing failed") };
+
+        let names: Vec<&str> = graph.entities.iter().map(|entity| entity.name.as_str()).collect();
+        assert_eq!(names, vec!["Delta", "Charlie", "Alpha", "Bravo", "Echo"]);
+        assert_eq!(graph.entities[2].mentions.len(), 2);
+    }
+
+    #[test]
+    fn entities_can_be_customized() {
+        let input = "entity Customer: #3366ff bold\nCustomer-places-Order\nentity Order: dashed";
+        let Ok(graph) = parse_graph(input) else { panic!("Parsing failed") };
+
+        assert_eq!(graph.entities[0].name, "Customer");
+        assert_eq!(graph.entities[0].color, 0x3366ff);
+        assert_eq!(graph.entities[0].style, Style::Bold);
+        assert_eq!(graph.entities[1].color, DEFAULT_COLOR);
+        assert_eq!(graph.entities[1].style, Style::Dashed);
+        assert_eq!(graph.entities[1].mentions.len(), 2);
+    }
+
+    #[test]
+    fn repeated_declarations_must_agree() {
+        assert!(parse_graph("entity A: #3366ff\nA-B\nentity A: #3366ff bold").is_ok());
+
+        let input = "entity A: #3366ff\nA-B\nentity A: #ff0000";
+        let (graph, diagnostics) = parse_graph_recovering(input);
+
+        assert_eq!(graph.entities[0].color, 0x3366ff);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].line(), 3);
+        let ParseGraphError::ConflictingDeclaration { entity, first_declared } = &diagnostics[0].error else {
+            panic!("Expected a conflicting declaration");
+        };
+        assert_eq!(entity, "A");
+        assert_eq!(first_declared.start.line, 1);
+        assert!(parse_graph(input).is_err());
+    }
+
+    #[test]
+    fn entities_have_attributes() {
+        let input = "Order { id: Uuid, placedAt: DateTime }\nCustomer-places-Order\nOrder {\n    total: Money\n}";
+        let Ok(graph) = parse_graph(input) else { panic!("Parsing failed") };
+
+        assert_eq!(graph.entities[0].name, "Order");
+        assert_eq!(graph.entities[0].attributes.iter().map(|attribute| attribute.name.as_str()).collect::<Vec<_>>(), vec!["id", "placedAt", "total"]);
+        assert_eq!(graph.entities[0].attributes[2].span.slice(input), "total: Money");
+        assert_eq!(graph.entities[0].attributes[2].span.start.line, 4);
+        assert!(graph.entities[1].attributes.is_empty());
+    }
+
+    #[test]
+    fn attribute_names_are_unique() {
+        let input = "Order { id: Uuid }\nOrder { id: Int }";
+        let (graph, diagnostics) = parse_graph_recovering(input);
+
+        assert_eq!(graph.entities[0].attributes.len(), 1);
+        assert_eq!(graph.entities[0].attributes[0].ty.as_deref(), Some("Uuid"));
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].line(), 2);
+    }
+
+    #[test]
+    fn recovering_resumes_after_a_bad_attribute_block() {
+        let input = "Order {\n    id: 1.2.3\n    total: Money\n}\nCustomer-places-Order";
+        let (graph, diagnostics) = parse_graph_recovering(input);
+
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].line(), 2);
+        assert_eq!(graph.relations.len(), 1);
+    }
+
+    #[test]
+    fn strict_parsing_fails_on_first_error() {
+        assert!(parse_graph("Jack-Jill\nJill").is_err());
+        assert!(parse_graph("Jack-Jill\n\nJill-Pail\n").is_ok());
+    }
+
+    #[test]
+    fn entities_remember_every_mention() {
+        let input = "Jack-Jill\nJill-Pail\npin Jack: 1 2";
+        let Ok(graph) = parse_graph(input) else { panic!("Parsing failed") };
+
+        let jack = &graph.entities[0];
+        assert_eq!(jack.name, "Jack");
+        assert_eq!(jack.mentions.len(), 2);
+        assert!(jack.mentions.iter().all(|span| span.slice(input) == "Jack"));
+        assert_eq!(jack.mentions[1].start.line, 3);
+
+        let jill = &graph.entities[1];
+        assert_eq!(jill.mentions.iter().map(|span| span.start.line).collect::<Vec<_>>(), vec![1, 2]);
+    }
+
+    #[test]
+    fn the_last_layout_wins() {
+        let Ok(graph) = parse_graph("Jack-Jill") else { panic!("Parsing failed") };
+        assert_eq!(graph.layout, None);
+
+        let Ok(graph) = parse_graph("layout force directed
+Jack-Jill
+layout hierarchical") else { panic!("Parsing failed") };
+        assert_eq!(graph.layout.map(|layout| layout.value).as_deref(), Some("hierarchical"));
+        assert_eq!(graph.entities.len(), 2);
+    }
+
+    #[test]
+    fn config_statements_are_kept_in_order() {
+        let Ok(graph) = parse_graph("config iterations: 300\nJack-Jill\nconfig gravity: 1") else { panic!("Parsing failed") };
+
+        assert_eq!(graph.config.iter().map(|setting| (setting.name.as_str(), setting.value, setting.span.start.line)).collect::<Vec<_>>(), vec![("iterations", 300.0, 1), ("gravity", 1.0, 3)]);
+    }
+}
@@ -2,102 +2,270 @@ use std::{iter::Peekable, num::NonZeroUsize};
 
 use serde::{Deserialize, Serialize};
 
-use crate::domain_model::{graph::{Arrow, Multiplicity}, parser::tokenizer::{Keyword, Token}};
+use crate::{domain_model::{graph::{Arrow, Attribute, ColorHexValue, Multiplicity, Style, Visibility}, parser::{Diagnostic, ParseGraphError, tokenizer::{Keyword, ParseTokenError, Token}}, span::{Span, Spanned}}};
 
 
 pub enum Statement {
     NewRelation {
         text: Option<String>,
         weight: NonZeroUsize,
-        entity_1: String,
-        entity_2: String,
+        style: Style,
+        entity_1: Spanned<String>,
+        entity_2: Spanned<String>,
         arrow_1: Arrow,
         arrow_2: Arrow,
         mult_1: Multiplicity,
-        mult_2: Multiplicity
+        mult_2: Multiplicity,
+        span: Span
     },
     Pin {
-        entity: String,
+        entity: Spanned<String>,
         x: f32,
-        y: f32
+        y: f32,
+        span: Span
+    },
+    Entity {
+        entity: Spanned<String>,
+        color: Option<ColorHexValue>,
+        style: Option<Style>,
+        span: Span
+    },
+    Attributes {
+        entity: Spanned<String>,
+        attributes: Vec<Attribute>,
+        span: Span
+    },
+    Layout {
+        layout: Spanned<String>,
+        span: Span
+    },
+    Config {
+        setting: Spanned<String>,
+        value: f32,
+        span: Span
     }
 }
 
 impl Statement {
-    pub fn try_from_tokens(tokens: &[Token]) -> Result<Statement, ParseStatementError> {
-        // Assume everything is a new relation for now
+    pub fn span(&self) -> Span {
+        match self {
+            Statement::NewRelation { span, .. } | Statement::Pin { span, .. } | Statement::Entity { span, .. } | Statement::Attributes { span, .. } | Statement::Layout { span, .. } | Statement::Config { span, .. } => *span
+        }
+    }
+
+    pub fn try_from_tokens(tokens: &[Spanned<Token>]) -> Result<Statement, ParseStatementError> {
+        let span = match (tokens.first(), tokens.last()) {
+            (Some(first), Some(last)) => first.span.to(last.span),
+            _ => Span::default()
+        };
+
         let mut tokens = tokens.iter().peekable();
 
         match tokens.peek() {
-            Some(Token::Keyword(Keyword::Pin)) => {
+            Some(Spanned { value: Token::Keyword(Keyword::Pin), .. }) => {
                 tokens.next(); // Consume the "pin" keyword
-                Self::try_from_pin_tokens(&mut tokens)
+                Self::try_from_pin_tokens(&mut tokens, span)
+            },
+            Some(Spanned { value: Token::Keyword(Keyword::Entity), .. }) => {
+                tokens.next(); // Consume the "entity" keyword
+                Self::try_from_entity_tokens(&mut tokens, span)
+            },
+            Some(Spanned { value: Token::Keyword(Keyword::Layout), .. }) => {
+                tokens.next(); // Consume the "layout" keyword
+                Self::try_from_layout_tokens(&mut tokens, span)
             },
-            Some(Token::Identifier(entity_1)) => {
+            Some(Spanned { value: Token::Keyword(Keyword::Config), .. }) => {
+                tokens.next(); // Consume the "config" keyword
+                Self::try_from_config_tokens(&mut tokens, span)
+            },
+            Some(Spanned { value: Token::Identifier(entity_1), span: entity_1_span }) => {
                 tokens.next(); // Consume the first identifier
-                Self::try_from_relation_tokens(entity_1, &mut tokens)
+                let entity_1 = Spanned::new(entity_1.clone(), *entity_1_span);
+                if let Some(Token::LeftBrace) = peek_token(&mut tokens) {
+                    Self::try_from_attribute_tokens(entity_1, &mut tokens, span)
+                } else {
+                    Self::try_from_relation_tokens(entity_1, &mut tokens, span)
+                }
             },
-            token => Err(ParseStatementError::ExpectedIdentifier(token.cloned().cloned()))
+            token => Err(ParseStatementError::ExpectedIdentifier(unspanned(token.copied())))
         }
     }
 
-    pub fn try_from_pin_tokens<'a>(tokens: &mut Peekable<impl Iterator<Item=&'a Token>>) -> Result<Statement, ParseStatementError> {
+    pub fn try_from_pin_tokens<'a>(tokens: &mut Peekable<impl Iterator<Item=&'a Spanned<Token>>>, span: Span) -> Result<Statement, ParseStatementError> {
         let entity = match tokens.next() {
-            Some(Token::Identifier(entity)) => entity,
-            token => return Err(ParseStatementError::ExpectedIdentifier(token.cloned()))
+            Some(Spanned { value: Token::Identifier(entity), span }) => Spanned::new(entity.clone(), *span),
+            token => return Err(ParseStatementError::ExpectedIdentifier(unspanned(token)))
         };
 
-        match tokens.peek() {
+        match tokens.peek().map(|token| &token.value) {
             Some(Token::Colon) => {
                 tokens.next(); // Consume the colon
             },
-            token => return Err(ParseStatementError::ExpectedColon(token.cloned().cloned()))
+            token => return Err(ParseStatementError::ExpectedColon(token.cloned()))
         }
 
-        let x = match tokens.next() {
+        let x = match tokens.next().map(|token| &token.value) {
             Some(Token::NaturalNumber(x)) => *x as f32,
             Some(Token::Float(x)) => *x,
             token => return Err(ParseStatementError::ExpectedCoordinate(token.cloned()))
         };
 
-        let y = match tokens.next() {
+        let y = match tokens.next().map(|token| &token.value) {
             Some(Token::NaturalNumber(y)) => *y as f32,
             Some(Token::Float(y)) => *y,
             token => return Err(ParseStatementError::ExpectedCoordinate(token.cloned()))
         };
 
-        match tokens.next() {
+        match tokens.next().map(|token| &token.value) {
+            None | Some(Token::EndStatement) => {},
+            Some(token) => return Err(ParseStatementError::ExpectedEndOfStatement(token.clone()))
+        }
+
+        Ok(Statement::Pin { entity, x, y, span })
+    }
+
+    pub fn try_from_entity_tokens<'a>(tokens: &mut Peekable<impl Iterator<Item=&'a Spanned<Token>>>, span: Span) -> Result<Statement, ParseStatementError> {
+        let entity = match tokens.next() {
+            Some(Spanned { value: Token::Identifier(entity), span }) => Spanned::new(entity.clone(), *span),
+            token => return Err(ParseStatementError::ExpectedIdentifier(unspanned(token)))
+        };
+
+        let (mut color, mut style) = (None, None);
+
+        match tokens.next().map(|token| &token.value) {
+            None | Some(Token::EndStatement) => return Ok(Statement::Entity { entity, color, style, span }),
+            Some(Token::Colon) => {},
+            token => return Err(ParseStatementError::ExpectedColon(token.cloned()))
+        }
+
+        // The customizations can come in any order
+        loop {
+            match tokens.next().map(|token| &token.value) {
+                None | Some(Token::EndStatement) => break,
+                Some(token @ Token::Color(value)) => {
+                    if color.replace(*value).is_some() {
+                        return Err(ParseStatementError::RepeatedCustomization(token.clone()));
+                    }
+                },
+                Some(token @ Token::Identifier(name)) => {
+                    let Some(value) = try_parse_style(name) else {
+                        return Err(ParseStatementError::UnknownStyle(name.clone()));
+                    };
+                    if style.replace(value).is_some() {
+                        return Err(ParseStatementError::RepeatedCustomization(token.clone()));
+                    }
+                },
+                Some(token) => return Err(ParseStatementError::ExpectedCustomization(token.clone()))
+            }
+        }
+
+        if color.is_none() && style.is_none() {
+            return Err(ParseStatementError::ExpectedCustomization(Token::EndStatement));
+        }
+
+        Ok(Statement::Entity { entity, color, style, span })
+    }
+
+    pub fn try_from_layout_tokens<'a>(tokens: &mut Peekable<impl Iterator<Item=&'a Spanned<Token>>>, span: Span) -> Result<Statement, ParseStatementError> {
+        let layout = match tokens.next() {
+            Some(Spanned { value: Token::Identifier(name), span }) => Spanned::new(name.clone(), *span),
+            token => return Err(ParseStatementError::ExpectedIdentifier(unspanned(token)))
+        };
+
+        match tokens.next().map(|token| &token.value) {
+            None | Some(Token::EndStatement) => {},
+            Some(token) => return Err(ParseStatementError::ExpectedEndOfStatement(token.clone()))
+        }
+
+        Ok(Statement::Layout { layout, span })
+    }
+
+    pub fn try_from_config_tokens<'a>(tokens: &mut Peekable<impl Iterator<Item=&'a Spanned<Token>>>, span: Span) -> Result<Statement, ParseStatementError> {
+        let setting = match tokens.next() {
+            Some(Spanned { value: Token::Identifier(setting), span }) => Spanned::new(setting.clone(), *span),
+            token => return Err(ParseStatementError::ExpectedIdentifier(unspanned(token)))
+        };
+
+        match tokens.next().map(|token| &token.value) {
+            Some(Token::Colon) => {},
+            token => return Err(ParseStatementError::ExpectedColon(token.cloned()))
+        }
+
+        let value = match tokens.next().map(|token| &token.value) {
+            Some(Token::NaturalNumber(value)) => *value as f32,
+            Some(Token::Float(value)) => *value,
+            token => return Err(ParseStatementError::ExpectedNumber(token.cloned()))
+        };
+
+        match tokens.next().map(|token| &token.value) {
             None | Some(Token::EndStatement) => {},
             Some(token) => return Err(ParseStatementError::ExpectedEndOfStatement(token.clone()))
         }
 
-        Ok(Statement::Pin { entity: entity.clone(), x, y })
+        Ok(Statement::Config { setting, value, span })
     }
 
-    pub fn try_from_relation_tokens<'a>(entity_1: &'a str, tokens: &mut Peekable<impl Iterator<Item=&'a Token>>) -> Result<Statement, ParseStatementError> {
-        let entity_1 = entity_1.to_string();
+    pub fn try_from_attribute_tokens<'a>(entity: Spanned<String>, tokens: &mut Peekable<impl Iterator<Item=&'a Spanned<Token>>>, span: Span) -> Result<Statement, ParseStatementError> {
+        match tokens.next().map(|token| &token.value) {
+            Some(Token::LeftBrace) => {},
+            token => return Err(ParseStatementError::ExpectedEndOfStatement(token.cloned().unwrap_or(Token::EndStatement)))
+        }
+
+        let mut attributes = Vec::new();
+
+        loop {
+            match peek_token(tokens) {
+                Some(Token::Comma | Token::EndStatement) => {
+                    tokens.next(); // Attributes can be separated by commas or new lines
+                },
+                Some(Token::RightBrace) => {
+                    tokens.next(); // Consume the closing brace
+                    break;
+                },
+                Some(_) => attributes.push(parse_attribute(tokens)?),
+                None => return Err(ParseStatementError::UnclosedBrace)
+            }
+        }
+
+        match tokens.next().map(|token| &token.value) {
+            None | Some(Token::EndStatement) => {},
+            Some(token) => return Err(ParseStatementError::ExpectedEndOfStatement(token.clone()))
+        }
 
-        let arrow_1 = {
-            if let Some(Token::LeftArrow) = tokens.peek() {
+        Ok(Statement::Attributes { entity, attributes, span })
+    }
+
+    pub fn try_from_relation_tokens<'a>(entity_1: Spanned<String>, tokens: &mut Peekable<impl Iterator<Item=&'a Spanned<Token>>>, span: Span) -> Result<Statement, ParseStatementError> {
+        let arrow_1 = match peek_token(tokens) {
+            Some(Token::LeftArrow) => {
                 tokens.next(); // Consume the left arrow
                 Arrow::Arrow
-            } else if let Some(Token::RightArrow) = tokens.peek() {
-                return Err(ParseStatementError::ArrowInWrongDirection)
-            } else {
-                Arrow::None
-            }
+            },
+            Some(Token::LeftTriangle) => {
+                tokens.next(); // Consume the left triangle
+                Arrow::Inheritance
+            },
+            Some(Token::HollowDiamond | Token::FilledDiamond) => parse_diamond(tokens),
+            Some(Token::RightArrow | Token::RightTriangle) => return Err(ParseStatementError::ArrowInWrongDirection),
+            _ => Arrow::None
         };
 
-        let mult_1 = parse_multiplicity(tokens)?;
+        let mut mult_1 = parse_multiplicity(tokens, true)?;
+
+        let (mut weight_1, mut style_1) = parse_line(tokens)?;
 
-        let weight_1 = count_dashes(tokens);
+        // A relation needs a line, so "1.." without one after it is the number and a dashed line
+        if weight_1 == 0 && let Multiplicity::RangeFrom(range) = &mult_1 {
+            mult_1 = Multiplicity::Number(range.start);
+            (weight_1, style_1) = (1, Some(Style::Dashed));
+        }
 
         let text = match tokens.peek() {
-            Some(Token::Identifier(text)) => {
+            Some(Spanned { value: Token::Identifier(text), span }) => {
                 tokens.next(); // Consume the identifier
-                Ok(text)
+                Ok(Spanned::new(text.clone(), *span))
             },
-            token => Err(ParseStatementError::ExpectedIdentifier(token.cloned().cloned())),
+            token => Err(ParseStatementError::ExpectedIdentifier(unspanned(token.copied()))),
         };
 
         if tokens.peek().is_none() {
@@ -113,39 +281,44 @@ impl Statement {
                 Statement::NewRelation {
                     text: None,
                     weight,
-                    entity_1: entity_1.clone(),
-                    entity_2: text.clone(),
+                    style: style_1.unwrap_or(Style::Regular),
+                    entity_1,
+                    entity_2: text,
                     arrow_1,
                     arrow_2: Arrow::None,
                     mult_1,
-                    mult_2: Multiplicity::None
+                    mult_2: Multiplicity::None,
+                    span
                 }
             )
         }
 
-        let weight_2 = count_dashes(tokens);
+        let (weight_2, style_2) = parse_line(tokens)?;
 
-        let mult_2 = parse_multiplicity(tokens)?;
+        let mult_2 = parse_multiplicity(tokens, false)?;
 
-        let arrow_2 = {
-            if let Some(Token::RightArrow) = tokens.peek() {
+        let arrow_2 = match peek_token(tokens) {
+            Some(Token::RightArrow) => {
                 tokens.next(); // Consume the right arrow
                 Arrow::Arrow
-            } else if let Some(Token::LeftArrow) = tokens.peek() {
-                return Err(ParseStatementError::ArrowInWrongDirection)
-            } else {
-                Arrow::None
-            }
+            },
+            Some(Token::RightTriangle) => {
+                tokens.next(); // Consume the right triangle
+                Arrow::Inheritance
+            },
+            Some(Token::HollowDiamond | Token::FilledDiamond) => parse_diamond(tokens),
+            Some(Token::LeftArrow | Token::LeftTriangle) => return Err(ParseStatementError::ArrowInWrongDirection),
+            _ => Arrow::None
         };
 
         let entity_2 = match tokens.next() {
-            Some(Token::Identifier(entity_2)) => entity_2,
+            Some(Spanned { value: Token::Identifier(entity_2), span }) => Spanned::new(entity_2.clone(), *span),
             token => {
-                return Err(ParseStatementError::ExpectedIdentifier(token.cloned()))
+                return Err(ParseStatementError::ExpectedIdentifier(unspanned(token)))
             }
         };
 
-        match tokens.next() {
+        match tokens.next().map(|token| &token.value) {
             None | Some(Token::EndStatement) => {},
             Some(token) => {
                 return Err(ParseStatementError::ExpectedEndOfStatement(token.clone()))
@@ -154,53 +327,172 @@ impl Statement {
 
         let weight = usize::max(weight_1, weight_2);
 
+        let style = match (style_1, style_2) {
+            (Some(style_1), Some(style_2)) if style_1 != style_2 => return Err(ParseStatementError::MixedLineStyles),
+            (style_1, style_2) => style_1.or(style_2).unwrap_or(Style::Regular)
+        };
+
         let Some(weight) = NonZeroUsize::new(weight) else {
             return Err(ParseStatementError::NoWeightSpecified);
         };
 
         Ok(
             Statement::NewRelation {
-                text: text.ok().cloned(),
+                text: text.ok().map(|text| text.value),
                 weight,
-                entity_1: entity_1.clone(),
-                entity_2: entity_2.clone(),
+                style,
+                entity_1,
+                entity_2,
                 arrow_1,
                 arrow_2,
                 mult_1,
-                mult_2
+                mult_2,
+                span
             }
         )
     }
 }
 
-fn count_dashes<'a>(tokens: &mut Peekable<impl Iterator<Item=&'a Token>>) -> usize {
+fn peek_token<'a, 'b>(tokens: &'b mut Peekable<impl Iterator<Item=&'a Spanned<Token>>>) -> Option<&'a Token> {
+    tokens.peek().map(|token| &token.value)
+}
+
+fn unspanned(token: Option<&Spanned<Token>>) -> Option<Token> {
+    token.map(|token| token.value.clone())
+}
+
+/// Diamonds look the same from either side, so they can go on either end of a relation
+fn parse_diamond<'a>(tokens: &mut Peekable<impl Iterator<Item=&'a Spanned<Token>>>) -> Arrow {
+    match tokens.next().map(|token| &token.value) {
+        Some(Token::FilledDiamond) => Arrow::Composition,
+        _ => Arrow::Aggregation
+    }
+}
+
+fn try_parse_style(name: &str) -> Option<Style> {
+    match name {
+        "regular" => Some(Style::Regular),
+        "dotted" => Some(Style::Dotted),
+        "dashed" => Some(Style::Dashed),
+        "bold" => Some(Style::Bold),
+        _ => None
+    }
+}
+
+fn parse_attribute<'a>(tokens: &mut Peekable<impl Iterator<Item=&'a Spanned<Token>>>) -> Result<Attribute, ParseStatementError> {
+    let start = tokens.peek().map(|token| token.span).unwrap_or_default();
+
+    let visibility = match peek_token(tokens) {
+        Some(Token::Plus) => Some(Visibility::Public),
+        Some(Token::Dash) => Some(Visibility::Private),
+        Some(Token::Hash) => Some(Visibility::Protected),
+        Some(Token::Tilde) => Some(Visibility::Package),
+        _ => None
+    };
+    if visibility.is_some() {
+        tokens.next(); // Consume the visibility marker
+    }
+
+    let (name, mut end) = match tokens.next() {
+        Some(Spanned { value: Token::Identifier(name), span }) => (name.clone(), *span),
+        token => return Err(ParseStatementError::ExpectedIdentifier(unspanned(token)))
+    };
+
+    let ty = if let Some(Token::Colon) = peek_token(tokens) {
+        tokens.next(); // Consume the colon
+        match tokens.next() {
+            Some(Spanned { value: Token::Identifier(ty), span }) => {
+                end = *span;
+                Some(ty.clone())
+            },
+            token => return Err(ParseStatementError::ExpectedIdentifier(unspanned(token)))
+        }
+    } else {
+        None
+    };
+
+    let multiplicity = if let Some(Token::LeftBracket) = peek_token(tokens) {
+        tokens.next(); // Consume the opening bracket
+        let multiplicity = parse_bracketed_multiplicity(tokens)?;
+        match tokens.next() {
+            Some(Spanned { value: Token::RightBracket, span }) => end = *span,
+            token => return Err(ParseStatementError::ExpectedClosingBracket(unspanned(token)))
+        }
+        multiplicity
+    } else {
+        Multiplicity::None
+    };
+
+    match peek_token(tokens) {
+        None | Some(Token::Comma | Token::EndStatement | Token::RightBrace) => {},
+        Some(token) => return Err(ParseStatementError::ExpectedEndOfAttribute(token.clone()))
+    }
+
+    Ok(Attribute { name, ty, visibility, multiplicity, span: start.to(end) })
+}
+
+/// Parses the multiplicity between the brackets of an attribute, where a star can stand for "any number"
+fn parse_bracketed_multiplicity<'a>(tokens: &mut Peekable<impl Iterator<Item=&'a Spanned<Token>>>) -> Result<Multiplicity, ParseStatementError> {
+    if let Some(Token::Identifier(star)) = peek_token(tokens) && star == "*" {
+        tokens.next(); // Consume the star
+        return Ok(Multiplicity::RangeFrom(0..));
+    }
+
+    let multiplicity = parse_multiplicity(tokens, false)?;
+
+    if let Multiplicity::RangeFrom(..) = multiplicity && let Some(Token::Identifier(star)) = peek_token(tokens) && star == "*" {
+        tokens.next(); // Consume the star
+    }
+
+    if let Multiplicity::None = multiplicity {
+        return Err(ParseStatementError::ExpectedMultiplicity(peek_token(tokens).cloned()));
+    }
+
+    Ok(multiplicity)
+}
+
+/// Reads the segments of one side of a relation's line, returning how many there were and what they look like
+fn parse_line<'a>(tokens: &mut Peekable<impl Iterator<Item=&'a Spanned<Token>>>) -> Result<(usize, Option<Style>), ParseStatementError> {
     let mut weight = 0;
-    while let Some(Token::Dash) = tokens.peek() {
-        tokens.next(); // Consume the dash
+    let mut style = None;
+    loop {
+        let segment = match peek_token(tokens) {
+            Some(Token::Dash) => Style::Regular,
+            Some(Token::Range) => Style::Dashed,
+            Some(Token::Tilde) => Style::Dotted,
+            Some(Token::Equals) => Style::Bold,
+            _ => return Ok((weight, style))
+        };
+        if style.replace(segment).is_some_and(|style| style != segment) {
+            return Err(ParseStatementError::MixedLineStyles);
+        }
+        tokens.next(); // Consume the segment
         weight += 1;
     }
-    weight
 }
 
-fn parse_multiplicity<'a>(tokens: &mut Peekable<impl Iterator<Item=&'a Token>>) -> Result<Multiplicity, ParseStatementError> {
-    let num = match tokens.peek() {
-        Some(Token::NaturalNumber(num)) => num,
-        Some(token @ Token::Float(..)) => {
-            return Err(ParseStatementError::ExpectedNaturalNumber((*token).clone()));
+/// Reads a multiplicity, if there is one. If a dashed line can come after it, dots that
+/// don't touch the number (like "1 ..") are the line's rather than a range's.
+fn parse_multiplicity<'a>(tokens: &mut Peekable<impl Iterator<Item=&'a Spanned<Token>>>, line_follows: bool) -> Result<Multiplicity, ParseStatementError> {
+    let (num, num_span) = match tokens.peek() {
+        Some(Spanned { value: Token::NaturalNumber(num), span }) => (num, *span),
+        Some(Spanned { value: token @ Token::Float(..), .. }) => {
+            return Err(ParseStatementError::ExpectedNaturalNumber(token.clone()));
         }
         _ => return Ok(Multiplicity::None)
     };
     tokens.next(); // Consume the number
 
-    let Some(Token::Range) = tokens.peek() else {
-        return Ok(Multiplicity::Number(*num));
-    };
+    match tokens.peek() {
+        Some(Spanned { value: Token::Range, span }) if !line_follows || span.start == num_span.end => {},
+        _ => return Ok(Multiplicity::Number(*num))
+    }
     tokens.next(); // Consume the dot
 
-    let num_2 = match tokens.peek() {
+    let num_2 = match peek_token(tokens) {
         Some(Token::NaturalNumber(num_2)) => num_2,
         Some(token @ Token::Float(..)) => {
-            return Err(ParseStatementError::ExpectedNaturalNumber((*token).clone()));
+            return Err(ParseStatementError::ExpectedNaturalNumber(token.clone()));
         }
         _ => return Ok(Multiplicity::RangeFrom(*num..))
     };
@@ -217,6 +509,8 @@ pub enum ParseStatementError {
     ExpectedColon(Option<Token>),
     /// The parser expected a coordinate but got something else (or nothing at all)
     ExpectedCoordinate(Option<Token>),
+    /// The parser expected a number (e.g. a setting's value) but got something else (or nothing at all)
+    ExpectedNumber(Option<Token>),
     /// The parser encountered an arrow in the wrong direction
     ArrowInWrongDirection,
     /// Finished parsing before the end of the statement was reached
@@ -224,8 +518,24 @@ pub enum ParseStatementError {
     ExpectedEndOfStatement(Token),
     /// No dashes were supplied
     NoWeightSpecified,
+    /// A relation's line was made of more than one kind of segment (e.g. "-..")
+    MixedLineStyles,
     /// The parser expected a natural number but got a float instead
-    ExpectedNaturalNumber(Token)
+    ExpectedNaturalNumber(Token),
+    /// The parser expected a color or a style but got something else
+    ExpectedCustomization(Token),
+    /// The style isn't one of "regular", "dotted", "dashed" or "bold"
+    UnknownStyle(String),
+    /// The same customization (e.g. the color) was given twice in one declaration
+    RepeatedCustomization(Token),
+    /// An attribute block was opened with "{" but never closed with "}"
+    UnclosedBrace,
+    /// An attribute was followed by something other than a comma, a new line or a closing brace
+    ExpectedEndOfAttribute(Token),
+    /// The parser expected a multiplicity (e.g. "1", "0..5" or "*") but got something else (or nothing at all)
+    ExpectedMultiplicity(Option<Token>),
+    /// A multiplicity was opened with "[" but never closed with "]"
+    ExpectedClosingBracket(Option<Token>)
 }
 
 impl std::fmt::Display for ParseStatementError {
@@ -234,59 +544,114 @@ impl std::fmt::Display for ParseStatementError {
             ParseStatementError::ExpectedIdentifier(token) => write!(f, "Expected identifier, got {:?}", token),
             ParseStatementError::ExpectedColon(token) => write!(f, "Expected colon, got {:?}", token),
             ParseStatementError::ExpectedCoordinate(token) => write!(f, "Expected coordinate, got {:?}", token),
+            ParseStatementError::ExpectedNumber(token) => write!(f, "Expected number, got {:?}", token),
             ParseStatementError::ArrowInWrongDirection => write!(f, "Arrow in wrong direction"),
+            ParseStatementError::MixedLineStyles => write!(f, "A relation's line can't mix \"-\", \"..\", \"~\" and \"=\""),
             ParseStatementError::ExpectedEndOfStatement(token) => write!(f, "Expected end of statement, got {:?}", token),
             ParseStatementError::NoWeightSpecified => write!(f, "No weight specified. Please include at least one dash ('-') to indicate the weight of the relation."),
-            ParseStatementError::ExpectedNaturalNumber(token) => write!(f, "Expected natural number, got {:?}", token)
+            ParseStatementError::ExpectedNaturalNumber(Token::Float(x)) if *x < 0.0 => write!(f, "Expected natural number, got {} (after a colon or another number, a tilde in front of a number is a minus sign)", x),
+            ParseStatementError::ExpectedNaturalNumber(token) => write!(f, "Expected natural number, got {:?}", token),
+            ParseStatementError::ExpectedCustomization(token) => write!(f, "Expected a color (like \"#3366ff\") or a style, got {:?}", token),
+            ParseStatementError::UnknownStyle(name) => write!(f, "Unknown style \"{}\". Try \"regular\", \"dotted\", \"dashed\" or \"bold\".", name),
+            ParseStatementError::RepeatedCustomization(token) => write!(f, "{} was given more than once in the same declaration", token),
+            ParseStatementError::UnclosedBrace => write!(f, "Unclosed brace. Did you forget a '}}'?"),
+            ParseStatementError::ExpectedEndOfAttribute(token) => write!(f, "Expected a comma, new line or '}}' after the attribute, got {:?}", token),
+            ParseStatementError::ExpectedMultiplicity(token) => write!(f, "Expected multiplicity, got {:?}", token),
+            ParseStatementError::ExpectedClosingBracket(token) => write!(f, "Expected ']', got {:?}", token)
         }
     }
 }
 
-pub struct StatementParsingIterator<'a, I: Iterator<Item=&'a Token>> {
-    tokens: Peekable<I>,
+/// Groups tokens into statements. If a statement can't be parsed, the error is
+/// yielded and parsing picks up again at the start of the next statement.
+pub struct StatementParsingIterator<I: Iterator<Item=Result<Spanned<Token>, Spanned<ParseTokenError>>>> {
+    tokens: I,
+    /// How many braces deep the current statement is. New lines inside braces don't end the statement.
+    depth: usize,
 }
 
-impl<'a, I: Iterator<Item=&'a Token>> StatementParsingIterator<'a, I> {
-    pub fn new(tokens: I) -> StatementParsingIterator<'a, I> {
-        StatementParsingIterator { tokens: tokens.peekable() }
+impl<I: Iterator<Item=Result<Spanned<Token>, Spanned<ParseTokenError>>>> StatementParsingIterator<I> {
+    pub fn new(tokens: I) -> StatementParsingIterator<I> {
+        StatementParsingIterator { tokens, depth: 0 }
+    }
+
+    fn skip_statement(&mut self) {
+        for token in self.tokens.by_ref() {
+            match token {
+                Ok(Spanned { value: Token::LeftBrace, .. }) => self.depth += 1,
+                Ok(Spanned { value: Token::RightBrace, .. }) => self.depth = self.depth.saturating_sub(1),
+                Ok(Spanned { value: Token::EndStatement, .. }) if self.depth == 0 => break,
+                // The tokenizer gave up on the block, and ends the statement right after this
+                Err(Spanned { value: ParseTokenError::UnclosedBrace, .. }) => self.depth = 0,
+                _ => {}
+            }
+        }
+        self.depth = 0;
     }
 }
 
-impl<'a, I: Iterator<Item=&'a Token>> Iterator for StatementParsingIterator<'a, I> {
-    type Item = Result<Statement, ParseStatementError>;
+impl<I: Iterator<Item=Result<Spanned<Token>, Spanned<ParseTokenError>>>> Iterator for StatementParsingIterator<I> {
+    type Item = Result<Statement, Diagnostic>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        let mut statement_tokens = Vec::<Token>::new();
-
-        while matches!(self.tokens.peek(), Some(&Token::EndStatement)) {
-            self.tokens.next();
-        }
-
-        for token in self.tokens.by_ref() {
-            if let Token::EndStatement = token {
-                break;
+        let mut statement_tokens = Vec::<Spanned<Token>>::new();
+
+        loop {
+            match self.tokens.next() {
+                None => break,
+                Some(Ok(Spanned { value: Token::EndStatement, .. })) if self.depth == 0 => {
+                    if statement_tokens.is_empty() {
+                        continue; // Skip blank lines
+                    }
+                    break;
+                },
+                Some(Ok(Spanned { value: Token::Comment(..), .. })) => {} // Comments are trivia
+                Some(Ok(token)) => {
+                    match token.value {
+                        Token::LeftBrace => self.depth += 1,
+                        Token::RightBrace => self.depth = self.depth.saturating_sub(1),
+                        _ => {}
+                    }
+                    statement_tokens.push(token);
+                },
+                Some(Err(Spanned { value: ParseTokenError::UnclosedBrace, span })) => {
+                    // The tokenizer already ended the statement on the line the block broke off at
+                    self.depth = 0;
+                    let span = match (statement_tokens.first(), statement_tokens.last()) {
+                        (Some(first), Some(last)) => first.span.to(last.span),
+                        _ => span
+                    };
+                    return Some(Err(Diagnostic::new(ParseGraphError::StatementizationFailed(ParseStatementError::UnclosedBrace), span)));
+                },
+                Some(Err(error)) => {
+                    self.skip_statement();
+                    return Some(Err(Diagnostic::new(ParseGraphError::TokenizationFailed(error.value), error.span)));
+                }
             }
-            statement_tokens.push(token.clone());
         }
 
-        if statement_tokens.is_empty() {
-            None
-        } else {
-            Some(Statement::try_from_tokens(&statement_tokens))
-        }
+        self.depth = 0;
+
+        let (first, last) = (statement_tokens.first()?, statement_tokens.last()?);
+        let span = first.span.to(last.span);
+
+        Some(
+            Statement::try_from_tokens(&statement_tokens)
+                .map_err(|error| Diagnostic::new(ParseGraphError::StatementizationFailed(error), span))
+        )
     }
 }
 
 #[cfg(test)]
 pub mod test {
-    use crate::domain_model::parser::tokenizer::{ParseTokenError, TokenParsingIterator};
+    use crate::domain_model::parser::tokenizer::TokenParsingIterator;
 
     use super::*;
 
     fn parse_statement(input: &str) -> Result<Statement, ParseStatementError> {
         let tokens = {
             TokenParsingIterator::new(input)
-                .collect::<Result<Vec<Token>, ParseTokenError>>()
+                .collect::<Result<Vec<Spanned<Token>>, Spanned<ParseTokenError>>>()
                 .expect("Tokenization failed in test")
         };
 
@@ -341,4 +706,174 @@ pub mod test {
     fn wrong_way_reverse_directed_relation() {
         assert_parsing_failed!("a>-b");
     }
+
+    #[test]
+    fn inheritance_relation() {
+        assert_statement_parsing!("Manager-|>Employee");
+        assert_statement_parsing!("Employee<|-Manager");
+
+        let Ok(Statement::NewRelation { arrow_1, arrow_2, .. }) = parse_statement("Manager -|> Employee") else {
+            panic!("Expected a relation");
+        };
+        assert_eq!((arrow_1, arrow_2), (Arrow::None, Arrow::Inheritance));
+    }
+
+    #[test]
+    fn wrong_way_inheritance_relation() {
+        assert_parsing_failed!("Manager-<|Employee");
+        assert_parsing_failed!("Manager|>-Employee");
+    }
+
+    #[test]
+    fn aggregation_and_composition_relations() {
+        for (input, arrows) in [
+            ("Team<>-Player", (Arrow::Aggregation, Arrow::None)),
+            ("Order <#>-contains-1..> Line", (Arrow::Composition, Arrow::Arrow)),
+            ("Player-<>Team", (Arrow::None, Arrow::Aggregation)),
+            ("Line 1..-<#> Order", (Arrow::None, Arrow::Composition)),
+        ] {
+            let Ok(Statement::NewRelation { arrow_1, arrow_2, .. }) = parse_statement(input) else {
+                panic!("Expected a relation from {:?}", input);
+            };
+            assert_eq!((arrow_1, arrow_2), arrows, "{:?}", input);
+        }
+    }
+
+    #[test]
+    fn styled_relations() {
+        for (input, expected_style, expected_weight) in [
+            ("a-b", Style::Regular, 1),
+            ("Controller ..> Service", Style::Dashed, 1),
+            ("a ==> b", Style::Bold, 2),
+            ("a~uses~~b", Style::Dotted, 2),
+            ("Service ..uses..> Repository", Style::Dashed, 1),
+            ("Order <#>=contains=1..> Line", Style::Bold, 1),
+        ] {
+            let Ok(Statement::NewRelation { style, weight, .. }) = parse_statement(input) else {
+                panic!("Expected a relation from {:?}", input);
+            };
+            assert_eq!(style, expected_style, "{:?}", input);
+            assert_eq!(weight.get(), expected_weight, "{:?}", input);
+        }
+    }
+
+    #[test]
+    fn mixed_line_styles() {
+        assert_eq!(parse_statement("a-..b").err(), Some(ParseStatementError::MixedLineStyles));
+        assert_eq!(parse_statement("a-uses..>b").err(), Some(ParseStatementError::MixedLineStyles));
+    }
+
+    #[test]
+    fn numbers_before_dashed_lines() {
+        for (input, expected_mult_1, expected_weight) in [
+            ("A 1..>B", Multiplicity::Number(1), 1),
+            ("A 0..1..>B", Multiplicity::Range(0..1), 1),
+            ("A 1 ....>B", Multiplicity::Number(1), 2),
+            ("A 1....>B", Multiplicity::RangeFrom(1..), 1),
+            ("A 1..uses..>B", Multiplicity::Number(1), 1),
+        ] {
+            let Ok(Statement::NewRelation { mult_1, style, weight, .. }) = parse_statement(input) else {
+                panic!("Expected a relation from {:?}", input);
+            };
+            assert_eq!(mult_1, expected_mult_1, "{:?}", input);
+            assert_eq!(style, Style::Dashed, "{:?}", input);
+            assert_eq!(weight.get(), expected_weight, "{:?}", input);
+        }
+    }
+
+    #[test]
+    fn entity_declaration() {
+        assert_statement_parsing!("entity a");
+        assert_statement_parsing!("entity a: #3366ff");
+        assert_statement_parsing!("entity a: bold");
+        assert_statement_parsing!("entity a: #3366ff bold");
+        assert_statement_parsing!("entity a: bold #36f");
+    }
+
+    #[test]
+    fn entity_declaration_customization() {
+        let Ok(Statement::Entity { entity, color, style, .. }) = parse_statement("entity Customer Account: dashed #3366ff") else {
+            panic!("Expected an entity declaration");
+        };
+
+        assert_eq!(entity.value, "Customer Account");
+        assert_eq!(color, Some(0x3366ff));
+        assert_eq!(style, Some(Style::Dashed));
+    }
+
+    #[test]
+    fn attribute_block() {
+        assert_statement_parsing!("Order {}");
+        assert_statement_parsing!("Order { id }");
+        assert_statement_parsing!("Order { id: Uuid, placedAt: DateTime, total: Money }");
+        assert_statement_parsing!("Order {\n    +id: Uuid\n    -lines: Line [1..*],\n}");
+    }
+
+    #[test]
+    fn attribute_markers() {
+        let Ok(Statement::Attributes { entity, attributes, .. }) = parse_statement("Order { +id: Uuid [1], -lines: Line [*], #total, ~notes [0..5] }") else {
+            panic!("Expected an attribute block");
+        };
+
+        assert_eq!(entity.value, "Order");
+        assert_eq!(attributes.len(), 4);
+        assert_eq!(attributes[0].name, "id");
+        assert_eq!(attributes[0].ty.as_deref(), Some("Uuid"));
+        assert_eq!(attributes[0].visibility, Some(Visibility::Public));
+        assert_eq!(attributes[0].multiplicity, Multiplicity::Number(1));
+        assert_eq!(attributes[1].visibility, Some(Visibility::Private));
+        assert_eq!(attributes[1].multiplicity, Multiplicity::RangeFrom(0..));
+        assert_eq!(attributes[2].ty, None);
+        assert_eq!(attributes[2].visibility, Some(Visibility::Protected));
+        assert_eq!(attributes[3].visibility, Some(Visibility::Package));
+        assert_eq!(attributes[3].multiplicity, Multiplicity::Range(0..5));
+    }
+
+    #[test]
+    fn bad_attribute_block() {
+        assert_parsing_failed!("Order { id: Uuid");
+        assert_parsing_failed!("Order { id: }");
+        assert_parsing_failed!("Order { id: Uuid > }");
+        assert_parsing_failed!("Order { id [] }");
+        assert_parsing_failed!("Order { id [1 }");
+        assert_parsing_failed!("Order { id } extra");
+    }
+
+    #[test]
+    fn bad_entity_declaration() {
+        assert_parsing_failed!("entity a:");
+        assert_parsing_failed!("entity a: sparkly");
+        assert_parsing_failed!("entity a: bold dotted");
+        assert_parsing_failed!("entity a: #3366ff #000000");
+        assert_parsing_failed!("entity a #3366ff");
+    }
+
+    #[test]
+    fn layout_statement() {
+        let Ok(Statement::Layout { layout, .. }) = parse_statement("layout hierarchical") else {
+            panic!("Expected a layout statement");
+        };
+        assert_eq!(layout.value, "hierarchical");
+
+        assert_statement_parsing!("layout force directed");
+        // Placing decides which layouts there are
+        assert_statement_parsing!("layout sideways");
+        assert_parsing_failed!("layout");
+        assert_parsing_failed!("layout hierarchical: bold");
+    }
+
+    #[test]
+    fn config_statement() {
+        let Ok(Statement::Config { setting, value, .. }) = parse_statement("config delta time: 0.05") else {
+            panic!("Expected a config statement");
+        };
+        assert_eq!(setting.value, "delta time");
+        assert_eq!(value, 0.05);
+
+        assert_statement_parsing!("config iterations: 500");
+        assert_statement_parsing!("config hierarchy gap: ~1");
+        assert_parsing_failed!("config iterations");
+        assert_parsing_failed!("config iterations: many");
+        assert_parsing_failed!("config: 5");
+    }
 }
\ No newline at end of file
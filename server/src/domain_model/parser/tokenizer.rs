@@ -2,23 +2,51 @@ use std::{fmt, str::Chars};
 
 use serde::{Deserialize, Serialize}; 
 
+use crate::domain_model::{graph::ColorHexValue, span::{Position, Span, Spanned}};
+
 #[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
 pub enum Token {
     Keyword(Keyword),
     Identifier(String),
     LeftArrow,
     RightArrow,
+    /// "<|", the head of an inheritance relation pointing left
+    LeftTriangle,
+    /// "|>", the head of an inheritance relation pointing right
+    RightTriangle,
+    /// "<>", the end of an aggregation relation
+    HollowDiamond,
+    /// "<#>", the end of a composition relation
+    FilledDiamond,
     Dash,
+    /// "=", a segment of a bold relation line
+    Equals,
     NaturalNumber(usize),
     Float(f32),
+    /// A hex color code, like "#3366ff"
+    Color(ColorHexValue),
     Range,
     Colon,
+    LeftBrace,
+    RightBrace,
+    Comma,
+    LeftBracket,
+    RightBracket,
+    Plus,
+    Hash,
+    /// "~", a segment of a dotted relation line, or the package visibility marker inside an attribute block
+    Tilde,
+    /// A line comment, starting with "//". It doesn't affect the meaning of the input.
+    Comment(String),
     EndStatement
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Keyword {
-    Pin
+    Pin,
+    Entity,
+    Layout,
+    Config
 }
 
 impl fmt::Display for Token {
@@ -28,11 +56,26 @@ impl fmt::Display for Token {
             Token::Keyword(keyword) => write!(f, "{}", keyword),
             Token::LeftArrow => write!(f, "<"),
             Token::RightArrow => write!(f, ">"),
+            Token::LeftTriangle => write!(f, "<|"),
+            Token::RightTriangle => write!(f, "|>"),
+            Token::HollowDiamond => write!(f, "<>"),
+            Token::FilledDiamond => write!(f, "<#>"),
             Token::Dash => write!(f, "-"),
+            Token::Equals => write!(f, "="),
             Token::NaturalNumber(n) => write!(f, "{}", n),
             Token::Float(x) => write!(f, "{}", x),
+            Token::Color(color) => write!(f, "#{:06x}", color),
             Token::Range => write!(f, ".."),
             Token::Colon => write!(f, ":"),
+            Token::LeftBrace => write!(f, "{{"),
+            Token::RightBrace => write!(f, "}}"),
+            Token::Comma => write!(f, ","),
+            Token::LeftBracket => write!(f, "["),
+            Token::RightBracket => write!(f, "]"),
+            Token::Plus => write!(f, "+"),
+            Token::Hash => write!(f, "#"),
+            Token::Tilde => write!(f, "~"),
+            Token::Comment(text) => write!(f, "//{}", text),
             Token::EndStatement => writeln!(f),
         }
     }
@@ -41,18 +84,88 @@ impl fmt::Display for Token {
 impl fmt::Display for Keyword {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            Keyword::Pin => write!(f, "pin")
+            Keyword::Pin => write!(f, "pin"),
+            Keyword::Entity => write!(f, "entity"),
+            Keyword::Layout => write!(f, "layout"),
+            Keyword::Config => write!(f, "config")
         }
     }
 }
 
 pub struct TokenParsingIterator<'a> {
-    chars: itertools::structs::PeekNth<Chars<'a>>,
+    input: &'a str,
+    chars: SourceChars<'a>,
+    /// How many braces deep we are. Inside braces, some symbols take on a different meaning.
+    depth: usize,
+    /// Whether this is only looking at the next line, to see if a block goes on there
+    looking_ahead: bool,
+    /// The new line to end the statement with, after giving up on an unclosed block
+    pending_end: Option<Span>,
+    /// Whether a tilde in front of a number is a minus sign here. Numbers can only be
+    /// negative after a colon or another number (like in "pin A: ~1 ~2"), so anywhere
+    /// else (like in "A~~1>B") the tilde is part of a dotted line.
+    tilde_is_minus: bool,
+    /// Whether the next token starts a statement, which is the only place a keyword can be
+    at_statement_start: bool,
+    /// Whether this is in an entity declaration, and whether it's past its colon, where
+    /// colors (like "#36f") can be written. Anywhere else a "#" is part of a name.
+    declaring_entity: bool,
+    customizing: bool
 }
 
 impl TokenParsingIterator<'_> {
     pub fn new(input: &str) -> TokenParsingIterator<'_> {
-        TokenParsingIterator { chars: itertools::peek_nth(input.chars()) }
+        TokenParsingIterator { looking_ahead: false, at_statement_start: true, ..TokenParsingIterator::look_ahead(input, 0) }
+    }
+
+    /// Tokenizes the input only to see what comes next, without recognizing keywords
+    fn look_ahead(input: &str, depth: usize) -> TokenParsingIterator<'_> {
+        TokenParsingIterator {
+            input,
+            chars: SourceChars::new(input),
+            depth,
+            looking_ahead: true,
+            pending_end: None,
+            tilde_is_minus: false,
+            at_statement_start: false,
+            declaring_entity: false,
+            customizing: false
+        }
+    }
+}
+
+/// Iterates over the characters of the input, keeping track of where in the input it is
+pub struct SourceChars<'a> {
+    chars: itertools::structs::PeekNth<Chars<'a>>,
+    position: Position,
+}
+
+impl SourceChars<'_> {
+    pub fn new(input: &str) -> SourceChars<'_> {
+        SourceChars { chars: itertools::peek_nth(input.chars()), position: Position::start() }
+    }
+
+    pub fn peek(&mut self) -> Option<&char> {
+        self.chars.peek()
+    }
+
+    pub fn peek_nth(&mut self, n: usize) -> Option<&char> {
+        self.chars.peek_nth(n)
+    }
+
+    /// The position of the next character
+    pub fn position(&self) -> Position {
+        self.position
+    }
+}
+
+impl Iterator for SourceChars<'_> {
+    type Item = char;
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.chars.next()?;
+        self.position.advance(c);
+        Some(c)
     }
 }
 
@@ -67,7 +180,9 @@ pub enum ParseTokenError {
     /// The user used a tilde to indicate a negative number, but did not include a number after the tilde
     NoNumberAfterTilde,
     /// The user used a backslash to escape a character, but did not include a character after the backslash
-    NoCharacterAfterEscape
+    NoCharacterAfterEscape,
+    /// The user opened a brace, but a line after it can't be inside braces. Tokenizing picks up on that line, outside the braces.
+    UnclosedBrace
 }
 
 impl std::fmt::Display for ParseTokenError {
@@ -78,18 +193,62 @@ impl std::fmt::Display for ParseTokenError {
             ParseTokenError::UnterminatedIdentifier => write!(f, "Unterminated identifier. Did you forget to close it?"),
             ParseTokenError::NoNumberAfterTilde => write!(f, "No number after tilde. Did you forget to include a number after the tilde?"),
             ParseTokenError::NoCharacterAfterEscape => write!(f, "No character after escape. Did you forget to include a character after the backslash?"),
+            ParseTokenError::UnclosedBrace => write!(f, "Unclosed brace. Did you forget a '}}'?"),
         }
     }
 }
 
 impl Iterator for TokenParsingIterator<'_> {
-    type Item = Result<Token, ParseTokenError>;
+    type Item = Result<Spanned<Token>, Spanned<ParseTokenError>>;
 
     fn next(&mut self) -> Option<Self::Item> {
+        if let Some(span) = self.pending_end.take() {
+            self.end_statement();
+            return Some(Ok(Spanned::new(Token::EndStatement, span)));
+        }
+
         while self.chars.peek().is_some_and(|c| *c == ' ' || *c == '\r') {
             self.chars.next(); // Skip leading whitespace
         }
 
+        let start = self.chars.position();
+        let token = self.next_token()?;
+        let mut end = self.chars.position();
+
+        if let Ok(Token::Identifier(_)) = token {
+            // Bare identifiers swallow trailing whitespace, which isn't part of the name
+            let text = &self.input[start.offset..end.offset];
+            let trimmed = text.trim_end();
+            end.column -= text[trimmed.len()..].chars().count();
+            end.offset = start.offset + trimmed.len();
+        }
+
+        let span = Span::new(start, end);
+        self.tilde_is_minus = matches!(token, Ok(Token::Colon | Token::NaturalNumber(_) | Token::Float(_)));
+        self.at_statement_start = false;
+        match token {
+            Ok(Token::EndStatement) => self.end_statement(),
+            Ok(Token::Keyword(Keyword::Entity)) => self.declaring_entity = true,
+            Ok(Token::Colon) if self.declaring_entity => self.customizing = true,
+            _ => {}
+        }
+        if let Err(ParseTokenError::UnclosedBrace) = token {
+            self.pending_end = Some(span);
+        }
+        Some(token.map(|token| Spanned::new(token, span)).map_err(|error| Spanned::new(error, span)))
+    }
+}
+
+impl TokenParsingIterator<'_> {
+    fn end_statement(&mut self) {
+        (self.at_statement_start, self.declaring_entity, self.customizing) = (true, false, false);
+    }
+
+    fn next_token(&mut self) -> Option<Result<Token, ParseTokenError>> {
+        if self.at_statement_start && let Some(keyword) = self.declaration_keyword() {
+            return Some(Ok(Token::Keyword(keyword)));
+        }
+
         match self.chars.next()? {
             // Dots and Ranges
             '.' => {
@@ -101,19 +260,113 @@ impl Iterator for TokenParsingIterator<'_> {
             }
             // Symbols
             '-' => Some(Ok(Token::Dash)),
+            '=' => Some(Ok(Token::Equals)),
+            // A tilde in front of a number is a minus sign instead, where a number can be negative
+            '~' if !(self.tilde_is_minus && self.chars.peek().is_some_and(|c| c.is_ascii_digit() || *c == '.')) => Some(Ok(Token::Tilde)),
+            '<' if self.chars.peek() == Some(&'>') => {
+                self.chars.next(); // Consume the closing side of the diamond
+                Some(Ok(Token::HollowDiamond))
+            },
+            '<' if self.chars.peek() == Some(&'#') && self.chars.peek_nth(1) == Some(&'>') => {
+                self.chars.next(); // Consume the fill
+                self.chars.next(); // Consume the closing side of the diamond
+                Some(Ok(Token::FilledDiamond))
+            },
+            '<' if self.chars.peek() == Some(&'|') => {
+                self.chars.next(); // Consume the bar
+                Some(Ok(Token::LeftTriangle))
+            },
+            '|' if self.chars.peek() == Some(&'>') => {
+                self.chars.next(); // Consume the arrow
+                Some(Ok(Token::RightTriangle))
+            },
             '<' => Some(Ok(Token::LeftArrow)),
             '>' => Some(Ok(Token::RightArrow)),
             ':' => Some(Ok(Token::Colon)),
+            '\n' if self.depth > 0 && !self.looking_ahead && !self.block_goes_on() => {
+                self.depth = 0;
+                Some(Err(ParseTokenError::UnclosedBrace))
+            },
             '\n' => Some(Ok(Token::EndStatement)),
+            '{' => {
+                self.depth += 1;
+                Some(Ok(Token::LeftBrace))
+            },
+            '}' => {
+                self.depth = self.depth.saturating_sub(1);
+                Some(Ok(Token::RightBrace))
+            },
+            // Symbols that only have a meaning inside of braces
+            ',' if self.depth > 0 => Some(Ok(Token::Comma)),
+            '[' if self.depth > 0 => Some(Ok(Token::LeftBracket)),
+            ']' if self.depth > 0 => Some(Ok(Token::RightBracket)),
+            '+' if self.depth > 0 => Some(Ok(Token::Plus)),
+            '#' if self.depth > 0 => Some(Ok(Token::Hash)),
+            // Comments
+            '/' if self.chars.peek() == Some(&'/') => Some(Ok(parse_comment_token(&mut self.chars))),
+            // Colors
+            '#' if self.customizing && color_length(&mut self.chars, 0).is_some() => Some(Ok(parse_color_token(&mut self.chars))),
             // Numbers
             symbol @ ('~' | '0'..='9') => parse_number_token(symbol, &mut self.chars),
             // Identifiers
-            symbol => parse_identifier_token(symbol, &mut self.chars)
+            symbol => parse_identifier_token(symbol, &mut self.chars, self.depth > 0, self.customizing)
         }
     }
 }
 
-fn parse_identifier_token(first_char: char, chars: &mut itertools::structs::PeekNth<Chars>) -> Option<Result<Token, ParseTokenError>> {
+impl TokenParsingIterator<'_> {
+    /// Consumes the keyword the statement starts with, if it's followed by the syntax of
+    /// its declaration (like "entity Order: bold" or "layout hierarchical"). Otherwise the
+    /// word is only the start of a name, like in "entity store-has-Item".
+    fn declaration_keyword(&mut self) -> Option<Keyword> {
+        let rest = &self.input[self.chars.position().offset..];
+        let (word, after) = rest.split_once(' ')?;
+        let keyword = try_parse_keyword(word)?;
+
+        let mut tokens = TokenParsingIterator::look_ahead(after, 0)
+            .map(|token| token.map(|token| token.value))
+            .filter(|token| !matches!(token, Ok(Token::Comment(_))));
+        let declares = match (keyword, tokens.next(), tokens.next()) {
+            // An entity can be declared without customizing it
+            (Keyword::Layout | Keyword::Entity, Some(Ok(Token::Identifier(_))), None | Some(Ok(Token::EndStatement))) => true,
+            (Keyword::Pin | Keyword::Entity | Keyword::Config, Some(Ok(Token::Identifier(_))), Some(Ok(Token::Colon))) => true,
+            _ => false
+        };
+        if !declares {
+            return None;
+        }
+
+        for _ in word.chars() {
+            self.chars.next(); // Consume the keyword
+        }
+        Some(keyword)
+    }
+
+    /// Whether the line starting at the current position could be inside the open braces.
+    /// Anything but attributes (like a relation) means the braces were never closed.
+    fn block_goes_on(&self) -> bool {
+        let rest = &self.input[self.chars.position().offset..];
+        let line = TokenParsingIterator::look_ahead(rest, self.depth);
+
+        let (mut starts_attribute, mut in_brackets) = (true, false);
+        for token in line {
+            match token.map(|token| token.value) {
+                Ok(Token::EndStatement | Token::RightBrace) | Err(_) => return true,
+                Ok(Token::Comment(_)) => {},
+                Ok(Token::Comma) => starts_attribute = true,
+                Ok(Token::Plus | Token::Dash | Token::Hash | Token::Tilde) if starts_attribute => starts_attribute = false,
+                Ok(Token::Identifier(_) | Token::Colon) => starts_attribute = false,
+                Ok(Token::LeftBracket) => in_brackets = true,
+                Ok(Token::RightBracket) => in_brackets = false,
+                Ok(Token::NaturalNumber(_) | Token::Range) if in_brackets => {},
+                Ok(_) => return false
+            }
+        }
+        true
+    }
+}
+
+fn parse_identifier_token(first_char: char, chars: &mut SourceChars, in_block: bool, customizing: bool) -> Option<Result<Token, ParseTokenError>> {
     #[derive(Clone, Copy)]
     enum IdentifierKind {
         DoubleQuote,
@@ -144,12 +397,15 @@ fn parse_identifier_token(first_char: char, chars: &mut itertools::structs::Peek
     };
 
     loop {
-        if 
-            let Bare = start &&
-            let None | Some(' ' | '-' | '.' | '<' | '>' | ':' | '\n' | '0'..='9') = chars.peek() &&
-            let Some(keyword) = try_parse_keyword(&buffer) 
-        {
-            return Some(Ok(Token::Keyword(keyword)));
+        let at_boundary = {
+            at_comment(chars) ||
+            ends_bare_identifier(chars.peek(), in_block) ||
+            (chars.peek() == Some(&'|') && chars.peek_nth(1) == Some(&'>')) ||
+            (customizing && buffer.ends_with(' ') && chars.peek() == Some(&'#') && color_length(chars, 1).is_some())
+        };
+
+        if let Bare = start && at_boundary {
+            return Some(Ok(Token::Identifier(buffer.trim().to_string())));
         }
 
         match (start, chars.peek()) {
@@ -157,12 +413,10 @@ fn parse_identifier_token(first_char: char, chars: &mut itertools::structs::Peek
                 chars.next(); // Consume the closing quote
                 return Some(Ok(Token::Identifier(buffer.trim().to_string())));
             },
-            (DoubleQuote | Apostrophe | Tick, None) => {
+            // A quote can't go past the end of its line, so the lines after it still parse
+            (DoubleQuote | Apostrophe | Tick, None | Some('\n')) => {
                 return Some(Err(ParseTokenError::UnterminatedIdentifier));
             }
-            (Bare, None | Some('-' | '.' | '<' | '>' | ':' | '\n' | '0'..='9')) => {
-                return Some(Ok(Token::Identifier(buffer.trim().to_string())));
-            },
             (_, Some('\\')) => {
                 chars.next(); // Consume the backslash
                 if chars.peek().is_none() {
@@ -176,7 +430,65 @@ fn parse_identifier_token(first_char: char, chars: &mut itertools::structs::Peek
     }
 }
 
-fn parse_number_token(first_char: char, chars: &mut itertools::structs::PeekNth<Chars>) -> Option<Result<Token, ParseTokenError>> {
+/// Whether a bare identifier stops before the given character
+fn ends_bare_identifier(next_char: Option<&char>, in_block: bool) -> bool {
+    match next_char {
+        None | Some('-' | '=' | '~' | '.' | '<' | '>' | ':' | '\n' | '{' | '}' | '0'..='9') => true,
+        Some(',' | '[' | ']') => in_block,
+        _ => false
+    }
+}
+
+/// Whether the next two characters start a line comment
+fn at_comment(chars: &mut SourceChars) -> bool {
+    chars.peek() == Some(&'/') && chars.peek_nth(1) == Some(&'/')
+}
+
+/// Parses the rest of a comment, after the first slash has already been consumed
+fn parse_comment_token(chars: &mut SourceChars) -> Token {
+    chars.next(); // Consume the second slash
+
+    let mut buffer = String::new();
+    while let Some(c) = chars.peek() && *c != '\n' {
+        buffer.push(*c);
+        chars.next();
+    }
+
+    Token::Comment(buffer.trim_end().to_string())
+}
+
+/// If the hex digits of a color code start `skip` characters ahead, returns how many digits there are
+fn color_length(chars: &mut SourceChars, skip: usize) -> Option<usize> {
+    let mut length = 0;
+    while chars.peek_nth(skip + length).is_some_and(char::is_ascii_hexdigit) {
+        length += 1;
+    }
+
+    let ends_word = !chars.peek_nth(skip + length).is_some_and(|c| c.is_alphanumeric() || *c == '_');
+
+    if ends_word && (length == 3 || length == 6) {
+        Some(length)
+    } else {
+        None
+    }
+}
+
+/// Parses the digits of a color code, after the '#' has already been consumed
+fn parse_color_token(chars: &mut SourceChars) -> Token {
+    let length = color_length(chars, 0).unwrap_or(0);
+    let digits: String = chars.take(length).collect();
+
+    let color = if length == 3 {
+        // Shorthand, where each digit is doubled (e.g. "#36f" is "#3366ff")
+        digits.chars().fold(0, |color, digit| (color << 8) | (digit.to_digit(16).unwrap() * 0x11))
+    } else {
+        ColorHexValue::from_str_radix(&digits, 16).unwrap()
+    };
+
+    Token::Color(color)
+}
+
+fn parse_number_token(first_char: char, chars: &mut SourceChars) -> Option<Result<Token, ParseTokenError>> {
     let mut buffer = String::new();
     buffer.push(first_char);
 
@@ -222,17 +534,49 @@ fn contains_decimal_point(buffer: &str) -> bool {
 fn try_parse_keyword(buffer: &str) -> Option<Keyword> {
     match buffer.trim() {
         "pin" => Some(Keyword::Pin),
+        "entity" => Some(Keyword::Entity),
+        "layout" => Some(Keyword::Layout),
+        "config" => Some(Keyword::Config),
         _ => None
     }
 }
 
+/// Writes the name so that it tokenizes back into the same identifier, quoting it if it has to
+pub fn escape_identifier(name: &str) -> String {
+    let is_bare = {
+        !name.is_empty() &&
+        name.trim() == name &&
+        try_parse_keyword(name.split(' ').next().unwrap_or_default()).is_none() &&
+        !name.chars().any(|c| c.is_ascii_digit() || "-=~.<>:{},[]|/#+\"'`\\\n".contains(c))
+    };
+
+    if is_bare {
+        return name.to_string();
+    }
+
+    let mut quoted = String::with_capacity(name.len() + 2);
+    quoted.push('"');
+    for c in name.chars() {
+        if c == '"' || c == '\\' {
+            quoted.push('\\'); // The next character is taken literally
+        }
+        quoted.push(c);
+    }
+    quoted.push('"');
+    quoted
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::domain_model::parser::tokenizer::ParseTokenError;
-    use super::{Token, TokenParsingIterator};
+    use crate::domain_model::{parser::tokenizer::ParseTokenError, span::{Position, Span}};
+    use super::{escape_identifier, Keyword, Token, TokenParsingIterator};
 
     fn tokenize(input: &str) -> Result<Vec<Token>, ParseTokenError> {
-        TokenParsingIterator::new(input).collect::<Result<Vec<Token>, _>>()
+        TokenParsingIterator::new(input).map(|token| token.map(|token| token.value).map_err(|error| error.value)).collect::<Result<Vec<Token>, _>>()
+    }
+
+    fn tokenize_spans(input: &str) -> Vec<Span> {
+        TokenParsingIterator::new(input).map(|token| token.map_err(|error| error.value).expect("Tokenization failed in test").span).collect()
     }
 
     #[test]
@@ -321,6 +665,254 @@ mod tests {
 
         assert_eq!(tokenize(input_3), Ok(output_3));
     }
+
+    #[test]
+    fn test_tokenizer_spans() {
+        let input = "peter - knows ->wendy\n\"hook\"";
+
+        let spans = tokenize_spans(input);
+
+        assert_eq!(spans[0], Span::new(Position { offset: 0, line: 1, column: 1 }, Position { offset: 5, line: 1, column: 6 }));
+        assert_eq!(spans[1], Span::new(Position { offset: 6, line: 1, column: 7 }, Position { offset: 7, line: 1, column: 8 }));
+        assert_eq!(spans[2].slice(input), "knows");
+        assert_eq!(spans[4].slice(input), ">");
+        assert_eq!(spans[5].slice(input), "wendy");
+        assert_eq!(spans[7], Span::new(Position { offset: 22, line: 2, column: 1 }, Position { offset: 28, line: 2, column: 7 }));
+    }
+
+    #[test]
+    fn test_tokenizer_spans_count_characters_for_columns() {
+        let input = "café-b";
+
+        let spans = tokenize_spans(input);
+
+        assert_eq!(spans[0].slice(input), "café");
+        assert_eq!(spans[1].start, Position { offset: 5, line: 1, column: 5 });
+    }
+
+    #[test]
+    fn test_tokenizer_comments() {
+        let input = "// Who knows who\npeter-knows-wendy // since the nursery\n\"//not a comment\"";
+
+        let output: Vec<Token> = vec![
+            Token::Comment(" Who knows who".to_string()),
+            Token::EndStatement,
+            Token::Identifier("peter".to_string()),
+            Token::Dash,
+            Token::Identifier("knows".to_string()),
+            Token::Dash,
+            Token::Identifier("wendy".to_string()),
+            Token::Comment(" since the nursery".to_string()),
+            Token::EndStatement,
+            Token::Identifier("//not a comment".to_string()),
+        ];
+
+        assert_eq!(tokenize(input), Ok(output));
+    }
+
+    #[test]
+    fn test_tokenizer_single_slash_is_part_of_identifier() {
+        let input = "input/output";
+
+        let output: Vec<Token> = vec![Token::Identifier("input/output".to_string())];
+
+        assert_eq!(tokenize(input), Ok(output));
+    }
+
+    #[test]
+    fn test_tokenizer_colors() {
+        let input = "entity Customer: #3366ff bold #36F";
+
+        let output: Vec<Token> = vec![
+            Token::Keyword(Keyword::Entity),
+            Token::Identifier("Customer".to_string()),
+            Token::Colon,
+            Token::Color(0x3366ff),
+            Token::Identifier("bold".to_string()),
+            Token::Color(0x3366ff),
+        ];
+
+        assert_eq!(tokenize(input), Ok(output));
+    }
+
+    #[test]
+    fn test_tokenizer_hash_without_color_is_part_of_identifier() {
+        let input = "C#-#deadbeef";
+
+        let output: Vec<Token> = vec![
+            Token::Identifier("C#".to_string()),
+            Token::Dash,
+            Token::Identifier("#deadbeef".to_string()),
+        ];
+
+        assert_eq!(tokenize(input), Ok(output));
+    }
+
+    #[test]
+    fn test_tokenizer_colors_only_in_entity_declarations() {
+        let input = "Issue #abc-Fix #123";
+
+        let output: Vec<Token> = vec![
+            Token::Identifier("Issue #abc".to_string()),
+            Token::Dash,
+            Token::Identifier("Fix #".to_string()),
+            Token::NaturalNumber(123),
+        ];
+
+        assert_eq!(tokenize(input), Ok(output));
+    }
+
+    #[test]
+    fn test_tokenizer_keywords_only_start_declarations() {
+        let input = "entity store-has-Item\nconfig value-Setting\nlayout engine->Car\nJack-pin cushion";
+
+        let output: Vec<Token> = vec![
+            Token::Identifier("entity store".to_string()),
+            Token::Dash,
+            Token::Identifier("has".to_string()),
+            Token::Dash,
+            Token::Identifier("Item".to_string()),
+            Token::EndStatement,
+            Token::Identifier("config value".to_string()),
+            Token::Dash,
+            Token::Identifier("Setting".to_string()),
+            Token::EndStatement,
+            Token::Identifier("layout engine".to_string()),
+            Token::Dash,
+            Token::RightArrow,
+            Token::Identifier("Car".to_string()),
+            Token::EndStatement,
+            Token::Identifier("Jack".to_string()),
+            Token::Dash,
+            Token::Identifier("pin cushion".to_string()),
+        ];
+
+        assert_eq!(tokenize(input), Ok(output));
+    }
+
+    #[test]
+    fn test_tokenizer_attribute_block() {
+        let input = "Order, Inc { +id: Uuid, #lines: Line [1..], ~total }";
+
+        let output: Vec<Token> = vec![
+            Token::Identifier("Order, Inc".to_string()),
+            Token::LeftBrace,
+            Token::Plus,
+            Token::Identifier("id".to_string()),
+            Token::Colon,
+            Token::Identifier("Uuid".to_string()),
+            Token::Comma,
+            Token::Hash,
+            Token::Identifier("lines".to_string()),
+            Token::Colon,
+            Token::Identifier("Line".to_string()),
+            Token::LeftBracket,
+            Token::NaturalNumber(1),
+            Token::Range,
+            Token::RightBracket,
+            Token::Comma,
+            Token::Tilde,
+            Token::Identifier("total".to_string()),
+            Token::RightBrace,
+        ];
+
+        assert_eq!(tokenize(input), Ok(output));
+    }
+
+    #[test]
+    fn test_tokenizer_triangles() {
+        let input = "Manager-|>Employee\nPerson<|-is a-Employee\na|b";
+
+        let output: Vec<Token> = vec![
+            Token::Identifier("Manager".to_string()),
+            Token::Dash,
+            Token::RightTriangle,
+            Token::Identifier("Employee".to_string()),
+            Token::EndStatement,
+            Token::Identifier("Person".to_string()),
+            Token::LeftTriangle,
+            Token::Dash,
+            Token::Identifier("is a".to_string()),
+            Token::Dash,
+            Token::Identifier("Employee".to_string()),
+            Token::EndStatement,
+            Token::Identifier("a|b".to_string()),
+        ];
+
+        assert_eq!(tokenize(input), Ok(output));
+    }
+
+    #[test]
+    fn test_tokenizer_diamonds() {
+        let input = "Team<>-Player\nOrder<#>-Line-<>Box";
+
+        let output: Vec<Token> = vec![
+            Token::Identifier("Team".to_string()),
+            Token::HollowDiamond,
+            Token::Dash,
+            Token::Identifier("Player".to_string()),
+            Token::EndStatement,
+            Token::Identifier("Order".to_string()),
+            Token::FilledDiamond,
+            Token::Dash,
+            Token::Identifier("Line".to_string()),
+            Token::Dash,
+            Token::HollowDiamond,
+            Token::Identifier("Box".to_string()),
+        ];
+
+        assert_eq!(tokenize(input), Ok(output));
+    }
+
+    #[test]
+    fn test_tokenizer_line_styles() {
+        let input = "a..>b\nc==>d\ne~~f\npin g: ~1 ~.5\nh~~2>i";
+
+        let output: Vec<Token> = vec![
+            Token::Identifier("a".to_string()),
+            Token::Range,
+            Token::RightArrow,
+            Token::Identifier("b".to_string()),
+            Token::EndStatement,
+            Token::Identifier("c".to_string()),
+            Token::Equals,
+            Token::Equals,
+            Token::RightArrow,
+            Token::Identifier("d".to_string()),
+            Token::EndStatement,
+            Token::Identifier("e".to_string()),
+            Token::Tilde,
+            Token::Tilde,
+            Token::Identifier("f".to_string()),
+            Token::EndStatement,
+            Token::Keyword(Keyword::Pin),
+            Token::Identifier("g".to_string()),
+            Token::Colon,
+            Token::Float(-1.0),
+            Token::Float(-0.5),
+            Token::EndStatement,
+            Token::Identifier("h".to_string()),
+            Token::Tilde,
+            Token::Tilde,
+            Token::NaturalNumber(2),
+            Token::RightArrow,
+            Token::Identifier("i".to_string()),
+        ];
+
+        assert_eq!(tokenize(input), Ok(output));
+    }
+
+    #[test]
+    fn test_escape_identifier() {
+        for name in ["Customer", "Line Item", "Vec2", "First-Person Camera", "pin cushion", "entity", "layout engine", "say \"hi\"", "C:\\", "a|>b"] {
+            let escaped = escape_identifier(name);
+            assert_eq!(tokenize(&escaped), Ok(vec![Token::Identifier(name.to_string())]), "{:?}", escaped);
+        }
+
+        assert_eq!(escape_identifier("Line Item"), "Line Item");
+        assert_eq!(escape_identifier("Vec2"), "\"Vec2\"");
+    }
+
     // ADD NUMBER TESTS
 
     // ADD MULTIPLE TOKEN TESTS
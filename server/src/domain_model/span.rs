@@ -0,0 +1,67 @@
+use serde::{Deserialize, Serialize};
+
+/// A point in the source text
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Position {
+    /// Byte offset from the start of the input
+    pub offset: usize,
+    /// 1-based line number
+    pub line: usize,
+    /// 1-based column, counted in characters
+    pub column: usize
+}
+
+impl Position {
+    pub fn start() -> Self {
+        Position { offset: 0, line: 1, column: 1 }
+    }
+
+    /// Moves the position past the given character
+    pub fn advance(&mut self, c: char) {
+        self.offset += c.len_utf8();
+        if c == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+    }
+}
+
+/// A range of the source text, from `start` (inclusive) to `end` (exclusive)
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Span {
+    pub start: Position,
+    pub end: Position
+}
+
+impl Span {
+    pub fn new(start: Position, end: Position) -> Self {
+        Span { start, end }
+    }
+
+    /// The smallest span covering both spans
+    pub fn to(&self, other: Span) -> Span {
+        let start = if other.start.offset < self.start.offset { other.start } else { self.start };
+        let end = if other.end.offset > self.end.offset { other.end } else { self.end };
+        Span { start, end }
+    }
+
+    /// The slice of the source text covered by this span
+    pub fn slice<'a>(&self, input: &'a str) -> &'a str {
+        &input[self.start.offset..self.end.offset]
+    }
+}
+
+/// A value along with the span of the source text it came from
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Spanned<T> {
+    pub value: T,
+    pub span: Span
+}
+
+impl<T> Spanned<T> {
+    pub fn new(value: T, span: Span) -> Self {
+        Spanned { value, span }
+    }
+}
@@ -0,0 +1,169 @@
+//! Places the text of each relation and its multiplicities along its route,
+//! where they don't cover an entity or each other. Every label has a list of
+//! spots it could go, from best to worst, and takes the first one that's free
+//! (or the one that covers the least, if none are).
+//! The multiplicities go first, since they have to stay close to their ends.
+//! They'd rather be beside the line, while the text would rather be on it.
+
+use crate::{domain_model::graph::{Graph, Multiplicity}, image_generation::{placers::{GridPlacements, Vec2}, render::{text_width, GRID_SIZE, RELATION_FONT_SIZE}}};
+
+/// The space around the text of a label, in pixels
+const LABEL_PADDING: f32 = 2.0;
+/// How far from the end of a relation its multiplicity goes, in pixels
+const MULTIPLICITY_OFFSET: f32 = 24.0;
+/// How many multiplicity offsets from the end a multiplicity can go, from best to worst
+const MULTIPLICITY_SPOTS: [f32; 3] = [1.0, 1.5, 2.0];
+/// How far along the route the text can go, from best to worst
+const TEXT_SPOTS: [f32; 7] = [0.5, 0.4, 0.6, 0.3, 0.7, 0.2, 0.8];
+
+/// The center and the size of something on the diagram, in grid units
+type Rect = (Vec2, Vec2);
+
+/// Where each routed relation's text and multiplicities go, given the size of
+/// each entity's box in grid units. The anchors are the centers of the labels.
+pub fn place_labels(graph: &Graph, placements: &mut GridPlacements, sizes: &[Vec2]) {
+    let mut taken: Vec<Rect> = placements.nodes.iter()
+        .filter_map(|node| sizes.get(node.entity).map(|&size| (node.position, size)))
+        .collect();
+
+    for edge in placements.edges.iter_mut() {
+        let Some(relation) = graph.relations.get(edge.relation) else { continue };
+        let reversed: Vec<Vec2> = edge.route.iter().rev().copied().collect();
+
+        for (multiplicity, route, anchor) in [
+            (&relation.mult_1, edge.route.as_slice(), &mut edge.labels.mult_1),
+            (&relation.mult_2, reversed.as_slice(), &mut edge.labels.mult_2)
+        ] {
+            if *multiplicity == Multiplicity::None { continue }
+
+            let size = label_size(&multiplicity.to_string());
+            let spots = MULTIPLICITY_SPOTS.iter()
+                .filter_map(|&offsets| point_at(route, offsets * MULTIPLICITY_OFFSET / GRID_SIZE))
+                .flat_map(|(point, direction)| {
+                    let (left, right) = beside(point, direction, size);
+                    [left, right, point]
+                })
+                .collect();
+            *anchor = pick_spot(spots, size, &mut taken);
+        }
+    }
+
+    for edge in placements.edges.iter_mut() {
+        let Some(text) = graph.relations.get(edge.relation).and_then(|relation| relation.text.as_ref()) else { continue };
+
+        let size = label_size(text);
+        let length = route_length(&edge.route);
+        let spots = TEXT_SPOTS.iter()
+            .filter_map(|&fraction| point_at(&edge.route, fraction * length))
+            .flat_map(|(point, direction)| {
+                let (left, right) = beside(point, direction, size);
+                [point, left, right]
+            })
+            .collect();
+        edge.labels.text = pick_spot(spots, size, &mut taken);
+    }
+}
+
+/// The size of a label with the given text, in grid units
+fn label_size(text: &str) -> Vec2 {
+    Vec2 {
+        x: text_width(text, RELATION_FONT_SIZE) + 2.0 * LABEL_PADDING,
+        y: RELATION_FONT_SIZE + 2.0 * LABEL_PADDING
+    } / GRID_SIZE
+}
+
+pub fn route_length(route: &[Vec2]) -> f32 {
+    route.windows(2).map(|stretch| (stretch[1] - stretch[0]).squared_length().sqrt()).sum()
+}
+
+/// The point the given distance along the route, and the direction the route
+/// goes there. Past the end of the route, it's the end.
+pub fn point_at(route: &[Vec2], distance: f32) -> Option<(Vec2, Vec2)> {
+    let mut left = distance;
+    let mut last = None;
+    for stretch in route.windows(2) {
+        let offset = stretch[1] - stretch[0];
+        let Some(direction) = offset.normalized() else { continue };
+        let length = offset.squared_length().sqrt();
+        if left <= length {
+            return Some((stretch[0] + direction * left, direction));
+        }
+        left -= length;
+        last = Some((stretch[1], direction));
+    }
+    last
+}
+
+/// The spots just clear of the line on either side of the point, left first (going along the line)
+fn beside(point: Vec2, direction: Vec2, size: Vec2) -> (Vec2, Vec2) {
+    let side = Vec2 { x: direction.y, y: -direction.x };
+    let clear = (side.x * size.x).abs() / 2.0 + (side.y * size.y).abs() / 2.0 + LABEL_PADDING / GRID_SIZE;
+    (point + side * clear, point - side * clear)
+}
+
+/// How much of the two rects covers the other
+fn overlap((a_center, a_size): Rect, (b_center, b_size): Rect) -> f32 {
+    let x = (a_size.x + b_size.x) / 2.0 - (a_center.x - b_center.x).abs();
+    let y = (a_size.y + b_size.y) / 2.0 - (a_center.y - b_center.y).abs();
+    if x > 0.0 && y > 0.0 { x * y } else { 0.0 }
+}
+
+/// Takes the first spot that doesn't cover anything, or else the one that covers the least
+fn pick_spot(spots: Vec<Vec2>, size: Vec2, taken: &mut Vec<Rect>) -> Option<Vec2> {
+    let covered = |spot: Vec2| taken.iter().map(|&rect| overlap((spot, size), rect)).sum::<f32>();
+
+    let spot = spots.iter().copied().find(|&spot| covered(spot) == 0.0)
+        .or_else(|| spots.iter().copied().min_by(|&a, &b| covered(a).total_cmp(&covered(b))))?;
+
+    taken.push((spot, size));
+    Some(spot)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{domain_model::parser::parse_graph, image_generation::placers::{box_sizes, place, PlacerOptions}};
+
+    use super::*;
+
+    #[test]
+    fn labels_dont_cover_anything() {
+        let Ok(graph) = parse_graph("Customer-places-1..>Order\nCustomer-pays for-1..>Order\nOrder<#>1-contains-1..Line Item\nLine Item 0..-refers to-1..2 Product\nProduct-Product") else { panic!("Parsing failed") };
+        let grid = place(&graph, &PlacerOptions::default());
+        let sizes = box_sizes(&graph, None);
+
+        let mut rects: Vec<(String, Rect)> = grid.nodes.iter().map(|node| (graph.entities[node.entity].name.clone(), (node.position, sizes[node.entity]))).collect();
+        for edge in grid.edges.iter() {
+            let relation = &graph.relations[edge.relation];
+            for (text, anchor) in [
+                (relation.text.clone(), edge.labels.text),
+                (Some(relation.mult_1.to_string()), edge.labels.mult_1),
+                (Some(relation.mult_2.to_string()), edge.labels.mult_2)
+            ] {
+                if let (Some(text), Some(anchor)) = (text, anchor) {
+                    rects.push((text.clone(), (anchor, label_size(&text))));
+                }
+            }
+        }
+
+        println!("{}", grid);
+
+        assert_eq!(rects.len(), 4 + 4 + 6); // The entities, the texts and the multiplicities
+        for (i, (name, rect)) in rects.iter().enumerate() {
+            for (other_name, other) in rects.iter().skip(i + 1) {
+                assert_eq!(overlap(*rect, *other), 0.0, "\"{}\" covers \"{}\" ({:?} and {:?})", name, other_name, rect, other);
+            }
+        }
+    }
+
+    #[test]
+    fn multiplicities_stay_near_their_ends() {
+        let Ok(graph) = parse_graph("A 1..-B\nA 0..1-B\nA 2-C") else { panic!("Parsing failed") };
+        let grid = place(&graph, &PlacerOptions::default());
+
+        for edge in grid.edges.iter() {
+            let Some(anchor) = edge.labels.mult_1 else { panic!("Relation {} has no multiplicity", edge.relation) };
+            let distance = (anchor - edge.route[0]).squared_length().sqrt();
+            assert!(distance < 3.0 * MULTIPLICITY_OFFSET / GRID_SIZE, "The multiplicity of relation {} is {} away from its end", edge.relation, distance);
+        }
+    }
+}
\ No newline at end of file
@@ -0,0 +1,220 @@
+//! Measures how good a layout is, so placers and their settings can be compared,
+//! and so tests can tell when a layout got worse.
+//! Relations are measured along their routes if they were routed, and as straight
+//! lines between the centers of their entities if not. The boxes are the ones the
+//! client measured if it passed them, and the estimated ones if not.
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{domain_model::graph::Graph, image_generation::placers::{box_sizes, GridPlacements, Vec2}};
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LayoutMetrics {
+    /// How many times two relations that don't share an entity cross
+    pub edge_crossings: usize,
+    /// How long all the relations are together, in grid units
+    pub total_edge_length: f32,
+    pub max_edge_length: f32,
+    /// How many pairs of entities have overlapping boxes
+    pub node_overlaps: usize,
+    /// The area of the rectangle around everything, in grid units squared
+    pub bounding_box_area: f32,
+    /// How much wider than tall the rectangle around everything is
+    pub aspect_ratio: f32,
+    /// The smallest angle (in radians) between two relations of the same entity,
+    /// if any entity has two relations to other entities
+    pub angular_resolution: Option<f32>
+}
+
+/// The center and the size of an entity's box, in grid units
+type Rect = (Vec2, Vec2);
+
+/// Measures the placements of the graph, with the boxes sized like `box_sizes` sizes them.
+/// Entities that weren't placed (and their relations) are left out.
+pub fn measure(graph: &Graph, placements: &GridPlacements, measured: Option<&HashMap<String, Vec2>>) -> LayoutMetrics {
+    let sizes = box_sizes(graph, measured);
+    let boxes: Vec<Option<Rect>> = (0..graph.entities.len())
+        .map(|entity| placements.position_of(entity).map(|position| (position, sizes[entity])))
+        .collect();
+
+    // The lines of every relation between two placed entities
+    let lines: Vec<(usize, Vec<Vec2>)> = graph.relations.iter().enumerate().filter_map(|(index, relation)| {
+        let (Some(&Some((center_1, _))), Some(&Some((center_2, _)))) = (boxes.get(relation.entity_1), boxes.get(relation.entity_2)) else { return None };
+        let line = placements.route_of(index).map_or_else(|| vec![center_1, center_2], <[Vec2]>::to_vec);
+        Some((index, line))
+    }).collect();
+
+    let mut edge_crossings = 0;
+    for (i, (index, line)) in lines.iter().enumerate() {
+        let relation = &graph.relations[*index];
+        for (other_index, other_line) in lines.iter().skip(i + 1) {
+            let other = &graph.relations[*other_index];
+            let shares_entity = [other.entity_1, other.entity_2].iter().any(|entity| *entity == relation.entity_1 || *entity == relation.entity_2);
+            if shares_entity { continue }
+
+            for stretch in line.windows(2) {
+                edge_crossings += other_line.windows(2).filter(|other_stretch| crosses((stretch[0], stretch[1]), (other_stretch[0], other_stretch[1]))).count();
+            }
+        }
+    }
+
+    let edge_lengths: Vec<f32> = lines.iter()
+        .map(|(_, line)| line.windows(2).map(|stretch| (stretch[1] - stretch[0]).squared_length().sqrt()).sum())
+        .collect();
+
+    let placed: Vec<Rect> = boxes.iter().flatten().copied().collect();
+    let node_overlaps = placed.iter().enumerate()
+        .map(|(i, a)| placed.iter().skip(i + 1).filter(|b| overlaps(*a, **b)).count())
+        .sum();
+
+    let corners = placed.iter()
+        .flat_map(|&(center, size)| [center - size / 2.0, center + size / 2.0])
+        .chain(lines.iter().flat_map(|(_, line)| line.iter().copied()));
+    let (min, max) = corners.fold(
+        (Vec2 { x: f32::INFINITY, y: f32::INFINITY }, Vec2 { x: f32::NEG_INFINITY, y: f32::NEG_INFINITY }),
+        |(min, max), point| (Vec2 { x: min.x.min(point.x), y: min.y.min(point.y) }, Vec2 { x: max.x.max(point.x), y: max.y.max(point.y) })
+    );
+    let (width, height) = if placed.is_empty() { (0.0, 0.0) } else { (max.x - min.x, max.y - min.y) };
+
+    LayoutMetrics {
+        edge_crossings,
+        total_edge_length: edge_lengths.iter().sum(),
+        max_edge_length: edge_lengths.iter().copied().fold(0.0, f32::max),
+        node_overlaps,
+        bounding_box_area: width * height,
+        aspect_ratio: if height > 0.0 { width / height } else { 0.0 },
+        angular_resolution: angular_resolution(graph, &boxes, &lines)
+    }
+}
+
+/// Whether the two stretches cross, without only touching
+fn crosses((a, b): (Vec2, Vec2), (c, d): (Vec2, Vec2)) -> bool {
+    let side = |from: Vec2, to: Vec2, point: Vec2| {
+        let (direction, offset) = (to - from, point - from);
+        direction.x * offset.y - direction.y * offset.x
+    };
+    side(a, b, c) * side(a, b, d) < 0.0 && side(c, d, a) * side(c, d, b) < 0.0
+}
+
+fn overlaps((a_center, a_size): Rect, (b_center, b_size): Rect) -> bool {
+    (a_center.x - b_center.x).abs() < (a_size.x + b_size.x) / 2.0 && (a_center.y - b_center.y).abs() < (a_size.y + b_size.y) / 2.0
+}
+
+/// The smallest angle between the directions that relations leave an entity in.
+/// A relation leaves toward where its line touches the entity's box.
+fn angular_resolution(graph: &Graph, boxes: &[Option<Rect>], lines: &[(usize, Vec<Vec2>)]) -> Option<f32> {
+    let mut angles: Vec<Vec<f32>> = vec![Vec::new(); boxes.len()];
+    for (index, line) in lines.iter() {
+        let relation = &graph.relations[*index];
+        if relation.entity_1 == relation.entity_2 { continue }
+
+        for (entity, toward) in [(relation.entity_1, line.first()), (relation.entity_2, line.last())] {
+            let (Some(Some((center, _))), Some(&toward)) = (boxes.get(entity), toward) else { continue };
+            // A straight line starts at the center, so it leaves toward the other end
+            let toward = if toward == *center { if entity == relation.entity_1 { line[line.len() - 1] } else { line[0] } } else { toward };
+            let direction = toward - *center;
+            angles[entity].push(direction.y.atan2(direction.x));
+        }
+    }
+
+    angles.into_iter().filter(|angles| angles.len() >= 2).map(|mut angles| {
+        angles.sort_by(f32::total_cmp);
+        let around = angles[0] + std::f32::consts::TAU - angles[angles.len() - 1];
+        angles.windows(2).map(|pair| pair[1] - pair[0]).fold(around, f32::min)
+    }).min_by(f32::total_cmp)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::FRAC_PI_2;
+
+    use crate::{domain_model::parser::parse_graph, image_generation::placers::{GridNode, Layout, place, PlacerOptions}};
+
+    use super::*;
+
+    fn placements(positions: &[(f32, f32)]) -> GridPlacements {
+        GridPlacements {
+            nodes: positions.iter().enumerate().map(|(entity, &(x, y))| GridNode { entity, position: Vec2 { x, y } }).collect(),
+            edges: Vec::new()
+        }
+    }
+
+    #[test]
+    fn straight_lines_are_measured() {
+        // A square, with both of its diagonals
+        let Ok(graph) = parse_graph("A-B\nB-C\nC-D\nD-A\nA-C\nB-D") else { panic!("Parsing failed") };
+        let grid = placements(&[(0.0, 0.0), (4.0, 0.0), (4.0, 4.0), (0.0, 4.0)]);
+
+        let metrics = measure(&graph, &grid, None);
+
+        assert_eq!(metrics.edge_crossings, 1);
+        assert_eq!(metrics.node_overlaps, 0);
+        assert!((metrics.total_edge_length - (16.0 + 2.0 * 32f32.sqrt())).abs() < 0.001);
+        assert!((metrics.max_edge_length - 32f32.sqrt()).abs() < 0.001);
+        assert!((metrics.angular_resolution.unwrap() - FRAC_PI_2 / 2.0).abs() < 0.001);
+
+        let size = box_sizes(&graph, None)[0];
+        assert!((metrics.bounding_box_area - (4.0 + size.x) * (4.0 + size.y)).abs() < 0.001);
+        assert!((metrics.aspect_ratio - (4.0 + size.x) / (4.0 + size.y)).abs() < 0.001);
+    }
+
+    #[test]
+    fn overlaps_are_counted() {
+        let Ok(graph) = parse_graph("A-B\nB-C") else { panic!("Parsing failed") };
+        let grid = placements(&[(0.0, 0.0), (1.0, 0.0), (0.0, 0.5)]);
+
+        let metrics = measure(&graph, &grid, None);
+
+        assert_eq!(metrics.node_overlaps, 3);
+        assert_eq!(metrics.edge_crossings, 0);
+    }
+
+    #[test]
+    fn measured_sizes_are_used() {
+        let Ok(graph) = parse_graph("A-B") else { panic!("Parsing failed") };
+        let grid = placements(&[(0.0, 0.0), (4.0, 0.0)]);
+        // A is measured as much wider than its one letter name
+        let measured = HashMap::from([("A".to_string(), Vec2 { x: 400.0, y: 36.0 })]);
+
+        assert_eq!(measure(&graph, &grid, None).node_overlaps, 0);
+        assert_eq!(measure(&graph, &grid, Some(&measured)).node_overlaps, 1);
+    }
+
+    #[test]
+    fn empty_graph() {
+        let metrics = measure(&Graph::new(), &placements(&[]), None);
+
+        assert_eq!(metrics, LayoutMetrics {
+            edge_crossings: 0,
+            total_edge_length: 0.0,
+            max_edge_length: 0.0,
+            node_overlaps: 0,
+            bounding_box_area: 0.0,
+            aspect_ratio: 0.0,
+            angular_resolution: None
+        });
+    }
+
+    /// A small ordering domain, to catch placers getting worse at it
+    const ORDERS: &str = "Customer-places->Order\nOrder<#>-Line Item\nLine Item-refers to->Product\nProduct-Category\nCustomer-Address\nOrder-ships to->Address\nOrder-Invoice\nInvoice-Payment\nPayment<|-Card Payment\nPayment<|-Bank Transfer";
+
+    #[test]
+    fn layouts_stay_readable() {
+        let Ok(graph) = parse_graph(ORDERS) else { panic!("Parsing failed") };
+
+        for layout in [Layout::ForceDirected, Layout::Hierarchical] {
+            let grid = place(&graph, &PlacerOptions { layout: Some(layout), ..PlacerOptions::default() });
+            let metrics = measure(&graph, &grid, None);
+
+            println!("{:?}\n{}\n{:?}", layout, grid, metrics);
+
+            assert_eq!(metrics.node_overlaps, 0, "{:?} has overlapping entities", layout);
+            assert!(metrics.edge_crossings <= 2, "{:?} has {} crossings", layout, metrics.edge_crossings);
+            assert!(metrics.max_edge_length <= 12.0, "{:?} has a relation {} long", layout, metrics.max_edge_length);
+            assert!(metrics.aspect_ratio > 0.25 && metrics.aspect_ratio < 4.0, "{:?} is {} times wider than tall", layout, metrics.aspect_ratio);
+        }
+    }
+}
\ No newline at end of file
@@ -0,0 +1,6 @@
+//! The stages that run after the entities have been placed, working out how
+//! everything else in the diagram is drawn around them.
+
+pub mod labels;
+pub mod metrics;
+pub mod routing;
\ No newline at end of file
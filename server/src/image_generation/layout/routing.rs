@@ -0,0 +1,420 @@
+//! Routes relations as orthogonal lines around the entities' boxes.
+//! The space around the boxes is divided into a lattice of half grid cells, and
+//! each relation takes the cheapest path through it from the center of one box
+//! to the center of the other. Every bend costs extra, and so does going along
+//! a stretch that another route already takes. The path is then cut off where
+//! it leaves the boxes.
+//! Relations between the same two entities share a path, spread apart from each other.
+
+use std::{cmp::Reverse, collections::{BinaryHeap, HashMap, HashSet}, mem};
+
+use crate::{domain_model::graph::{EntityIndex, Graph}, image_generation::{placers::{EdgeLabels, GridEdge, GridPlacements, RelationIndex, Vec2}, render::clip_to_box}};
+
+/// Grid units between neighboring points of the lattice
+const STEP: f32 = 0.5;
+/// How close to a box a route may go, in grid units
+const CLEARANCE: f32 = 0.25;
+/// How many points of the lattice are left around the boxes, so routes can go around the outside
+const MARGIN: i32 = 4;
+/// Bigger lattices take too long to search, so their relations are drawn straight
+const MAX_POINTS: usize = 250_000;
+/// What a bend costs, in steps
+const BEND_COST: u32 = 3;
+/// What going along a stretch that another route already takes costs, in steps
+const SHARED_COST: u32 = 2;
+/// Grid units between relations that connect the same two entities
+const PARALLEL_GAP: f32 = 0.2;
+/// How far a relation from an entity to itself loops out of its box, in grid units
+const LOOP_SIZE: f32 = 0.5;
+
+/// The ways a route can go from a point of the lattice, in order, so that
+/// turning around is going `(direction + 2) % 4`
+const DIRECTIONS: [(i32, i32); 4] = [(1, 0), (0, 1), (-1, 0), (0, -1)];
+/// The direction a route has when it hasn't gone anywhere yet
+const NO_DIRECTION: usize = DIRECTIONS.len();
+
+/// The center and the size of an entity's box, in grid units
+type Box = (Vec2, Vec2);
+
+/// Routes every relation between two placed entities, given the size of each
+/// entity's box in grid units. The relations that can't be routed (because
+/// the boxes overlap, or there's no way around them) are drawn straight.
+pub fn route(graph: &Graph, placements: &GridPlacements, sizes: &[Vec2]) -> Vec<GridEdge> {
+    let boxes: Vec<Option<Box>> = (0..graph.entities.len()).map(|entity| {
+        placements.position_of(entity).zip(sizes.get(entity).copied())
+    }).collect();
+    let mut lattice = Lattice::new(&boxes);
+
+    // The relations between each two entities, in the order they were declared
+    let mut pairs: Vec<((EntityIndex, EntityIndex), Vec<RelationIndex>)> = Vec::new();
+    let mut pair_indices: HashMap<(EntityIndex, EntityIndex), usize> = HashMap::new();
+    for (index, relation) in graph.relations.iter().enumerate() {
+        let pair = (relation.entity_1.min(relation.entity_2), relation.entity_1.max(relation.entity_2));
+        let pair_index = *pair_indices.entry(pair).or_insert_with(|| {
+            pairs.push((pair, Vec::new()));
+            pairs.len() - 1
+        });
+        pairs[pair_index].1.push(index);
+    }
+
+    let mut edges = Vec::new();
+    for ((from, to), relations) in pairs {
+        let (Some(&Some(from_box)), Some(&Some(to_box))) = (boxes.get(from), boxes.get(to)) else { continue };
+
+        if from == to {
+            for (nth, &relation) in relations.iter().enumerate() {
+                edges.push(GridEdge { relation, route: self_loop(from_box, nth), labels: EdgeLabels::default() });
+            }
+            continue;
+        }
+
+        let path = lattice.as_mut()
+            .and_then(|lattice| lattice.find_path((from, from_box.0), (to, to_box.0)))
+            .and_then(|path| cut_at_boxes(&path, from_box, to_box))
+            .unwrap_or_else(|| vec![clip_to_box(from_box.0, from_box.1, to_box.0), clip_to_box(to_box.0, to_box.1, from_box.0)]);
+
+        let middle = (relations.len() - 1) as f32 / 2.0;
+        for (nth, &relation) in relations.iter().enumerate() {
+            let mut route = offset_route(&path, (nth as f32 - middle) * PARALLEL_GAP);
+            // The path goes from the entity that comes first, which might be the relation's second one
+            if graph.relations[relation].entity_1 != from {
+                route.reverse();
+            }
+            edges.push(GridEdge { relation, route, labels: EdgeLabels::default() });
+        }
+    }
+
+    edges.sort_by_key(|edge| edge.relation);
+    edges
+}
+
+/// The points routes can go through, with the boxes that cover them
+struct Lattice {
+    /// The coordinates (in steps) of the top left point
+    origin: (i32, i32),
+    width: i32,
+    height: i32,
+    /// The entities whose boxes cover each point
+    covered_by: Vec<Vec<EntityIndex>>,
+    /// The stretches between two neighboring points that routes already take
+    taken: HashSet<(usize, usize)>,
+    /// Kept between paths, so every path doesn't allocate its own
+    search: Search
+}
+
+/// What finding a path keeps track of for each state. A state is a point and the
+/// direction the route came into it from.
+#[derive(Default)]
+struct Search {
+    costs: Vec<u32>,
+    previous: Vec<usize>,
+    /// The states whose cost was set, to reset only those after a path is found
+    touched: Vec<usize>
+}
+
+impl Lattice {
+    fn new(boxes: &[Option<Box>]) -> Option<Lattice> {
+        let (min, max) = boxes.iter().flatten().fold(
+            (Vec2 { x: f32::INFINITY, y: f32::INFINITY }, Vec2 { x: f32::NEG_INFINITY, y: f32::NEG_INFINITY }),
+            |(min, max), &(center, size)| {
+                let (top_left, bottom_right) = (center - size / 2.0, center + size / 2.0);
+                (
+                    Vec2 { x: min.x.min(top_left.x), y: min.y.min(top_left.y) },
+                    Vec2 { x: max.x.max(bottom_right.x), y: max.y.max(bottom_right.y) }
+                )
+            }
+        );
+        if !min.x.is_finite() || !min.y.is_finite() || !max.x.is_finite() || !max.y.is_finite() {
+            return None; // Nothing was placed
+        }
+
+        let origin = ((min.x / STEP).floor() as i32 - MARGIN, (min.y / STEP).floor() as i32 - MARGIN);
+        let width = (max.x / STEP).ceil() as i32 + MARGIN - origin.0 + 1;
+        let height = (max.y / STEP).ceil() as i32 + MARGIN - origin.1 + 1;
+        if width as usize * height as usize > MAX_POINTS {
+            return None;
+        }
+
+        let state_count = width as usize * height as usize * (NO_DIRECTION + 1);
+        let mut lattice = Lattice {
+            origin,
+            width,
+            height,
+            covered_by: vec![Vec::new(); width as usize * height as usize],
+            taken: HashSet::new(),
+            search: Search { costs: vec![u32::MAX; state_count], previous: vec![usize::MAX; state_count], touched: Vec::new() }
+        };
+
+        for (entity, &(center, size)) in boxes.iter().enumerate().filter_map(|(entity, entity_box)| entity_box.as_ref().map(|entity_box| (entity, entity_box))) {
+            let half = size / 2.0 + Vec2 { x: CLEARANCE, y: CLEARANCE };
+            let (left, top) = (((center.x - half.x) / STEP).ceil() as i32, ((center.y - half.y) / STEP).ceil() as i32);
+            let (right, bottom) = (((center.x + half.x) / STEP).floor() as i32, ((center.y + half.y) / STEP).floor() as i32);
+            for y in top..=bottom {
+                for x in left..=right {
+                    if let Some(index) = lattice.index((x, y)) {
+                        lattice.covered_by[index].push(entity);
+                    }
+                }
+            }
+        }
+
+        Some(lattice)
+    }
+
+    fn index(&self, (x, y): (i32, i32)) -> Option<usize> {
+        let (x, y) = (x - self.origin.0, y - self.origin.1);
+        if x < 0 || y < 0 || x >= self.width || y >= self.height {
+            None
+        } else {
+            Some((y * self.width + x) as usize)
+        }
+    }
+
+    fn point(&self, index: usize) -> (i32, i32) {
+        (index as i32 % self.width + self.origin.0, index as i32 / self.width + self.origin.1)
+    }
+
+    /// The cheapest path (with A*) from the center of one entity's box to the
+    /// center of the other's, going around every other box. The stretches it
+    /// goes along are taken, so later paths keep away from them.
+    fn find_path(&mut self, (from, from_center): (EntityIndex, Vec2), (to, to_center): (EntityIndex, Vec2)) -> Option<Vec<Vec2>> {
+        let nearest = |point: Vec2| ((point.x / STEP).round() as i32, (point.y / STEP).round() as i32);
+        let (start, goal) = (self.index(nearest(from_center))?, self.index(nearest(to_center))?);
+        if start == goal {
+            return None;
+        }
+
+        let mut search = mem::take(&mut self.search);
+        let passable = |index: usize| self.covered_by[index].iter().all(|&entity| entity == from || entity == to);
+        let goal_point = self.point(goal);
+        let estimate = |index: usize| {
+            let (x, y) = self.point(index);
+            x.abs_diff(goal_point.0) + y.abs_diff(goal_point.1)
+        };
+
+        let mut queue = BinaryHeap::new();
+
+        let start_state = start * (NO_DIRECTION + 1) + NO_DIRECTION;
+        search.costs[start_state] = 0;
+        search.touched.push(start_state);
+        queue.push(Reverse((estimate(start), 0, start_state)));
+
+        let mut reached = None;
+        while let Some(Reverse((_, cost, state))) = queue.pop() {
+            if cost > search.costs[state] { continue } // There was a cheaper way here
+            let (index, direction) = (state / (NO_DIRECTION + 1), state % (NO_DIRECTION + 1));
+            if index == goal {
+                reached = Some(state);
+                break;
+            }
+
+            let (x, y) = self.point(index);
+            for (next_direction, (dx, dy)) in DIRECTIONS.iter().enumerate() {
+                // Turning around never makes a route better
+                if direction != NO_DIRECTION && next_direction == (direction + 2) % 4 { continue }
+                let Some(next) = self.index((x + dx, y + dy)) else { continue };
+                if !passable(next) { continue }
+
+                let mut next_cost = cost + 1;
+                if direction != NO_DIRECTION && direction != next_direction {
+                    next_cost += BEND_COST;
+                }
+                if self.taken.contains(&stretch(index, next)) {
+                    next_cost += SHARED_COST;
+                }
+
+                let next_state = next * (NO_DIRECTION + 1) + next_direction;
+                if next_cost < search.costs[next_state] {
+                    search.costs[next_state] = next_cost;
+                    search.previous[next_state] = state;
+                    search.touched.push(next_state);
+                    queue.push(Reverse((next_cost + estimate(next), next_cost, next_state)));
+                }
+            }
+        }
+
+        let path = reached.map(|mut state| {
+            let mut indices = vec![state / (NO_DIRECTION + 1)];
+            while search.previous[state] != usize::MAX {
+                state = search.previous[state];
+                indices.push(state / (NO_DIRECTION + 1));
+            }
+            indices.reverse();
+            indices
+        });
+
+        // Only what this search touched has to be reset for the next one
+        for &state in search.touched.iter() {
+            search.costs[state] = u32::MAX;
+            search.previous[state] = usize::MAX;
+        }
+        search.touched.clear();
+        self.search = search;
+
+        let indices = path?;
+
+        for pair in indices.windows(2) {
+            self.taken.insert(stretch(pair[0], pair[1]));
+        }
+
+        Some(indices.into_iter().map(|index| {
+            let (x, y) = self.point(index);
+            Vec2 { x: x as f32 * STEP, y: y as f32 * STEP }
+        }).collect())
+    }
+}
+
+/// The stretch between two neighboring points, the same both ways
+fn stretch(a: usize, b: usize) -> (usize, usize) {
+    (a.min(b), a.max(b))
+}
+
+fn inside((center, size): Box, point: Vec2) -> bool {
+    (point.x - center.x).abs() < size.x / 2.0 && (point.y - center.y).abs() < size.y / 2.0
+}
+
+/// Where the straight stretch from a point inside the box to a point outside it crosses the border
+fn border_point((center, size): Box, inside: Vec2, outside: Vec2) -> Vec2 {
+    if inside.y == outside.y {
+        Vec2 { x: center.x + (outside.x - inside.x).signum() * size.x / 2.0, y: inside.y }
+    } else {
+        Vec2 { x: inside.x, y: center.y + (outside.y - inside.y).signum() * size.y / 2.0 }
+    }
+}
+
+/// Cuts the path off where it leaves the first box and where it enters the
+/// second, keeping only the corners. Fails if there's nothing between the boxes.
+fn cut_at_boxes(path: &[Vec2], from: Box, to: Box) -> Option<Vec<Vec2>> {
+    let leaves = path.iter().position(|&point| !inside(from, point))?;
+    let enters = path.iter().rposition(|&point| !inside(to, point))?;
+    if leaves == 0 || enters + 1 >= path.len() || leaves > enters {
+        return None;
+    }
+
+    let mut points = vec![border_point(from, path[leaves - 1], path[leaves])];
+    points.extend_from_slice(&path[leaves..=enters]);
+    points.push(border_point(to, path[enters + 1], path[enters]));
+
+    Some(corners(points))
+}
+
+/// Leaves out the points that are on a straight line between their neighbors
+fn corners(points: Vec<Vec2>) -> Vec<Vec2> {
+    let mut corners: Vec<Vec2> = Vec::with_capacity(points.len());
+    for point in points {
+        if corners.last() == Some(&point) { continue }
+        if let [.., before, last] = corners[..]
+            && ((before.x == last.x && last.x == point.x) || (before.y == last.y && last.y == point.y)) {
+            corners.pop();
+        }
+        corners.push(point);
+    }
+    corners
+}
+
+/// Moves the route sideways by `offset` grid units (to the left, going along it).
+/// Each corner moves with both stretches next to it, so the route stays orthogonal.
+fn offset_route(route: &[Vec2], offset: f32) -> Vec<Vec2> {
+    if offset == 0.0 || route.len() < 2 {
+        return route.to_vec();
+    }
+
+    let normals: Vec<Vec2> = route.windows(2).map(|stretch| {
+        let direction = (stretch[1] - stretch[0]).normalized().unwrap_or(Vec2 { x: 0.0, y: 0.0 });
+        Vec2 { x: direction.y, y: -direction.x } * offset
+    }).collect();
+
+    route.iter().enumerate().map(|(index, &point)| {
+        let before = if index > 0 { normals[index - 1] } else { Vec2 { x: 0.0, y: 0.0 } };
+        let after = normals.get(index).copied().unwrap_or(Vec2 { x: 0.0, y: 0.0 });
+        point + before + after
+    }).collect()
+}
+
+/// A relation from an entity to itself goes out of the right side of its box
+/// and back in at the top. Each next one loops a bit further out.
+fn self_loop((center, size): Box, nth: usize) -> Vec<Vec2> {
+    let half = size / 2.0;
+    let spread = nth as f32 * PARALLEL_GAP;
+    let (right, top) = (center.x + half.x + LOOP_SIZE + spread, center.y - half.y - LOOP_SIZE - spread);
+    let (start_y, end_x) = (center.y + spread.min(half.y), center.x - spread.min(half.x));
+
+    vec![
+        Vec2 { x: center.x + half.x, y: start_y },
+        Vec2 { x: right, y: start_y },
+        Vec2 { x: right, y: top },
+        Vec2 { x: end_x, y: top },
+        Vec2 { x: end_x, y: center.y - half.y }
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{domain_model::parser::parse_graph, image_generation::placers::{box_sizes, GridNode}};
+
+    use super::*;
+
+    fn placements(positions: &[(f32, f32)]) -> GridPlacements {
+        GridPlacements {
+            nodes: positions.iter().enumerate().map(|(entity, &(x, y))| GridNode { entity, position: Vec2 { x, y } }).collect(),
+            edges: Vec::new()
+        }
+    }
+
+    /// Whether the straight stretch from `a` to `b` goes through the inside of the box
+    fn crosses((center, size): Box, a: Vec2, b: Vec2) -> bool {
+        let half = size / 2.0;
+        a.x.min(b.x) < center.x + half.x && a.x.max(b.x) > center.x - half.x
+            && a.y.min(b.y) < center.y + half.y && a.y.max(b.y) > center.y - half.y
+    }
+
+    fn on_border((center, size): Box, point: Vec2) -> bool {
+        let (x, y, half) = ((point.x - center.x).abs(), (point.y - center.y).abs(), size / 2.0);
+        (x == half.x && y <= half.y) || (y == half.y && x <= half.x)
+    }
+
+    #[test]
+    fn routes_go_around_boxes() {
+        let Ok(graph) = parse_graph("A-B\nC-D") else { panic!("Parsing failed") };
+        // C is right between A and B
+        let grid = placements(&[(0.0, 0.0), (8.0, 0.0), (4.0, 0.0), (4.0, 6.0)]);
+        let sizes = box_sizes(&graph, None);
+
+        let edges = route(&graph, &grid, &sizes);
+        let route = &edges[0].route;
+
+        println!("{:?}", route);
+
+        assert_eq!(edges[0].relation, 0);
+        assert!(route.len() > 2, "The route goes straight through C");
+        assert!(on_border((Vec2 { x: 0.0, y: 0.0 }, sizes[0]), route[0]), "The route doesn't start at A's border");
+        assert!(on_border((Vec2 { x: 8.0, y: 0.0 }, sizes[1]), *route.last().unwrap()), "The route doesn't end at B's border");
+        for stretch in route.windows(2) {
+            assert!(stretch[0].x == stretch[1].x || stretch[0].y == stretch[1].y, "{:?} isn't orthogonal", stretch);
+            assert!(!crosses((Vec2 { x: 4.0, y: 0.0 }, sizes[2]), stretch[0], stretch[1]), "{:?} goes through C", stretch);
+        }
+    }
+
+    #[test]
+    fn parallel_relations_are_spread_apart() {
+        let Ok(graph) = parse_graph("A-B\nA-knows->B\nB-A\nA-A\nA-A") else { panic!("Parsing failed") };
+        let grid = placements(&[(0.0, 0.0), (5.0, 3.0)]);
+
+        let edges = route(&graph, &grid, &box_sizes(&graph, None));
+
+        for edge in edges.iter() {
+            println!("{}: {:?}", edge.relation, edge.route);
+        }
+
+        assert_eq!(edges.len(), 5);
+        for (i, edge) in edges.iter().enumerate() {
+            for other in edges.iter().skip(i + 1) {
+                assert!(edge.route.iter().all(|point| !other.route.contains(point)), "Relations {} and {} touch", edge.relation, other.relation);
+            }
+        }
+
+        // B-A goes the other way, from B to A
+        let (a_to_b, b_to_a) = (&edges[0].route, &edges[2].route);
+        assert!(a_to_b[0].x < 0.5 && b_to_a.last().unwrap().x < 0.5);
+    }
+}
\ No newline at end of file
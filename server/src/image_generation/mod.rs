@@ -1 +1,3 @@
-pub mod placers;
\ No newline at end of file
+pub mod layout;
+pub mod placers;
+pub mod render;
\ No newline at end of file
@@ -0,0 +1,316 @@
+//! Diagrams often have parts that aren't related to each other at all, like
+//! separate bounded contexts. Each of those parts (connected component) is
+//! placed on its own, and then they're packed into rows, biggest first, so
+//! that together they make a rectangle of about the configured aspect ratio.
+//! The packing is the same for every layout, so it has its own config.
+//! Parts with pinned entities, or entities that were placed before (if the placer
+//! keeps those), stay where they were placed, and the rest are packed below them.
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{domain_model::graph::{EntityIndex, Graph, Relation}, image_generation::placers::{box_sizes, force_directed::{check, SimConfigError}, GridNode, GridPlacements, packing_config, Placer, PlacerOptions, Vec2}};
+
+/// The settings of the packing. Like the simulation's, each setting can be changed
+/// with a statement like `config component gap: 3`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct PackingConfig {
+    /// How far apart (in grid units) the parts of a diagram that aren't related are packed
+    pub component_gap: f32,
+    /// How much wider than tall the packed parts should be
+    pub aspect_ratio: f32
+}
+
+impl Default for PackingConfig {
+    fn default() -> Self {
+        PackingConfig {
+            component_gap: 2.0,
+            aspect_ratio: 1.5
+        }
+    }
+}
+
+impl PackingConfig {
+    /// The names of the settings, as written in a `config` statement
+    pub const SETTINGS: [&str; 2] = ["component gap", "aspect ratio"];
+
+    /// Checks that every setting is in a range the packing can handle
+    pub fn validate(&self) -> Result<(), SimConfigError> {
+        check("component gap", self.component_gap, 0.0..=100.0)?;
+        check("aspect ratio", self.aspect_ratio, 0.1..=10.0)
+    }
+
+    /// Changes one setting by its name, as written in a `config` statement. The
+    /// config is left as it was if the setting doesn't exist or the value is invalid.
+    pub fn set(&mut self, setting: &str, value: f32) -> Result<(), SimConfigError> {
+        let mut config = self.clone();
+
+        match setting {
+            "component gap" => config.component_gap = value,
+            "aspect ratio" => config.aspect_ratio = value,
+            _ => return Err(SimConfigError::UnknownSetting(setting.to_string()))
+        }
+
+        config.validate()?;
+        *self = config;
+        Ok(())
+    }
+}
+
+/// The entities of each part of the graph that's connected by relations,
+/// ordered by their first entity
+pub fn connected_components(graph: &Graph) -> Vec<Vec<EntityIndex>> {
+    // Union-find, where every entity points toward the first entity of its component
+    let mut parent: Vec<EntityIndex> = (0..graph.entities.len()).collect();
+    fn root(parent: &mut [EntityIndex], mut entity: EntityIndex) -> EntityIndex {
+        while parent[entity] != entity {
+            parent[entity] = parent[parent[entity]]; // Halve the path on the way up
+            entity = parent[entity];
+        }
+        entity
+    }
+
+    for relation in graph.relations.iter() {
+        let (root_1, root_2) = (root(&mut parent, relation.entity_1), root(&mut parent, relation.entity_2));
+        parent[root_1.max(root_2)] = root_1.min(root_2);
+    }
+
+    let mut components: Vec<Vec<EntityIndex>> = Vec::new();
+    let mut component_of_root: HashMap<EntityIndex, usize> = HashMap::new();
+    for entity in 0..graph.entities.len() {
+        let entity_root = root(&mut parent, entity);
+        let component = *component_of_root.entry(entity_root).or_insert_with(|| {
+            components.push(Vec::new());
+            components.len() - 1
+        });
+        components[component].push(entity);
+    }
+    components
+}
+
+/// The part of the graph with only the given entities (and the relations between them).
+/// Entity `n` of the subgraph is `entities[n]`.
+fn subgraph(graph: &Graph, entities: &[EntityIndex]) -> Graph {
+    let index_of: HashMap<EntityIndex, EntityIndex> = entities.iter().enumerate().map(|(index, &entity)| (entity, index)).collect();
+
+    let mut subgraph = Graph::new();
+    subgraph.entities = entities.iter().map(|&entity| graph.entities[entity].clone()).collect();
+    subgraph.relations = graph.relations.iter()
+        .filter_map(|relation| {
+            let (&entity_1, &entity_2) = (index_of.get(&relation.entity_1)?, index_of.get(&relation.entity_2)?);
+            Some(Relation { entity_1, entity_2, ..relation.clone() })
+        })
+        .collect();
+    subgraph.pins = graph.pins.iter()
+        .filter_map(|(entity, &pos)| index_of.get(entity).map(|&index| (index, pos)))
+        .collect();
+    subgraph.layout = graph.layout.clone();
+    subgraph.config = graph.config.clone();
+    subgraph.raw = graph.raw.clone();
+    subgraph
+}
+
+/// Places every part of the graph on its own with the placer, and packs them together
+pub fn place_packed(placer: &dyn Placer, graph: &Graph, options: &PlacerOptions) -> GridPlacements {
+    let components = connected_components(graph);
+    if components.len() <= 1 {
+        return placer.place(graph, options);
+    }
+
+    let config = &packing_config(graph, options);
+    let sizes = box_sizes(graph, options.sizes.as_ref());
+    // Only some placers put the entities back where they were, so the rest can't count on it
+    let previous = options.previous.as_ref().filter(|_| placer.keeps_previous());
+
+    let mut positions = vec![Vec2 { x: 0.0, y: 0.0 }; graph.entities.len()];
+    let mut fixed = Vec::new();
+    let mut free = Vec::new();
+    for entities in components {
+        let grid = placer.place(&subgraph(graph, &entities), options);
+        for node in grid.nodes {
+            positions[entities[node.entity]] = node.position;
+        }
+
+        let is_fixed = entities.iter().any(|entity| {
+            graph.pins.contains_key(entity) || previous.is_some_and(|previous| previous.contains_key(&graph.entities[*entity].name))
+        });
+        if is_fixed {
+            fixed.push(entities);
+        } else {
+            free.push(entities);
+        }
+    }
+
+    pack(&free, &fixed, &mut positions, &sizes, config);
+
+    let nodes = positions.into_iter().enumerate()
+        .map(|(entity, position)| GridNode { entity, position })
+        .collect();
+
+    GridPlacements { nodes, edges: Vec::new() }
+}
+
+/// The top left and bottom right corners around the boxes of the entities
+fn bounds(entities: &[EntityIndex], positions: &[Vec2], sizes: &[Vec2]) -> (Vec2, Vec2) {
+    entities.iter().fold(
+        (Vec2 { x: f32::INFINITY, y: f32::INFINITY }, Vec2 { x: f32::NEG_INFINITY, y: f32::NEG_INFINITY }),
+        |(min, max), &entity| {
+            let (top_left, bottom_right) = (positions[entity] - sizes[entity] / 2.0, positions[entity] + sizes[entity] / 2.0);
+            (
+                Vec2 { x: min.x.min(top_left.x), y: min.y.min(top_left.y) },
+                Vec2 { x: max.x.max(bottom_right.x), y: max.y.max(bottom_right.y) }
+            )
+        }
+    )
+}
+
+/// Moves the free components into rows, below the fixed ones if there are any.
+/// They're moved by whole grid cells, so they stay on the grid.
+fn pack(free: &[Vec<EntityIndex>], fixed: &[Vec<EntityIndex>], positions: &mut [Vec2], sizes: &[Vec2], config: &PackingConfig) {
+    if free.is_empty() {
+        return;
+    }
+
+    let gap = config.component_gap;
+    let mut components: Vec<(&Vec<EntityIndex>, (Vec2, Vec2))> = free.iter()
+        .map(|entities| (entities, bounds(entities, positions, sizes)))
+        .collect();
+    // The tallest go first, so every row is about as tall as its first component
+    components.sort_by(|(_, (a_min, a_max)), (_, (b_min, b_max))| (b_max.y - b_min.y).total_cmp(&(a_max.y - a_min.y)));
+
+    let area: f32 = components.iter().map(|(_, (min, max))| (max.x - min.x + gap) * (max.y - min.y + gap)).sum();
+    let widest = components.iter().map(|(_, (min, max))| max.x - min.x).fold(0.0, f32::max);
+    let row_width = (area * config.aspect_ratio).sqrt().max(widest);
+
+    let fixed_entities: Vec<EntityIndex> = fixed.iter().flatten().copied().collect();
+    let origin = if fixed_entities.is_empty() {
+        Vec2 { x: 0.0, y: 0.0 }
+    } else {
+        let (min, max) = bounds(&fixed_entities, positions, sizes);
+        Vec2 { x: min.x, y: max.y + gap }
+    };
+
+    let (mut x, mut y, mut row_bottom) = (origin.x, origin.y, origin.y);
+    let mut packed_max = origin;
+    for (entities, (min, max)) in components {
+        if x > origin.x && x + (max.x - min.x) > origin.x + row_width {
+            x = origin.x;
+            y = row_bottom + gap;
+        }
+
+        let offset = Vec2 { x: (x - min.x).ceil(), y: (y - min.y).ceil() };
+        for &entity in entities.iter() {
+            positions[entity] += offset;
+        }
+
+        x = max.x + offset.x + gap;
+        row_bottom = row_bottom.max(max.y + offset.y);
+        packed_max = Vec2 { x: packed_max.x.max(max.x + offset.x), y: packed_max.y.max(max.y + offset.y) };
+    }
+
+    // Without anything fixed to go below, the packed rectangle goes around the middle of the grid
+    if fixed_entities.is_empty() {
+        let center = Vec2 { x: (packed_max.x / 2.0).round(), y: (packed_max.y / 2.0).round() };
+        for entities in free {
+            for &entity in entities.iter() {
+                positions[entity] -= center;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{domain_model::parser::parse_graph, image_generation::placers::{force_directed::ForceDirected, hierarchical::Hierarchical}};
+
+    use super::*;
+
+    /// Whether any two entities' boxes overlap
+    fn any_overlap(grid: &GridPlacements, sizes: &[Vec2]) -> bool {
+        grid.nodes.iter().enumerate().any(|(i, a)| grid.nodes.iter().skip(i + 1).any(|b| {
+            let (a_size, b_size) = (sizes[a.entity], sizes[b.entity]);
+            (a.position.x - b.position.x).abs() < (a_size.x + b_size.x) / 2.0 && (a.position.y - b.position.y).abs() < (a_size.y + b_size.y) / 2.0
+        }))
+    }
+
+    #[test]
+    fn components_are_found() {
+        let Ok(graph) = parse_graph("A-B\nC-D\nB-E\nentity F: #000000\nD-D") else { panic!("Parsing failed") };
+
+        let names: Vec<Vec<&str>> = connected_components(&graph).iter()
+            .map(|component| component.iter().map(|&entity| graph.entities[entity].name.as_str()).collect())
+            .collect();
+
+        assert_eq!(names, vec![vec!["A", "B", "E"], vec!["C", "D"], vec!["F"]]);
+    }
+
+    #[test]
+    fn islands_are_packed_together() {
+        let Ok(graph) = parse_graph("A-B\nB-C\nD-E\nF-G\nG-H\nH-F\nI-J\nK-L\nM-N\nO-P") else { panic!("Parsing failed") };
+        let sizes = box_sizes(&graph, None);
+
+        let grid = place_packed(&ForceDirected, &graph, &PlacerOptions::default());
+
+        println!("{}", grid);
+
+        assert!(!any_overlap(&grid, &sizes), "Some entities overlap");
+
+        let all: Vec<EntityIndex> = (0..graph.entities.len()).collect();
+        let positions: Vec<Vec2> = all.iter().map(|&entity| grid.position_of(entity).unwrap()).collect();
+        let (min, max) = bounds(&all, &positions, &sizes);
+        let (width, height) = (max.x - min.x, max.y - min.y);
+        assert!(width / height > 0.5 && width / height < 4.0, "The islands are packed {} by {}", width, height);
+
+        // The islands are all next to each other, rather than spread far apart
+        for (entities, (island_min, island_max)) in connected_components(&graph).iter().map(|entities| (entities, bounds(entities, &positions, &sizes))) {
+            let others: Vec<EntityIndex> = all.iter().copied().filter(|entity| !entities.contains(entity)).collect();
+            let (others_min, others_max) = bounds(&others, &positions, &sizes);
+            let gap_x = (others_min.x - island_max.x).max(island_min.x - others_max.x);
+            let gap_y = (others_min.y - island_max.y).max(island_min.y - others_max.y);
+            assert!(gap_x.max(gap_y) < 2.0 * PackingConfig::default().component_gap, "The island of {} is too far from the rest", graph.entities[entities[0]].name);
+        }
+    }
+
+    #[test]
+    fn pinned_islands_stay_put() {
+        let Ok(graph) = parse_graph("A-B\nC-D\nE-F\npin C: 10 10") else { panic!("Parsing failed") };
+        let sizes = box_sizes(&graph, None);
+
+        let grid = place_packed(&ForceDirected, &graph, &PlacerOptions::default());
+
+        println!("{}", grid);
+
+        assert_eq!(grid.position_of(2), Some(Vec2 { x: 10.0, y: 10.0 }));
+        assert!(!any_overlap(&grid, &sizes), "Some entities overlap");
+        // The other islands go below the pinned one
+        let (_, pinned_max) = bounds(&[2, 3], &grid.nodes.iter().map(|node| node.position).collect::<Vec<_>>(), &sizes);
+        for entity in [0, 1, 4, 5] {
+            assert!(grid.position_of(entity).unwrap().y > pinned_max.y, "{} isn't below the pinned island", graph.entities[entity].name);
+        }
+    }
+
+    #[test]
+    fn previous_placements_only_fix_islands_if_the_placer_keeps_them() {
+        let Ok(graph) = parse_graph("layout hierarchical\nA-B\nC-D") else { panic!("Parsing failed") };
+        let sizes = box_sizes(&graph, None);
+        let previous = HashMap::from([("A".to_string(), Vec2 { x: 0.0, y: 0.0 }), ("C".to_string(), Vec2 { x: 10.0, y: 0.0 })]);
+        let options = PlacerOptions { previous: Some(previous), ..PlacerOptions::default() };
+
+        // The hierarchical placer ignores where the entities were, so both islands are packed
+        let grid = place_packed(&Hierarchical, &graph, &options);
+
+        println!("{}", grid);
+
+        assert!(!any_overlap(&grid, &sizes), "Some entities overlap");
+
+        let grid = place_packed(&ForceDirected, &graph, &options);
+
+        println!("{}", grid);
+
+        assert_eq!(grid.position_of(0), Some(Vec2 { x: 0.0, y: 0.0 }));
+        assert_eq!(grid.position_of(2), Some(Vec2 { x: 10.0, y: 0.0 }));
+        assert!(!any_overlap(&grid, &sizes), "Some entities overlap");
+    }
+}
\ No newline at end of file
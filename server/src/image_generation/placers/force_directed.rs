@@ -4,50 +4,228 @@
 //! Nodes that aren't connected in the domain model have a weak spring (of the
 //! longest length present in the model + 1), while nodes that are connected
 //! have a strong spring.
+//! Supertypes are also pushed above their subtypes, so that inheritance
+//! hierarchies read from top to bottom.
+//! How the simulation behaves can be tuned with a `SimConfig`.
+//! Nodes only push (and keep apart from) nodes that are close to them, so nodes
+//! are sorted into buckets by position, and only nearby buckets are checked.
+//! Each node is as big as its entity's box, and boxes are kept from overlapping.
+//! Nodes are added to the simulation a few at a time, and big graphs only get a
+//! full run once all of their nodes are in, instead of one for every node.
+//! The simulation only uses ordered collections and arithmetic that's exact
+//! everywhere (no `sin` or `cos`), so the same graph and seed always end up in
+//! exactly the same place, natively and in the browser.
 
-use std::{collections::HashMap};
+use std::{collections::{BTreeMap, HashMap}, fmt, ops::RangeInclusive};
 
-use crate::{domain_model::graph::Graph, image_generation::placers::{GridNode, GridPlacements, Vec2}};
+use serde::{Deserialize, Serialize};
 
-const ITERATIONS: usize = 1000;
-const fn neighbor_spring_k(iterations: usize) -> f32 {
-    if iterations < 100 {
-        0.008 * iterations as f32 // Warm up
-    } else if iterations < ITERATIONS / 2 {
-        0.8
-    } else {
-        0.0
+use crate::{domain_model::graph::{Arrow, Graph}, image_generation::placers::{box_sizes, GridNode, GridPlacements, Placer, PlacerOptions, sim_config, Vec2}};
+
+/// More iterations than this would freeze the browser for too long
+const MAX_ITERATIONS: usize = 10_000;
+/// The space kept between two entities' boxes after each round of the simulation, in grid units
+const BOX_GAP: f32 = 0.25;
+/// Placing a few new nodes around already placed ones takes this many times fewer iterations
+const RELAXATION_DIVISOR: usize = 4;
+/// The nodes are added to the simulation in at most this many batches, so big graphs
+/// don't take a run for every single node
+const MAX_BATCHES: usize = 16;
+/// Turning by this is turning by the golden angle (about 2.4 radians), which spreads
+/// the nodes around a spiral without lining any of them up
+const GOLDEN_TURN: Vec2 = Vec2 { x: -0.737_326_2, y: 0.675_536_9 };
+/// How far (in grid units, either way) a seed can move a node from where it starts
+const SCATTER: f32 = 1.0;
+
+/// The settings of the simulation. Each setting can be changed with a statement
+/// like `config delta time: 0.05`, using the setting's name with spaces.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct SimConfig {
+    /// How many steps the simulation runs for, each time a node is added
+    pub iterations: usize,
+    /// The part of the iterations in which the springs are slowly tightened
+    pub warm_up: f32,
+    /// How strongly related entities pull toward each other, during the first half
+    pub neighbor_spring_k: f32,
+    /// How far apart unrelated entities are pushed
+    pub expansion_push: f32,
+    pub expansion_spring_k: f32,
+    /// How strongly entities line up with their neighbors, during the second half
+    pub orthogonal_factor: f32,
+    pub delta_time: f32,
+    /// How much of its velocity a node keeps from one step to the next
+    pub damping: f32,
+    /// How far (vertically) a supertype should be above its subtypes
+    pub hierarchy_gap: f32,
+    pub hierarchy_spring_k: f32
+}
+
+impl Default for SimConfig {
+    fn default() -> Self {
+        SimConfig {
+            iterations: 1000,
+            warm_up: 0.1,
+            neighbor_spring_k: 0.8,
+            expansion_push: 4.0,
+            expansion_spring_k: 0.2,
+            orthogonal_factor: 0.8,
+            delta_time: 0.1,
+            damping: 0.95,
+            hierarchy_gap: 1.0,
+            hierarchy_spring_k: 0.5
+        }
+    }
+}
+
+impl SimConfig {
+    /// Checks that every setting is in a range the simulation can handle
+    pub fn validate(&self) -> Result<(), SimConfigError> {
+        check("iterations", self.iterations as f32, 1.0..=MAX_ITERATIONS as f32)?;
+        check("warm up", self.warm_up, 0.0..=0.5)?;
+        check("neighbor spring k", self.neighbor_spring_k, 0.01..=10.0)?;
+        check("expansion push", self.expansion_push, 0.1..=100.0)?;
+        check("expansion spring k", self.expansion_spring_k, 0.0..=10.0)?;
+        check("orthogonal factor", self.orthogonal_factor, 0.0..=10.0)?;
+        check("delta time", self.delta_time, 0.01..=1.0)?;
+        check("damping", self.damping, 0.0..=1.0)?;
+        check("hierarchy gap", self.hierarchy_gap, 0.0..=100.0)?;
+        check("hierarchy spring k", self.hierarchy_spring_k, 0.0..=10.0)
+    }
+
+    /// Changes one setting by its name, as written in a `config` statement. The
+    /// config is left as it was if the setting doesn't exist or the value is invalid.
+    pub fn set(&mut self, setting: &str, value: f32) -> Result<(), SimConfigError> {
+        let mut config = self.clone();
+
+        match setting {
+            "iterations" => {
+                if value.fract() != 0.0 {
+                    return Err(SimConfigError::OutOfRange { setting: setting.to_string(), value, min: 1.0, max: MAX_ITERATIONS as f32 });
+                }
+                config.iterations = value as usize;
+            },
+            "warm up" => config.warm_up = value,
+            "neighbor spring k" => config.neighbor_spring_k = value,
+            "expansion push" => config.expansion_push = value,
+            "expansion spring k" => config.expansion_spring_k = value,
+            "orthogonal factor" => config.orthogonal_factor = value,
+            "delta time" => config.delta_time = value,
+            "damping" => config.damping = value,
+            "hierarchy gap" => config.hierarchy_gap = value,
+            "hierarchy spring k" => config.hierarchy_spring_k = value,
+            _ => return Err(SimConfigError::UnknownSetting(setting.to_string()))
+        }
+
+        config.validate()?;
+        *self = config;
+        Ok(())
+    }
+
+    fn neighbor_spring_k(&self, iteration: usize) -> f32 {
+        let warm_up_iterations = (self.iterations as f32 * self.warm_up) as usize;
+        if iteration < warm_up_iterations {
+            self.neighbor_spring_k * iteration as f32 / warm_up_iterations as f32 // Warm up
+        } else if iteration < self.iterations / 2 {
+            self.neighbor_spring_k
+        } else {
+            0.0
+        }
+    }
+
+    fn orthogonal_factor(&self, iteration: usize) -> f32 {
+        if iteration < self.iterations / 2 {
+            0.0
+        } else {
+            self.orthogonal_factor
+        }
     }
 }
-const DESIRED_EXPANSION_PUSH: f32 = 4.0;
-const EXPANSION_SPRING_K: f32 = 0.2;
-const fn orth_factor(iterations: usize) -> f32 {
-    if iterations < ITERATIONS / 2 {
-        0.0
+
+pub(super) fn check(setting: &str, value: f32, range: RangeInclusive<f32>) -> Result<(), SimConfigError> {
+    if range.contains(&value) {
+        Ok(())
     } else {
-        0.8
+        Err(SimConfigError::OutOfRange { setting: setting.to_string(), value, min: *range.start(), max: *range.end() })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum SimConfigError {
+    /// There's no setting with this name
+    UnknownSetting(String),
+    /// The setting has to be between `min` and `max` (and a whole number, for the iterations)
+    OutOfRange {
+        setting: String,
+        value: f32,
+        min: f32,
+        max: f32
+    }
+}
+
+impl fmt::Display for SimConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SimConfigError::UnknownSetting(setting) => write!(f, "There's no setting named \"{}\"", setting),
+            SimConfigError::OutOfRange { setting, value, min, max } => write!(f, "\"{}\" has to be between {} and {}, not {}", setting, min, max, value)
+        }
     }
 }
-const DELTA_TIME: f32 = 0.10;
 
 type EntityID = usize;
 
+/// Runs the simulation to completion, with the config from the options or else the graph's.
+/// If the options have the previous placements, only the new entities are placed.
+pub struct ForceDirected;
+
+impl Placer for ForceDirected {
+    fn place(&self, graph: &Graph, options: &PlacerOptions) -> GridPlacements {
+        let config = sim_config(graph, options);
+        let mut sim = Sim::new(graph, config);
+        if options.sizes.is_some() {
+            sim.set_sizes(&box_sizes(graph, options.sizes.as_ref()));
+        }
+        if let Some(seed) = options.seed {
+            sim.scatter(seed);
+        }
+
+        match &options.previous {
+            Some(previous) if sim.anchor(graph, previous) > 0 => sim.relax(),
+            _ => sim.run()
+        }
+
+        sim.build_grid()
+    }
+
+    fn keeps_previous(&self) -> bool {
+        true
+    }
+}
+
 #[derive(Clone, Debug, PartialEq)]
 pub struct Sim {
     highest_desired_dist: f32,
-    neighbors: HashMap<EntityID, HashMap<EntityID, f32>>,
-    nodes: Vec<SimNode>
+    neighbors: BTreeMap<EntityID, BTreeMap<EntityID, f32>>,
+    /// The supertypes of each entity, from inheritance relations
+    supertypes: BTreeMap<EntityID, Vec<EntityID>>,
+    nodes: Vec<SimNode>,
+    config: SimConfig
 }
 
 impl Sim {
-    pub fn new(graph: &Graph) -> Sim {
-        let angle_delta = 2.3999; // radians
+    pub fn new(graph: &Graph, config: SimConfig) -> Sim {
+        let mut direction = Vec2 { x: 1.0, y: 0.0 };
         let mut nodes: Vec<SimNode> = graph.entities.iter().enumerate().map(|(entity, _)| {
-            let (sin, cos) = (entity as f32 * angle_delta).sin_cos();
             let radius = 4.0 * (entity as f32).sqrt();
-            SimNode::new(entity, radius * cos, radius * sin)
+            let node = SimNode::new(entity, radius * direction.x, radius * direction.y);
+            direction = turn(direction);
+            node
         }).collect();
 
+        for (node, size) in nodes.iter_mut().zip(box_sizes(graph, None)) {
+            node.size = size;
+        }
+
         if let Some(sim_node) = nodes.first_mut() {
             sim_node.pinned = true;
         }
@@ -59,7 +237,7 @@ impl Sim {
             }
         }
 
-        let mut neighbors = HashMap::new();
+        let mut neighbors = BTreeMap::new();
 
         for relation in graph.relations.iter() {
             let relation_strength = {
@@ -68,103 +246,162 @@ impl Sim {
                 2.0 + text_strength + weight_strength
             };
 
-            let node_neighbors = neighbors.entry(relation.entity_1).or_insert_with(HashMap::new);
+            let node_neighbors = neighbors.entry(relation.entity_1).or_insert_with(BTreeMap::new);
             node_neighbors.insert(relation.entity_2, relation_strength);
 
-            let other_neighbors = neighbors.entry(relation.entity_2).or_insert_with(HashMap::new);
+            let other_neighbors = neighbors.entry(relation.entity_2).or_insert_with(BTreeMap::new);
             other_neighbors.insert(relation.entity_1, relation_strength);
         }
 
+        let mut supertypes: BTreeMap<EntityID, Vec<EntityID>> = BTreeMap::new();
+
+        for relation in graph.relations.iter() {
+            if let Arrow::Inheritance = relation.arrow_2 {
+                supertypes.entry(relation.entity_1).or_default().push(relation.entity_2);
+            }
+            if let Arrow::Inheritance = relation.arrow_1 {
+                supertypes.entry(relation.entity_2).or_default().push(relation.entity_1);
+            }
+        }
+
         let highest_desired_dist = neighbors.values()
-            .flat_map(HashMap::values)
+            .flat_map(BTreeMap::values)
             .map(|&dist| dist * 2.0)
             .fold(3.0, f32::max);
 
         for entity_id in nodes.iter().enumerate().map(|(entity_id, _)| entity_id) {
-            neighbors.entry(entity_id).or_insert_with(HashMap::new);
+            neighbors.entry(entity_id).or_insert_with(BTreeMap::new);
         }
 
-        Sim { nodes, neighbors, highest_desired_dist }
+        Sim { nodes, neighbors, supertypes, highest_desired_dist, config }
+    }
+
+    /// The buckets of the nodes being simulated. Unrelated nodes only push each other
+    /// when they're closer than the expansion push, so that's how big the cells are.
+    fn buckets(&self, num_nodes: usize) -> Buckets {
+        Buckets::new(self.nodes.iter().take(num_nodes).map(|node| node.pos), self.config.expansion_push)
     }
 
-    fn step(&mut self, iteration: usize, num_nodes: usize) {
+    /// Steps the simulation, and moves the nodes that moved to their new buckets
+    fn step(&mut self, iteration: usize, num_nodes: usize, buckets: &mut Buckets) {
+        let before: Vec<Vec2> = self.nodes.iter().take(num_nodes).map(|node| node.pos).collect();
+        self.step_with_buckets(iteration, num_nodes, buckets);
+        for (node, (&from, to)) in before.iter().zip(self.nodes.iter()).enumerate() {
+            buckets.moved(node, from, to.pos);
+        }
+    }
+
+    fn step_with_buckets(&mut self, iteration: usize, num_nodes: usize, buckets: &Buckets) {
+        let config = &self.config;
         let mut buffer = self.nodes.clone();
         for node in buffer.iter_mut() {
             if node.pinned { continue }
-            node.pos += node.vel * DELTA_TIME;
-            node.vel *= 0.95; // damping
+            node.pos += node.vel * config.delta_time;
+            node.vel *= config.damping;
         }
         for node in buffer.iter_mut().take(num_nodes) {
             if node.pinned { continue }
-            for other in self.nodes.iter().take(num_nodes) {
-                if other.entity_id == node.entity_id { continue }
+            let Some(neighbors) = self.neighbors.get(&node.entity_id) else { continue };
+
+            for (&other, &desired_dist) in neighbors.iter() {
+                if other >= num_nodes || other == node.entity_id { continue }
+                let other = &self.nodes[other];
+
+                let acceleration = self.pull(node, other, desired_dist, iteration);
+                node.vel += if other.pinned { acceleration * 2.0 } else { acceleration };
+            }
 
+            // Only the nodes that aren't neighbors push, and only the close ones do
+            for other in buckets.near(node.pos) {
+                let other = &self.nodes[other];
                 let offset = other.pos - node.pos;
-                if let Some(neighbors) = self.neighbors.get(&node.entity_id) {
-                    let acceleration = if let Some(&desired_dist) = neighbors.get(&other.entity_id) {
-                        let spring_constant = neighbor_spring_k(iteration);
-                        
-                        let pull = {
-                            if let Some(normalized) = offset.chess_normalized() {
-                                let equilibrium = normalized * desired_dist;
-                                (offset - equilibrium) * spring_constant * DELTA_TIME
-                            } else {
-                                Vec2 { x: 0.0, y: 0.0 }
-                            }
-                        };
-
-                        let align = {
-                            // Check which spot next to them we are closest to and try to go there
-                            let (mut closest_spot, mut closest_squared_length) = (Vec2 { x: f32::INFINITY, y: f32::INFINITY }, f32::INFINITY);
-                            for spot_offset in [
-                                Vec2 { x: 0.0, y: 1.0 },
-                                Vec2 { x: 0.0, y: -1.0 },
-                                Vec2 { x: 1.0, y: 0.0 },
-                                Vec2 { x: -1.0, y: 0.0 },
-                                Vec2 { x: 1.0, y: 1.0 },
-                                Vec2 { x: 1.0, y: -1.0 },
-                                Vec2 { x: -1.0, y: 1.0 },
-                                Vec2 { x: -1.0, y: -1.0 },
-                            ] {
-                                let spot_pos = other.pos + spot_offset * desired_dist;
-                                let squared_dist = (spot_pos - node.pos).squared_length();
-                                if squared_dist < closest_squared_length {
-                                    closest_spot = spot_pos;
-                                    closest_squared_length = squared_dist;
-                                }
-                            }
-                            let to_orthogonal = closest_spot - node.pos;
-
-                            to_orthogonal * DELTA_TIME * orth_factor(iteration)
-                        };
-
-                        pull + align
-                    } else {
-                        let push = {
-                            if let Some(normalized) = offset.taxicab_normalized() {
-                                let desired_offset = normalized * DESIRED_EXPANSION_PUSH;
-                                if offset.chess_length() < desired_offset.chess_length() {
-                                    (offset - desired_offset) * DELTA_TIME * EXPANSION_SPRING_K
-                                } else {
-                                    Vec2 { x: 0.0, y: 0.0 }
-                                }
-                            } else {
-                                Vec2 { x: 0.0, y: 0.0 }
-                            }
-                        };
-
-                        push
-                    };
+                if offset.chess_length() >= config.expansion_push || other.entity_id == node.entity_id || neighbors.contains_key(&other.entity_id) { continue }
 
-                    if other.pinned {
-                        node.vel += acceleration * 2.0;
-                    } else {
-                        node.vel += acceleration;
+                let acceleration = self.push(offset);
+                node.vel += if other.pinned { acceleration * 2.0 } else { acceleration };
+            }
+        }
+        self.nodes = buffer;
+    }
+
+    /// How a neighbor pulls the node toward the right distance, toward a spot next to the
+    /// neighbor (on the right side of it, if one is a supertype of the other)
+    fn pull(&self, node: &SimNode, other: &SimNode, desired_dist: f32, iteration: usize) -> Vec2 {
+        let config = &self.config;
+        let offset = other.pos - node.pos;
+
+        let pull = {
+            if let Some(normalized) = offset.chess_normalized() {
+                let equilibrium = normalized * desired_dist;
+                (offset - equilibrium) * config.neighbor_spring_k(iteration) * config.delta_time
+            } else {
+                Vec2 { x: 0.0, y: 0.0 }
+            }
+        };
+
+            // Positive y is down, so a subtype should be below (+1) its supertype, and vice versa
+            let side = if self.is_supertype(other.entity_id, node.entity_id) {
+                1.0
+            } else if self.is_supertype(node.entity_id, other.entity_id) {
+                -1.0
+            } else {
+                0.0
+            };
+
+            let align = {
+                // Check which spot next to them we are closest to and try to go there
+                let (mut closest_spot, mut closest_squared_length) = (Vec2 { x: f32::INFINITY, y: f32::INFINITY }, f32::INFINITY);
+                for spot_offset in [
+                    Vec2 { x: 0.0, y: 1.0 },
+                    Vec2 { x: 0.0, y: -1.0 },
+                    Vec2 { x: 1.0, y: 0.0 },
+                    Vec2 { x: -1.0, y: 0.0 },
+                    Vec2 { x: 1.0, y: 1.0 },
+                    Vec2 { x: 1.0, y: -1.0 },
+                    Vec2 { x: -1.0, y: 1.0 },
+                    Vec2 { x: -1.0, y: -1.0 },
+                ] {
+                    if side != 0.0 && spot_offset.y != side {
+                        continue; // Keep to the correct side of the hierarchy
+                    }
+                    let spot_pos = other.pos + spot_offset * desired_dist;
+                    let squared_dist = (spot_pos - node.pos).squared_length();
+                    if squared_dist < closest_squared_length {
+                        closest_spot = spot_pos;
+                        closest_squared_length = squared_dist;
                     }
                 }
+                let to_orthogonal = closest_spot - node.pos;
+
+                to_orthogonal * config.delta_time * config.orthogonal_factor(iteration)
+            };
+
+            let hierarchy = {
+                let gap = (node.pos.y - other.pos.y) * side;
+                if side != 0.0 && gap < config.hierarchy_gap {
+                    Vec2 { x: 0.0, y: side * (config.hierarchy_gap - gap) * config.hierarchy_spring_k * config.delta_time }
+                } else {
+                    Vec2 { x: 0.0, y: 0.0 }
+                }
+            };
+
+        pull + align + hierarchy
+    }
+
+    /// How a node that isn't a neighbor pushes away a node that's closer than the expansion push
+    fn push(&self, offset: Vec2) -> Vec2 {
+        let config = &self.config;
+        if let Some(normalized) = offset.taxicab_normalized() {
+            let desired_offset = normalized * config.expansion_push;
+            if offset.chess_length() < desired_offset.chess_length() {
+                return (offset - desired_offset) * config.delta_time * config.expansion_spring_k;
             }
         }
-        self.nodes = buffer;
+        Vec2 { x: 0.0, y: 0.0 }
+    }
+
+    fn is_supertype(&self, supertype: EntityID, subtype: EntityID) -> bool {
+        self.supertypes.get(&subtype).is_some_and(|supertypes| supertypes.contains(&supertype))
     }
 
     fn step_toward_grid(&mut self) {
@@ -175,42 +412,158 @@ impl Sim {
                 node.pos = grid_pos;
                 continue;
             }
-            node.pos += (grid_pos - node.pos) * DELTA_TIME;
+            node.pos += (grid_pos - node.pos) * self.config.delta_time;
+        }
+    }
+
+    /// Moves every node that isn't pinned a little from where it starts, by an amount
+    /// that only depends on the seed
+    pub fn scatter(&mut self, seed: u64) {
+        let mut rng = SplitMix64(seed);
+        for node in self.nodes.iter_mut() {
+            let offset = Vec2 { x: rng.next_signed(), y: rng.next_signed() } * SCATTER;
+            if node.pinned { continue }
+            node.pos += offset;
+        }
+    }
+
+    /// Replaces the estimated sizes of the boxes, e.g. with the ones the client measured
+    pub fn set_sizes(&mut self, sizes: &[Vec2]) {
+        for (node, &size) in self.nodes.iter_mut().zip(sizes.iter()) {
+            node.size = size;
         }
     }
 
     fn keep_nodes_apart(&mut self) {
+        // Two boxes can only overlap if their centers are closer than the biggest box (and the gap)
+        let cell_size = self.nodes.iter()
+            .map(|node| node.size.x.max(node.size.y))
+            .fold(0.0, f32::max) + BOX_GAP;
+        self.keep_nodes_apart_with_cells(cell_size);
+    }
+
+    /// Pushes overlapping boxes apart the shorter way. They're pushed by whole grid
+    /// cells, so nodes that are on the grid stay on it.
+    /// Goes through the pairs of nodes in the same order as checking every pair,
+    /// but skips the nodes that aren't in a cell next to the first node's
+    fn keep_nodes_apart_with_cells(&mut self, cell_size: f32) {
+        let mut buckets = Buckets::new(self.nodes.iter().map(|node| node.pos), cell_size);
+
         for i in 0..self.nodes.len() {
-            for j in (i+1)..self.nodes.len() {
-                let offset = self.nodes[j].pos - self.nodes[i].pos;
-                if offset.squared_length() < 0.5625 {
-                    let push = offset.normalized().unwrap_or(Vec2 { x: 0.0, y: 0.0 }) * 0.75;
+            let mut last = i;
+            while let Some(j) = buckets.near(self.nodes[i].pos).filter(|&j| j > last).min() {
+                last = j;
+                let (i_pos, j_pos) = (self.nodes[i].pos, self.nodes[j].pos);
+                let offset = j_pos - i_pos;
+                let reach = (self.nodes[i].size + self.nodes[j].size) / 2.0 + Vec2 { x: BOX_GAP, y: BOX_GAP };
+                let overlap = Vec2 { x: reach.x - offset.x.abs(), y: reach.y - offset.y.abs() };
+                if overlap.x > 0.0 && overlap.y > 0.0 {
+                    let push = if overlap.x <= overlap.y {
+                        Vec2 { x: overlap.x.ceil() * offset.x.signum(), y: 0.0 }
+                    } else {
+                        Vec2 { x: 0.0, y: overlap.y.ceil() * offset.y.signum() }
+                    };
                     if self.nodes[i].pinned && self.nodes[j].pinned {
                         continue;
                     } else if self.nodes[i].pinned {
-                        self.nodes[j].pos += push * 2.0;
+                        self.nodes[j].pos += push;
                     } else if self.nodes[j].pinned {
-                        self.nodes[i].pos -= push * 2.0;
-                    } else {
                         self.nodes[i].pos -= push;
-                        self.nodes[j].pos += push;
+                    } else {
+                        // Split the push between them, without leaving either half a cell off
+                        let half = Vec2 { x: (push.x / 2.0).trunc(), y: (push.y / 2.0).trunc() };
+                        self.nodes[i].pos -= half;
+                        self.nodes[j].pos += push - half;
                     }
+                    buckets.moved(i, i_pos, self.nodes[i].pos);
+                    buckets.moved(j, j_pos, self.nodes[j].pos);
                 }
             }
         }
     }
 
+    /// Puts the entities that were placed before back where they were, and keeps
+    /// them there. New entities start out next to the anchored entities they're
+    /// related to. Returns how many entities were anchored.
+    pub fn anchor(&mut self, graph: &Graph, previous: &HashMap<String, Vec2>) -> usize {
+        let mut anchored = vec![false; self.nodes.len()];
+
+        for (entity_id, entity) in graph.entities.iter().enumerate() {
+            // Pins still win over where the entity was before
+            if graph.pins.contains_key(&entity_id) { continue }
+            if let Some(&pos) = previous.get(&entity.name) && let Some(sim_node) = self.nodes.get_mut(entity_id) {
+                sim_node.pos = pos;
+                sim_node.pinned = true;
+                anchored[entity_id] = true;
+            }
+        }
+
+        let anchored_count = anchored.iter().filter(|&&anchored| anchored).count();
+        if anchored_count == 0 {
+            return 0;
+        }
+
+        // The first node is only pinned to hold the layout in place, which the anchored nodes do now
+        if !anchored[0] && !graph.pins.contains_key(&0) {
+            self.nodes[0].pinned = false;
+        }
+
+        let mut direction = Vec2 { x: 1.0, y: 0.0 };
+        for entity_id in 0..self.nodes.len() {
+            let offset = direction * 2.0;
+            direction = turn(direction);
+            if self.nodes[entity_id].pinned { continue }
+
+            let anchored_neighbors: Vec<Vec2> = self.neighbors[&entity_id].keys()
+                .filter(|&&neighbor| anchored[neighbor])
+                .map(|&neighbor| self.nodes[neighbor].pos)
+                .collect();
+            if anchored_neighbors.is_empty() { continue }
+
+            let center = anchored_neighbors.iter().fold(Vec2 { x: 0.0, y: 0.0 }, |sum, &pos| sum + pos) / anchored_neighbors.len() as f32;
+            self.nodes[entity_id].pos = center + offset;
+        }
+
+        anchored_count
+    }
+
+    /// A shorter run that moves every free node at once, for when most of the
+    /// nodes are anchored and only a few new ones have to find their place
+    pub fn relax(&mut self) {
+        let config = self.config.clone();
+        self.config.iterations = (config.iterations / RELAXATION_DIVISOR).max(1);
+
+        let mut buckets = self.buckets(self.nodes.len());
+        for iteration in 0..self.config.iterations {
+            self.step(iteration, self.nodes.len(), &mut buckets);
+        }
+        for _ in 0..100 {
+            self.step_toward_grid();
+            self.keep_nodes_apart();
+        }
+
+        self.config = config;
+    }
+
     pub fn run(&mut self) {
+        let config = self.config.clone();
+
         // TODO: Skip pinned nodes
-        for nodes in 2..self.nodes.len() + 1 {
-            for iteration in 0..ITERATIONS {
-                self.step(iteration, nodes);
+        for (nodes, iterations) in runs(self.nodes.len(), config.iterations) {
+            self.config.iterations = iterations;
+            let mut buckets = self.buckets(nodes);
+            for iteration in 0..iterations {
+                self.step(iteration, nodes, &mut buckets);
             }
-            for _ in 0..100 {
+            // A shortened run only has to settle before the next batch, which the last run snaps to the grid anyway
+            let rounds = if iterations < config.iterations { 100 / RELAXATION_DIVISOR } else { 100 };
+            for _ in 0..rounds {
                 self.step_toward_grid();
                 self.keep_nodes_apart();
             }
         }
+
+        self.config = config;
     }
 
     #[must_use]
@@ -222,7 +575,89 @@ impl Sim {
             }
         ).collect();
 
-        GridPlacements { nodes }
+        GridPlacements { nodes, edges: Vec::new() }
+    }
+}
+
+/// How many nodes each run of the simulation moves, and for how many iterations.
+/// Small graphs get a full run for every node that's added. Bigger ones get several
+/// nodes at a time, in at most `MAX_BATCHES` runs, and only the last of those is a full one.
+fn runs(num_nodes: usize, iterations: usize) -> impl Iterator<Item=(usize, usize)> {
+    let added = num_nodes.saturating_sub(1);
+    let batch_size = added.div_ceil(MAX_BATCHES).max(1);
+    let batches = added.div_ceil(batch_size);
+    let shortened = if batch_size > 1 { (iterations / RELAXATION_DIVISOR).max(1) } else { iterations };
+
+    (1..=batches).map(move |batch| {
+        let nodes = (1 + batch * batch_size).min(num_nodes);
+        (nodes, if batch == batches { iterations } else { shortened })
+    })
+}
+
+/// Turns the direction by the golden angle
+fn turn(direction: Vec2) -> Vec2 {
+    Vec2 {
+        x: direction.x * GOLDEN_TURN.x - direction.y * GOLDEN_TURN.y,
+        y: direction.x * GOLDEN_TURN.y + direction.y * GOLDEN_TURN.x
+    }
+}
+
+/// A small random number generator, so that seeding doesn't need a dependency
+/// (or anything that could differ between platforms)
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// A number from -1 up to 1. Only 24 bits are used, so it converts to `f32` exactly.
+    fn next_signed(&mut self) -> f32 {
+        (self.next() >> 40) as f32 / (1 << 23) as f32 - 1.0
+    }
+}
+
+/// Sorts nodes into square cells by their position. Anything closer to a point than
+/// the size of a cell is in the point's cell or one of the eight around it.
+struct Buckets {
+    cell_size: f32,
+    cells: BTreeMap<(i32, i32), Vec<EntityID>>
+}
+
+impl Buckets {
+    fn new(positions: impl Iterator<Item=Vec2>, cell_size: f32) -> Buckets {
+        let mut buckets = Buckets { cell_size, cells: BTreeMap::new() };
+        for (node, pos) in positions.enumerate() {
+            buckets.cells.entry(buckets.cell(pos)).or_default().push(node);
+        }
+        buckets
+    }
+
+    fn cell(&self, pos: Vec2) -> (i32, i32) {
+        ((pos.x / self.cell_size).floor() as i32, (pos.y / self.cell_size).floor() as i32)
+    }
+
+    /// The nodes in the point's cell and the cells around it, a cell at a time
+    fn near(&self, pos: Vec2) -> impl Iterator<Item=EntityID> + '_ {
+        let (x, y) = self.cell(pos);
+        // The cells of each column are next to each other in the map
+        (-1..=1)
+            .flat_map(move |dx| self.cells.range((x.saturating_add(dx), y.saturating_sub(1))..=(x.saturating_add(dx), y.saturating_add(1))))
+            .flat_map(|(_, nodes)| nodes.iter().copied())
+    }
+
+    fn moved(&mut self, node: EntityID, from: Vec2, to: Vec2) {
+        let (from, to) = (self.cell(from), self.cell(to));
+        if from == to { return }
+
+        if let Some(nodes) = self.cells.get_mut(&from) {
+            nodes.retain(|&other| other != node);
+        }
+        self.cells.entry(to).or_default().push(node);
     }
 }
 
@@ -231,6 +666,8 @@ pub struct SimNode {
     entity_id: usize,
     pos: Vec2,
     vel: Vec2,
+    /// The size of the entity's box, in grid units
+    size: Vec2,
     pinned: bool,
 }
 
@@ -240,6 +677,7 @@ impl SimNode {
             entity_id,
             pos: Vec2 { x, y },
             vel: Vec2 { x: 0.0, y: 0.0 },
+            size: Vec2 { x: 0.0, y: 0.0 },
             pinned: false,
         }
     }
@@ -247,17 +685,36 @@ impl SimNode {
 
 #[cfg(test)]
 pub mod tests {
-    use crate::domain_model::graph::test::*;
+    use crate::{domain_model::{graph::{Relation, test::*}, parser::parse_graph}, image_generation::{layout::metrics::measure, render::{entity_box_size, GRID_SIZE}}};
 
     use super::*;
 
+    /// How long a relation can get before the layout counts as spread out, in grid units
+    const MAX_EDGE_LENGTH: f32 = 8.0;
+
     #[test]
     fn generation_is_consistent() {
         let mut graph = Graph::new();
         graph.entities = vec![dummy_entity(), dummy_entity(), dummy_entity()];
         graph.relations = vec![dummy_relation(0, 2), dummy_relation(1, 2)];
 
-        assert_eq!(Sim::new(&graph), Sim::new(&graph));
+        assert_eq!(Sim::new(&graph, SimConfig::default()), Sim::new(&graph, SimConfig::default()));
+    }
+
+    #[test]
+    fn seeds_give_the_same_placements_everywhere() {
+        // The reference output of SplitMix64
+        assert_eq!(SplitMix64(0).next(), 0xE220_A839_7B1D_CDAF);
+
+        let mut graph = Graph::new();
+        graph.entities = vec![dummy_entity(), dummy_entity(), dummy_entity(), dummy_entity()];
+        graph.relations = vec![dummy_relation(0, 1), dummy_relation(1, 2), dummy_relation(2, 3), dummy_relation(3, 0)];
+
+        let grid = ForceDirected.place(&graph, &PlacerOptions { seed: Some(42), ..PlacerOptions::default() });
+        let positions: Vec<Vec2> = grid.nodes.iter().map(|node| node.position).collect();
+
+        // If this changes, the placer changed. tests/determinism.rs checks that wasm places them the same
+        assert_eq!(positions, vec![Vec2 { x: 0.0, y: 0.0 }, Vec2 { x: -2.0, y: -2.0 }, Vec2 { x: 1.0, y: -4.0 }, Vec2 { x: 2.0, y: -2.0 }]);
     }
 
     #[test]
@@ -272,7 +729,7 @@ pub mod tests {
             dummy_relation(4, 2)
         ];
 
-        let mut sim = Sim::new(&graph);
+        let mut sim = Sim::new(&graph, SimConfig::default());
         sim.run();
 
         let grid = sim.build_grid();
@@ -289,6 +746,32 @@ pub mod tests {
         }
     }
 
+    #[test]
+    fn supertypes_are_above_subtypes() {
+        let mut graph = Graph::new();
+        graph.entities = vec![dummy_entity(), dummy_entity(), dummy_entity(), dummy_entity(), dummy_entity()];
+        let inheritance = |subtype, supertype| Relation { arrow_2: Arrow::Inheritance, ..dummy_relation(subtype, supertype) };
+        graph.relations = vec![
+            inheritance(0, 1),
+            inheritance(2, 1),
+            inheritance(3, 0),
+            dummy_relation(3, 4),
+            Relation { arrow_1: Arrow::Inheritance, arrow_2: Arrow::None, ..dummy_relation(4, 2) },
+        ];
+
+        let mut sim = Sim::new(&graph, SimConfig::default());
+        sim.run();
+
+        let grid = sim.build_grid();
+
+        println!("{}", grid);
+
+        for (subtype, supertype) in [(0, 1), (2, 1), (3, 0), (2, 4)] {
+            let (subtype_pos, supertype_pos) = (grid.nodes[subtype].position, grid.nodes[supertype].position);
+            assert!(supertype_pos.y < subtype_pos.y, "Entity {} should be above entity {}! ({:?} vs {:?})", supertype, subtype, supertype_pos, subtype_pos);
+        }
+    }
+
     #[test]
     fn nodes_stay_together() {
         let mut graph = Graph::new();
@@ -307,12 +790,18 @@ pub mod tests {
             dummy_relation(1, 7), 
         ];
 
-        let mut sim = Sim::new(&graph);
+        let mut sim = Sim::new(&graph, SimConfig::default());
         sim.run();
 
         let grid = sim.build_grid();
 
-        println!("{}", grid);
+        let metrics = measure(&graph, &grid, None);
+
+        println!("{}\n{:?}", grid, metrics);
+
+        assert_eq!(metrics.node_overlaps, 0, "Some entities overlap");
+        assert!(metrics.max_edge_length <= MAX_EDGE_LENGTH, "A relation is {} units long", metrics.max_edge_length);
+        assert!(metrics.edge_crossings <= 1, "The relations cross {} times", metrics.edge_crossings);
 
         for node in grid.nodes.iter() {
             let mut farthest_distance = f32::INFINITY;
@@ -325,4 +814,228 @@ pub mod tests {
             assert!(farthest_distance <= 3.0, "Entity {} is too far away from everything! ({} units)", node.entity, farthest_distance);
         }
     }
+
+    #[test]
+    fn config_is_validated() {
+        assert_eq!(SimConfig::default().validate(), Ok(()));
+
+        let mut config = SimConfig::default();
+        assert_eq!(config.set("delta time", 0.05), Ok(()));
+        assert_eq!(config.delta_time, 0.05);
+        assert_eq!(config.set("iterations", 200.0), Ok(()));
+        assert_eq!(config.iterations, 200);
+
+        assert_eq!(config.set("gravity", 1.0), Err(SimConfigError::UnknownSetting("gravity".to_string())));
+        assert!(config.set("damping", 1.5).is_err());
+        assert!(config.set("delta time", f32::NAN).is_err());
+        assert!(config.set("iterations", 2.5).is_err());
+        assert!(config.set("iterations", -1.0).is_err());
+        assert!(SimConfig { expansion_push: 0.0, ..SimConfig::default() }.validate().is_err());
+
+        // Invalid values don't change anything
+        assert_eq!(config, SimConfig { delta_time: 0.05, iterations: 200, ..SimConfig::default() });
+    }
+
+    #[test]
+    fn fewer_iterations_still_keep_nodes_apart() {
+        let mut graph = Graph::new();
+        graph.entities = vec![dummy_entity(), dummy_entity(), dummy_entity(), dummy_entity()];
+        graph.relations = vec![dummy_relation(0, 1), dummy_relation(0, 2), dummy_relation(0, 3)];
+
+        let config = SimConfig { iterations: 50, ..SimConfig::default() };
+        let mut sim = Sim::new(&graph, config);
+        sim.run();
+
+        let grid = sim.build_grid();
+
+        println!("{}", grid);
+
+        for node in grid.nodes.iter() {
+            for other in grid.nodes.iter() {
+                if node.entity == other.entity { continue }
+                assert!(node.position != other.position, "Entities {} and {} are on the same point on the grid! ({:?})", node.entity, other.entity, node.position);
+            }
+        }
+    }
+
+    /// A graph with enough unrelated nodes close together for the buckets to matter
+    fn crowded_graph() -> Graph {
+        let mut graph = Graph::new();
+        graph.entities = (0..40).map(|_| dummy_entity()).collect();
+        graph.relations = (1..40).filter(|entity| entity % 3 != 0).map(|entity| dummy_relation(entity / 2, entity)).collect();
+        graph.pins.insert(7, Vec2 { x: 1.0, y: 1.0 });
+        graph
+    }
+
+    #[test]
+    fn buckets_match_checking_every_node() {
+        let mut sim = Sim::new(&crowded_graph(), SimConfig::default());
+
+        for iteration in 0..200 {
+            let mut checking_everything = sim.clone();
+            // With infinitely big cells, every node is in the same cell
+            let one_cell = Buckets::new(checking_everything.nodes.iter().map(|node| node.pos), f32::INFINITY);
+            checking_everything.step_with_buckets(iteration, 40, &one_cell);
+
+            let mut buckets = sim.buckets(40);
+            sim.step(iteration, 40, &mut buckets);
+
+            // The forces are added up in another order, so they can only differ by rounding
+            for (node, other) in sim.nodes.iter().zip(checking_everything.nodes.iter()) {
+                let close = (node.pos - other.pos).squared_length() < 1e-8 && (node.vel - other.vel).squared_length() < 1e-8;
+                assert!(close, "The simulations split up at iteration {} ({:?} and {:?})", iteration, node, other);
+            }
+            // The buckets that were kept up to date have the same nodes as new ones
+            assert_eq!(bucket_contents(&buckets), bucket_contents(&sim.buckets(40)), "The buckets split up at iteration {}", iteration);
+
+            // Without the rounding adding up over the iterations
+            sim = checking_everything;
+        }
+    }
+
+    fn bucket_contents(buckets: &Buckets) -> BTreeMap<(i32, i32), Vec<EntityID>> {
+        buckets.cells.iter()
+            .filter(|(_, nodes)| !nodes.is_empty())
+            .map(|(&cell, nodes)| {
+                let mut nodes = nodes.clone();
+                nodes.sort();
+                (cell, nodes)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn buckets_keep_the_same_nodes_apart() {
+        let mut sim = Sim::new(&crowded_graph(), SimConfig::default());
+        // Squeeze everything together, so that lots of nodes overlap
+        for node in sim.nodes.iter_mut() {
+            node.pos *= 0.1;
+        }
+
+        for _ in 0..20 {
+            let mut checking_everything = sim.clone();
+            checking_everything.keep_nodes_apart_with_cells(f32::INFINITY);
+
+            sim.keep_nodes_apart();
+
+            assert_eq!(sim, checking_everything);
+        }
+    }
+
+    fn placements_by_name(graph: &Graph, grid: &GridPlacements) -> HashMap<String, Vec2> {
+        grid.nodes.iter().map(|node| (graph.entities[node.entity].name.clone(), node.position)).collect()
+    }
+
+    #[test]
+    fn previous_placements_are_kept() {
+        let Ok(before) = parse_graph("Customer-places->Order\nOrder<#>-Line Item\nLine Item-Product") else { panic!("Parsing failed") };
+        let previous = placements_by_name(&before, &ForceDirected.place(&before, &PlacerOptions::default()));
+
+        // A new entity shows up in the middle of the input
+        let Ok(after) = parse_graph("Customer-places->Order\nOrder-Invoice\nOrder<#>-Line Item\nLine Item-Product") else { panic!("Parsing failed") };
+        let options = PlacerOptions { previous: Some(previous.clone()), ..PlacerOptions::default() };
+        let grid = ForceDirected.place(&after, &options);
+
+        println!("{}", grid);
+
+        let placed = placements_by_name(&after, &grid);
+        for (name, position) in previous.iter() {
+            assert_eq!(placed[name], *position, "{} moved", name);
+        }
+
+        let invoice = placed["Invoice"];
+        assert!(previous.values().all(|&position| position != invoice), "Invoice was placed on top of another entity ({:?})", invoice);
+        assert!((invoice - placed["Order"]).taxicab_length() <= 4.0, "Invoice is too far from Order ({:?} vs {:?})", invoice, placed["Order"]);
+    }
+
+    #[test]
+    fn pins_win_over_previous_placements() {
+        let Ok(graph) = parse_graph("A-B\nB-C\npin B: 5 5") else { panic!("Parsing failed") };
+        let previous = HashMap::from([("A".to_string(), Vec2 { x: 0.0, y: 0.0 }), ("B".to_string(), Vec2 { x: 2.0, y: 0.0 })]);
+        let options = PlacerOptions { previous: Some(previous), ..PlacerOptions::default() };
+
+        let grid = ForceDirected.place(&graph, &options);
+
+        assert_eq!(grid.position_of(0), Some(Vec2 { x: 0.0, y: 0.0 }));
+        assert_eq!(grid.position_of(1), Some(Vec2 { x: 5.0, y: 5.0 }));
+        assert!(grid.position_of(2).is_some_and(|position| position != Vec2 { x: 0.0, y: 0.0 } && position != Vec2 { x: 5.0, y: 5.0 }));
+    }
+
+    /// Whether the boxes of two placed nodes overlap, with the sizes in grid units
+    fn boxes_overlap(a: (Vec2, Vec2), b: (Vec2, Vec2)) -> bool {
+        let ((a_pos, a_size), (b_pos, b_size)) = (a, b);
+        (a_pos.x - b_pos.x).abs() < (a_size.x + b_size.x) / 2.0 && (a_pos.y - b_pos.y).abs() < (a_size.y + b_size.y) / 2.0
+    }
+
+    #[test]
+    fn long_names_dont_overlap() {
+        let Ok(graph) = parse_graph("Customer Loyalty Program Enrollment-Customer\nCustomer Loyalty Program Enrollment-Reward Points Transaction Ledger\nCustomer-Order\nOrder-Reward Points Transaction Ledger") else { panic!("Parsing failed") };
+
+        let mut sim = Sim::new(&graph, SimConfig::default());
+        sim.run();
+        let boxes: Vec<(Vec2, Vec2)> = sim.nodes.iter().map(|node| (node.pos, node.size)).collect();
+
+        println!("{}", sim.build_grid());
+
+        for (i, a) in boxes.iter().enumerate() {
+            for (j, b) in boxes.iter().enumerate().skip(i + 1) {
+                assert!(!boxes_overlap(*a, *b), "The boxes of {} and {} overlap ({:?} and {:?})", graph.entities[i].name, graph.entities[j].name, a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn big_graphs_take_a_few_runs() {
+        assert_eq!(runs(5, 1000).collect::<Vec<_>>(), vec![(2, 1000), (3, 1000), (4, 1000), (5, 1000)]);
+        assert_eq!(runs(1, 1000).count(), 0);
+
+        for num_nodes in [17, 18, 300, 1000] {
+            let runs: Vec<(usize, usize)> = runs(num_nodes, 1000).collect();
+            let steps: usize = runs.iter().map(|(_, iterations)| iterations).sum();
+            println!("{} nodes take {} runs and {} steps", num_nodes, runs.len(), steps);
+
+            assert!(steps <= MAX_BATCHES * 1000, "{} nodes take {} steps", num_nodes, steps);
+            if num_nodes >= 300 {
+                assert!(steps <= 5 * 1000, "{} nodes take {} steps, more than five full runs", num_nodes, steps);
+            }
+            assert!(runs.windows(2).all(|pair| pair[0].0 < pair[1].0));
+            assert_eq!(runs.last(), Some(&(num_nodes, 1000)));
+        }
+    }
+
+    #[test]
+    fn big_graphs_dont_overlap() {
+        // 300 entities in a chain, with 100 more relations across it, like a big enterprise model
+        let chain = (1..300).map(|i| format!("\"E{}\"-\"E{}\"", i - 1, i));
+        let across = (0..100).map(|i| format!("\"E{}\"-\"E{}\"", i * 3, (i * 3 + 50) % 300));
+        let input = chain.chain(across).collect::<Vec<_>>().join("\n");
+        let Ok(graph) = parse_graph(&input) else { panic!("Parsing failed") };
+        assert_eq!((graph.entities.len(), graph.relations.len()), (300, 399));
+
+        let start = std::time::Instant::now();
+        let mut sim = Sim::new(&graph, SimConfig::default());
+        sim.run();
+        println!("Placed {} entities in {:?}", graph.entities.len(), start.elapsed());
+
+        let boxes: Vec<(Vec2, Vec2)> = sim.nodes.iter().map(|node| (node.pos, node.size)).collect();
+        for (i, a) in boxes.iter().enumerate() {
+            assert!(!boxes.iter().skip(i + 1).any(|b| boxes_overlap(*a, *b)), "The box of {} overlaps another", graph.entities[i].name);
+        }
+    }
+
+    #[test]
+    fn measured_sizes_replace_the_estimates() {
+        let Ok(graph) = parse_graph("A-B") else { panic!("Parsing failed") };
+        // B is measured as much wider than its one letter name
+        let sizes = HashMap::from([("B".to_string(), Vec2 { x: 500.0, y: 40.0 })]);
+        let options = PlacerOptions { sizes: Some(sizes), ..PlacerOptions::default() };
+
+        let grid = ForceDirected.place(&graph, &options);
+
+        println!("{}", grid);
+
+        let (a, b) = (grid.position_of(0).unwrap(), grid.position_of(1).unwrap());
+        let (a_size, b_size) = (entity_box_size(&graph.entities[0]) / GRID_SIZE, Vec2 { x: 10.0, y: 0.8 });
+        assert!(!boxes_overlap((a, a_size), (b, b_size)), "A ({:?}) is inside of B ({:?})", a, b);
+    }
 }
\ No newline at end of file
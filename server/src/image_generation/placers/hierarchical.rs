@@ -0,0 +1,435 @@
+//! This placer puts entities in layers from top to bottom, following the direction
+//! of their relations (the Sugiyama method), so that process and ownership
+//! diagrams read from top to bottom.
+//! 1. Cycles are broken by flipping the relations that close them
+//! 2. Every entity goes one layer below the lowest entity above it
+//! 3. Relations that skip layers get a dummy node in every layer they cross
+//! 4. The layers are reordered to remove crossings, with the barycenter heuristic
+//! 5. Every node is moved toward the nodes it's connected to, keeping the order
+//!
+//! Nodes are spaced by the size of their boxes, so long names and long attribute
+//! lists get the room they need.
+//!
+//! Pinned entities keep their positions, and the rest of the layout moves along
+//! with the first of them.
+
+use crate::{domain_model::graph::{Arrow, EntityIndex, Graph, Relation}, image_generation::placers::{box_sizes, GridNode, GridPlacements, Placer, PlacerOptions, Vec2}};
+
+/// Grid units kept between the tallest boxes of neighboring layers
+const LAYER_GAP: f32 = 2.0;
+/// Grid units kept between the boxes of neighboring nodes in a layer
+const NODE_GAP: f32 = 2.0;
+/// How many times the layers are swept (down, then up) to remove crossings
+const ORDERING_SWEEPS: usize = 12;
+/// How many times the layers are swept (down, then up) to straighten relations
+const POSITIONING_SWEEPS: usize = 4;
+
+/// A node of the layered graph. The first nodes are the entities (with the same
+/// indices), and the rest are the dummy nodes of relations that skip layers.
+type NodeID = usize;
+
+pub struct Hierarchical;
+
+impl Placer for Hierarchical {
+    fn place(&self, graph: &Graph, options: &PlacerOptions) -> GridPlacements {
+        place(graph, &box_sizes(graph, options.sizes.as_ref()))
+    }
+}
+
+/// Places the graph, with `sizes` being the size of each entity's box in grid units
+pub fn place(graph: &Graph, sizes: &[Vec2]) -> GridPlacements {
+    let entity_count = graph.entities.len();
+
+    let mut edges: Vec<(NodeID, NodeID)> = graph.relations.iter()
+        .map(direction)
+        .filter(|(upper, lower)| upper != lower)
+        .collect();
+    edges.sort();
+    edges.dedup();
+
+    break_cycles(entity_count, &mut edges);
+    let mut layer_of = assign_layers(entity_count, &edges);
+
+    // Relations that skip layers are split up, so that every edge goes from one layer to the next
+    let mut short_edges = Vec::new();
+    for (upper, lower) in edges {
+        let mut previous = upper;
+        for layer in (layer_of[upper] + 1)..layer_of[lower] {
+            let dummy = layer_of.len();
+            layer_of.push(layer);
+            short_edges.push((previous, dummy));
+            previous = dummy;
+        }
+        short_edges.push((previous, lower));
+    }
+
+    let layer_count = layer_of.iter().max().map_or(0, |&layer| layer + 1);
+    let mut layers: Vec<Vec<NodeID>> = vec![Vec::new(); layer_count];
+    for (node, &layer) in layer_of.iter().enumerate() {
+        layers[layer].push(node);
+    }
+
+    let mut upper_neighbors = vec![Vec::new(); layer_of.len()];
+    let mut lower_neighbors = vec![Vec::new(); layer_of.len()];
+    for &(upper, lower) in short_edges.iter() {
+        upper_neighbors[lower].push(upper);
+        lower_neighbors[upper].push(lower);
+    }
+
+    order_layers(&mut layers, &upper_neighbors, &lower_neighbors);
+    // Dummy nodes don't have a box, so they only need the gap
+    let widths: Vec<f32> = (0..layer_of.len()).map(|node| sizes.get(node).map_or(0.0, |size| size.x)).collect();
+    let x = position_nodes(&layers, &widths, &upper_neighbors, &lower_neighbors);
+
+    // Every layer goes as far below the one above it as their tallest boxes need
+    let heights: Vec<f32> = layers.iter()
+        .map(|layer| layer.iter().filter_map(|&node| sizes.get(node)).map(|size| size.y).fold(0.0, f32::max))
+        .collect();
+    let mut layer_y = vec![0.0; layer_count];
+    for layer in 1..layer_count {
+        layer_y[layer] = layer_y[layer - 1] + ((heights[layer - 1] + heights[layer]) / 2.0 + LAYER_GAP).ceil();
+    }
+
+    let mut positions: Vec<Vec2> = (0..entity_count)
+        .map(|entity| Vec2 { x: x[entity], y: layer_y[layer_of[entity]] })
+        .collect();
+
+    honor_pins(graph, sizes, &mut positions);
+
+    let nodes = positions.into_iter().enumerate()
+        .map(|(entity, position)| GridNode { entity, position })
+        .collect();
+
+    GridPlacements { nodes, edges: Vec::new() }
+}
+
+/// Which entity of the relation goes above the other one. Supertypes go above their
+/// subtypes, wholes above their parts, and arrows point down. Relations without a
+/// direction are read the way they're written, so the first entity goes on top.
+fn direction(relation: &Relation) -> (EntityIndex, EntityIndex) {
+    let is_above = |own_end: Arrow, other_end: Arrow| {
+        matches!(own_end, Arrow::Inheritance | Arrow::Aggregation | Arrow::Composition) || other_end == Arrow::Arrow
+    };
+
+    match (is_above(relation.arrow_1, relation.arrow_2), is_above(relation.arrow_2, relation.arrow_1)) {
+        (false, true) => (relation.entity_2, relation.entity_1),
+        _ => (relation.entity_1, relation.entity_2)
+    }
+}
+
+/// Flips every edge that closes a cycle, found with a depth-first search
+fn break_cycles(node_count: usize, edges: &mut [(NodeID, NodeID)]) {
+    #[derive(Clone, Copy, PartialEq)]
+    enum Visit { New, InProgress, Done }
+
+    fn visit(node: NodeID, edges: &mut [(NodeID, NodeID)], visits: &mut [Visit]) {
+        visits[node] = Visit::InProgress;
+        for index in 0..edges.len() {
+            let (upper, lower) = edges[index];
+            if upper != node { continue }
+            match visits[lower] {
+                Visit::New => visit(lower, edges, visits),
+                Visit::InProgress => edges[index] = (lower, upper), // Points back up the path, so it closes a cycle
+                Visit::Done => {}
+            }
+        }
+        visits[node] = Visit::Done;
+    }
+
+    let mut visits = vec![Visit::New; node_count];
+    for node in 0..node_count {
+        if visits[node] == Visit::New {
+            visit(node, edges, &mut visits);
+        }
+    }
+}
+
+/// Puts every node one layer below the lowest node above it, which needs the edges to have no cycles
+fn assign_layers(node_count: usize, edges: &[(NodeID, NodeID)]) -> Vec<usize> {
+    let mut layer_of = vec![0; node_count];
+    let mut remaining_uppers = vec![0; node_count];
+    for &(_, lower) in edges {
+        remaining_uppers[lower] += 1;
+    }
+
+    // Nodes are only placed once everything above them is
+    let mut ready: Vec<NodeID> = (0..node_count).filter(|&node| remaining_uppers[node] == 0).collect();
+    while let Some(node) = ready.pop() {
+        for &(upper, lower) in edges {
+            if upper != node { continue }
+            layer_of[lower] = usize::max(layer_of[lower], layer_of[node] + 1);
+            remaining_uppers[lower] -= 1;
+            if remaining_uppers[lower] == 0 {
+                ready.push(lower);
+            }
+        }
+    }
+
+    layer_of
+}
+
+/// Reorders the nodes of each layer by the average position of their neighbors in
+/// the layer before, going down and then up, and keeps the order with the fewest crossings
+fn order_layers(layers: &mut [Vec<NodeID>], upper_neighbors: &[Vec<NodeID>], lower_neighbors: &[Vec<NodeID>]) {
+    let mut best = (count_crossings(layers, lower_neighbors), layers.to_vec());
+
+    for sweep in 0..ORDERING_SWEEPS * 2 {
+        if best.0 == 0 { break }
+
+        if sweep % 2 == 0 {
+            for layer in 1..layers.len() {
+                let (above, below) = layers.split_at_mut(layer);
+                sort_by_barycenter(&mut below[0], &above[layer - 1], upper_neighbors);
+            }
+        } else {
+            for layer in (0..layers.len().saturating_sub(1)).rev() {
+                let (above, below) = layers.split_at_mut(layer + 1);
+                sort_by_barycenter(&mut above[layer], &below[0], lower_neighbors);
+            }
+        }
+
+        let crossings = count_crossings(layers, lower_neighbors);
+        if crossings < best.0 {
+            best = (crossings, layers.to_vec());
+        }
+    }
+
+    layers.clone_from_slice(&best.1);
+}
+
+fn sort_by_barycenter(layer: &mut [NodeID], fixed_layer: &[NodeID], neighbors: &[Vec<NodeID>]) {
+    let index_in_fixed = |node: NodeID| fixed_layer.iter().position(|&other| other == node);
+
+    let barycenters: Vec<(NodeID, f32)> = layer.iter().enumerate().map(|(index, &node)| {
+        let indices: Vec<usize> = neighbors[node].iter().filter_map(|&neighbor| index_in_fixed(neighbor)).collect();
+        if indices.is_empty() {
+            (node, index as f32) // Nothing to follow, so it stays where it is
+        } else {
+            (node, indices.iter().sum::<usize>() as f32 / indices.len() as f32)
+        }
+    }).collect();
+
+    let mut sorted = barycenters;
+    sorted.sort_by(|(_, a), (_, b)| a.total_cmp(b));
+    for (slot, (node, _)) in layer.iter_mut().zip(sorted) {
+        *slot = node;
+    }
+}
+
+/// Counts the pairs of edges that cross between each layer and the next
+fn count_crossings(layers: &[Vec<NodeID>], lower_neighbors: &[Vec<NodeID>]) -> usize {
+    let mut crossings = 0;
+
+    for pair in layers.windows(2) {
+        let (upper_layer, lower_layer) = (&pair[0], &pair[1]);
+        let index_in_lower = |node: NodeID| lower_layer.iter().position(|&other| other == node).unwrap_or(0);
+
+        let edges: Vec<(usize, usize)> = upper_layer.iter().enumerate()
+            .flat_map(|(upper_index, &upper)| lower_neighbors[upper].iter().map(move |&lower| (upper_index, lower)))
+            .map(|(upper_index, lower)| (upper_index, index_in_lower(lower)))
+            .collect();
+
+        for (i, &(upper_1, lower_1)) in edges.iter().enumerate() {
+            for &(upper_2, lower_2) in edges[i + 1..].iter() {
+                if (upper_1 < upper_2 && lower_1 > lower_2) || (upper_1 > upper_2 && lower_1 < lower_2) {
+                    crossings += 1;
+                }
+            }
+        }
+    }
+
+    crossings
+}
+
+/// How far apart the centers of two neighboring nodes in a layer have to be, so
+/// there's `NODE_GAP` between their boxes. It's whole grid units, so rounding the
+/// positions to the grid never closes the gap.
+fn spacing(widths: &[f32], left: NodeID, right: NodeID) -> f32 {
+    ((widths[left] + widths[right]) / 2.0 + NODE_GAP).ceil()
+}
+
+/// Gives every node an x coordinate, keeping the order of each layer and the spacing
+/// between neighbors, while moving nodes toward their neighbors in the layer before
+fn position_nodes(layers: &[Vec<NodeID>], widths: &[f32], upper_neighbors: &[Vec<NodeID>], lower_neighbors: &[Vec<NodeID>]) -> Vec<f32> {
+    let mut x = vec![0.0; upper_neighbors.len()];
+    for layer in layers.iter() {
+        for index in 1..layer.len() {
+            x[layer[index]] = x[layer[index - 1]] + spacing(widths, layer[index - 1], layer[index]);
+        }
+        // Centered around 0
+        let (Some(&first), Some(&last)) = (layer.first(), layer.last()) else { continue };
+        let center = (x[first] + x[last]) / 2.0;
+        for &node in layer.iter() {
+            x[node] -= center;
+        }
+    }
+
+    for _ in 0..POSITIONING_SWEEPS {
+        for layer in layers.iter().skip(1) {
+            align_layer(layer, widths, upper_neighbors, &mut x);
+        }
+        for layer in layers.iter().rev().skip(1) {
+            align_layer(layer, widths, lower_neighbors, &mut x);
+        }
+    }
+
+    x.iter().map(|x| x.round()).collect()
+}
+
+fn align_layer(layer: &[NodeID], widths: &[f32], neighbors: &[Vec<NodeID>], x: &mut [f32]) {
+    let desired: Vec<f32> = layer.iter().map(|&node| {
+        if neighbors[node].is_empty() {
+            x[node]
+        } else {
+            neighbors[node].iter().map(|&neighbor| x[neighbor]).sum::<f32>() / neighbors[node].len() as f32
+        }
+    }).collect();
+
+    // Packing the layer from the left and from the right both keep the gaps, and
+    // so does their average, which doesn't lean to either side
+    let mut from_left = desired.clone();
+    for index in 1..from_left.len() {
+        from_left[index] = f32::max(from_left[index], from_left[index - 1] + spacing(widths, layer[index - 1], layer[index]));
+    }
+    let mut from_right = desired;
+    for index in (0..from_right.len().saturating_sub(1)).rev() {
+        from_right[index] = f32::min(from_right[index], from_right[index + 1] - spacing(widths, layer[index], layer[index + 1]));
+    }
+
+    for (index, &node) in layer.iter().enumerate() {
+        x[node] = (from_left[index] + from_right[index]) / 2.0;
+    }
+}
+
+/// Moves the layout so the first pinned entity is where it was pinned, puts the
+/// other pinned entities where they were pinned, and moves anything they land on out of the way
+fn honor_pins(graph: &Graph, sizes: &[Vec2], positions: &mut [Vec2]) {
+    let Some((&anchor, &anchor_pin)) = graph.pins.iter().filter(|(entity, _)| **entity < positions.len()).min_by_key(|(entity, _)| **entity) else {
+        return;
+    };
+
+    let offset = anchor_pin - positions[anchor];
+    let offset = Vec2 { x: offset.x.round(), y: offset.y.round() };
+    for position in positions.iter_mut() {
+        *position += offset;
+    }
+
+    for (&entity, &pin) in graph.pins.iter() {
+        if let Some(position) = positions.get_mut(entity) {
+            *position = pin;
+        }
+    }
+
+    let overlaps = |positions: &[Vec2], entity: EntityIndex, other: EntityIndex| {
+        let (offset, reach) = (positions[other] - positions[entity], (sizes[entity] + sizes[other]) / 2.0);
+        offset.x.abs() < reach.x && offset.y.abs() < reach.y
+    };
+    for entity in 0..positions.len() {
+        if graph.pins.contains_key(&entity) { continue }
+        while (0..positions.len()).any(|other| other != entity && overlaps(positions, entity, other)) {
+            positions[entity].x += NODE_GAP;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{domain_model::{graph::test::*, parser::parse_graph}, image_generation::layout::metrics::measure};
+
+    use super::*;
+
+    fn graph_with(entity_count: usize, relations: Vec<Relation>) -> Graph {
+        let mut graph = Graph::new();
+        graph.entities = (0..entity_count).map(|_| dummy_entity()).collect();
+        graph.relations = relations;
+        graph
+    }
+
+    #[test]
+    fn relations_point_down() {
+        let inheritance = |subtype, supertype| Relation { arrow_2: Arrow::Inheritance, ..dummy_relation(subtype, supertype) };
+        let composition = |whole, part| Relation { arrow_1: Arrow::Composition, arrow_2: Arrow::None, ..dummy_relation(whole, part) };
+        let graph = graph_with(5, vec![dummy_relation(0, 1), dummy_relation(1, 2), inheritance(3, 2), composition(3, 4), dummy_relation(0, 4)]);
+
+        let grid = place(&graph, &box_sizes(&graph, None));
+
+        println!("{}", grid);
+
+        for (upper, lower) in [(0, 1), (1, 2), (2, 3), (3, 4), (0, 4)] {
+            let (upper_pos, lower_pos) = (grid.nodes[upper].position, grid.nodes[lower].position);
+            assert!(upper_pos.y < lower_pos.y, "Entity {} should be above entity {}! ({:?} vs {:?})", upper, lower, upper_pos, lower_pos);
+        }
+    }
+
+    #[test]
+    fn crossings_are_removed() {
+        // Entities 2 and 3 start out in the wrong order for 0 and 1
+        let graph = graph_with(6, vec![dummy_relation(0, 3), dummy_relation(1, 2), dummy_relation(3, 4), dummy_relation(2, 5)]);
+
+        let grid = place(&graph, &box_sizes(&graph, None));
+
+        println!("{}", grid);
+
+        let x = |entity: usize| grid.nodes[entity].position.x;
+        assert_eq!(x(0) < x(1), x(3) < x(2));
+        assert_eq!(x(3) < x(2), x(4) < x(5));
+    }
+
+    #[test]
+    fn cycles_and_self_relations_are_placed() {
+        let graph = graph_with(4, vec![dummy_relation(0, 1), dummy_relation(1, 2), dummy_relation(2, 0), dummy_relation(2, 2), dummy_relation(2, 3)]);
+
+        let grid = place(&graph, &box_sizes(&graph, None));
+
+        println!("{}", grid);
+
+        assert_eq!(grid.nodes.len(), 4);
+        for node in grid.nodes.iter() {
+            for other in grid.nodes.iter() {
+                if node.entity == other.entity { continue }
+                assert!(node.position != other.position, "Entities {} and {} are on the same point on the grid! ({:?})", node.entity, other.entity, node.position);
+            }
+        }
+    }
+
+    #[test]
+    fn big_boxes_get_room() {
+        let input = "Customer-Customer Loyalty Program Enrollment\nCustomer-Order\nCustomer-Invoice\nCustomer-Subscription Renewal Reminder\n\
+            Order { id: Uuid, total: Money, placed: Date, shipped: Date, address: Address, notes: Text, status: Status }\nOrder-Line Item\nInvoice-Payment";
+        let Ok(graph) = parse_graph(input) else { panic!("Parsing failed") };
+
+        let grid = place(&graph, &box_sizes(&graph, None));
+
+        println!("{}", grid);
+
+        assert_eq!(measure(&graph, &grid, None).node_overlaps, 0);
+        // Order's attributes don't run into Line Item below it
+        let index = |name: &str| graph.entities.iter().position(|entity| entity.name == name).unwrap();
+        let (order, line_item) = (index("Order"), index("Line Item"));
+        let sizes = box_sizes(&graph, None);
+        let gap = grid.nodes[line_item].position.y - grid.nodes[order].position.y - (sizes[order].y + sizes[line_item].y) / 2.0;
+        assert!(gap >= LAYER_GAP, "Order and Line Item are only {} apart", gap);
+    }
+
+    #[test]
+    fn pins_are_honored() {
+        let mut graph = graph_with(4, vec![dummy_relation(0, 1), dummy_relation(0, 2), dummy_relation(2, 3)]);
+        graph.pins.insert(2, Vec2 { x: 10.0, y: -5.0 });
+        graph.pins.insert(3, Vec2 { x: 10.0, y: -2.0 });
+
+        let grid = place(&graph, &box_sizes(&graph, None));
+
+        println!("{}", grid);
+
+        assert_eq!(grid.position_of(2), Some(Vec2 { x: 10.0, y: -5.0 }));
+        assert_eq!(grid.position_of(3), Some(Vec2 { x: 10.0, y: -2.0 }));
+        // The rest of the layout follows the first pinned entity
+        assert!(grid.nodes[0].position.y < -5.0);
+        for node in grid.nodes.iter() {
+            for other in grid.nodes.iter() {
+                if node.entity == other.entity { continue }
+                assert!((node.position - other.position).chess_length() >= 1.0, "Entities {} and {} overlap! ({:?})", node.entity, other.entity, node.position);
+            }
+        }
+    }
+}
\ No newline at end of file
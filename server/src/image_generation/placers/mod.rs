@@ -1,22 +1,214 @@
-use std::ops::{Add, AddAssign, Div, DivAssign, Mul, MulAssign, Sub, SubAssign};
+use std::{collections::HashMap, fmt, ops::{Add, AddAssign, Div, DivAssign, Mul, MulAssign, Sub, SubAssign}};
 
 use serde::{Deserialize, Serialize};
 
-use crate::domain_model::graph::EntityIndex;
+use crate::{domain_model::{graph::{EntityIndex, Graph}, parser::{Diagnostic, ParseGraphError}}, image_generation::{layout::{labels, routing}, placers::{components::PackingConfig, force_directed::{SimConfig, SimConfigError}}, render::{entity_box_size, GRID_SIZE}}};
 
+pub mod components;
 pub mod force_directed;
+pub mod hierarchical;
+
+/// Places the entities of a graph on a grid
+pub trait Placer {
+    fn place(&self, graph: &Graph, options: &PlacerOptions) -> GridPlacements;
+
+    /// Whether entities that were placed before are put back where they were
+    fn keeps_previous(&self) -> bool {
+        false
+    }
+}
+
+/// The placers that can be picked, by name, from JS or with a `layout` statement
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Layout {
+    #[default]
+    ForceDirected,
+    Hierarchical
+}
+
+impl Layout {
+    pub fn placer(&self) -> &'static dyn Placer {
+        match self {
+            Layout::ForceDirected => &force_directed::ForceDirected,
+            Layout::Hierarchical => &hierarchical::Hierarchical
+        }
+    }
+
+    /// The layout with the name a `layout` statement uses for it
+    pub fn from_name(name: &str) -> Option<Layout> {
+        match name {
+            "force directed" => Some(Layout::ForceDirected),
+            "hierarchical" => Some(Layout::Hierarchical),
+            _ => None
+        }
+    }
+}
+
+/// The layout, simulation config and packing config a graph's `layout` and `config` statements pick
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Directives {
+    pub layout: Option<Layout>,
+    pub sim_config: SimConfig,
+    pub packing: PackingConfig
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum DirectiveError {
+    /// The layout isn't one of "force directed" or "hierarchical"
+    UnknownLayout(String),
+    /// A `config` statement named a setting that doesn't exist, or gave it a value it can't have
+    InvalidConfig(SimConfigError)
+}
+
+impl fmt::Display for DirectiveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DirectiveError::UnknownLayout(name) => write!(f, "Unknown layout \"{}\". Try \"force directed\" or \"hierarchical\".", name),
+            DirectiveError::InvalidConfig(e) => write!(f, "Invalid config: {}", e)
+        }
+    }
+}
+
+/// Reads what the graph's `layout` and `config` statements mean. An unknown layout is
+/// left out, and settings are applied in order, skipping the ones that can't be applied.
+/// Everything that was left out is returned along with where it was written.
+pub fn read_directives(graph: &Graph) -> (Directives, Vec<Diagnostic<DirectiveError>>) {
+    let mut diagnostics = Vec::new();
+
+    let layout = graph.layout.as_ref().and_then(|layout| {
+        let known = Layout::from_name(&layout.value);
+        if known.is_none() {
+            diagnostics.push(Diagnostic::new(DirectiveError::UnknownLayout(layout.value.clone()), layout.span));
+        }
+        known
+    });
+
+    let (mut sim_config, mut packing) = (SimConfig::default(), PackingConfig::default());
+    for setting in graph.config.iter() {
+        let result = if PackingConfig::SETTINGS.contains(&setting.name.as_str()) {
+            packing.set(&setting.name, setting.value)
+        } else {
+            sim_config.set(&setting.name, setting.value)
+        };
+        if let Err(e) = result {
+            diagnostics.push(Diagnostic::new(DirectiveError::InvalidConfig(e), setting.span));
+        }
+    }
+
+    (Directives { layout, sim_config, packing }, diagnostics)
+}
+
+/// The errors of the graph's `layout` and `config` statements, to report along with
+/// the ones from parsing
+pub fn check_directives(graph: &Graph) -> Vec<Diagnostic> {
+    read_directives(graph).1.into_iter()
+        .map(|diagnostic| Diagnostic::new(ParseGraphError::InvalidLayout(diagnostic.error.to_string()), diagnostic.span))
+        .collect()
+}
+
+/// The simulation config from the options, or else the one from the graph's `config` statements
+pub fn sim_config(graph: &Graph, options: &PlacerOptions) -> SimConfig {
+    options.sim_config.clone().unwrap_or_else(|| read_directives(graph).0.sim_config)
+}
+
+/// The packing config from the options, or else the one from the graph's `config` statements
+pub fn packing_config(graph: &Graph, options: &PlacerOptions) -> PackingConfig {
+    options.packing.clone().unwrap_or_else(|| read_directives(graph).0.packing)
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct PlacerOptions {
+    /// Overrides the graph's own layout, if it has one
+    pub layout: Option<Layout>,
+    /// Replaces the graph's own simulation config, from its `config` statements
+    pub sim_config: Option<SimConfig>,
+    /// Replaces the graph's own packing config, from its `config` statements. The
+    /// packing is done for every layout.
+    pub packing: Option<PackingConfig>,
+    /// Where each entity was placed last time, by name. The force-directed placer
+    /// keeps those entities where they were and only places the new ones.
+    pub previous: Option<HashMap<String, Vec2>>,
+    /// The size of each entity's box in pixels, by name, as measured by the client.
+    /// Entities that aren't in here get a size estimated from their text.
+    pub sizes: Option<HashMap<String, Vec2>>,
+    /// Scatters the force-directed placer's starting positions. The same graph,
+    /// options and seed always give the same placements, on every platform.
+    pub seed: Option<u64>
+}
+
+/// Places the graph with the layout from the options, then the one from the
+/// graph's `layout` statement, and otherwise the force-directed one.
+/// The parts of the graph that aren't related are placed apart and packed together.
+/// The relations are routed around the placed entities afterwards, and then labeled.
+pub fn place(graph: &Graph, options: &PlacerOptions) -> GridPlacements {
+    let layout = options.layout.or_else(|| read_directives(graph).0.layout).unwrap_or_default();
+    let mut grid = components::place_packed(layout.placer(), graph, options);
+
+    let sizes = box_sizes(graph, options.sizes.as_ref());
+    grid.edges = routing::route(graph, &grid, &sizes);
+    labels::place_labels(graph, &mut grid, &sizes);
+    grid
+}
+
+/// The size of each entity's box in grid units, as measured by the client if it
+/// was, and otherwise estimated from its text
+pub fn box_sizes(graph: &Graph, measured: Option<&HashMap<String, Vec2>>) -> Vec<Vec2> {
+    graph.entities.iter().map(|entity| {
+        match measured.and_then(|measured| measured.get(&entity.name)) {
+            Some(&size) => size / GRID_SIZE,
+            None => entity_box_size(entity) / GRID_SIZE
+        }
+    }).collect()
+}
 
-#[cfg_attr(not(test), allow(unused))]
 #[derive(Serialize, Deserialize)]
 pub struct GridPlacements {
-    nodes: Vec<GridNode>
+    pub nodes: Vec<GridNode>,
+    /// The routes of the relations, if they've been routed
+    #[serde(default)]
+    pub edges: Vec<GridEdge>
+}
+
+impl GridPlacements {
+    /// Where the given entity was placed, if it was placed at all
+    pub fn position_of(&self, entity: EntityIndex) -> Option<Vec2> {
+        self.nodes.iter().find(|node| node.entity == entity).map(|node| node.position)
+    }
+
+    /// The route of the given relation, if it was routed
+    pub fn route_of(&self, relation: RelationIndex) -> Option<&[Vec2]> {
+        self.edges.iter().find(|edge| edge.relation == relation).map(|edge| edge.route.as_slice())
+    }
 }
 
-#[cfg_attr(not(test), allow(unused))]
 #[derive(Serialize, Deserialize)]
 pub struct GridNode {
-    entity: EntityIndex,
-    position: Vec2,
+    pub entity: EntityIndex,
+    pub position: Vec2,
+}
+
+pub type RelationIndex = usize;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct GridEdge {
+    pub relation: RelationIndex,
+    /// The corners of the line, in grid units, from the border of the first
+    /// entity's box to the border of the second's
+    pub route: Vec<Vec2>,
+    #[serde(default)]
+    pub labels: EdgeLabels
+}
+
+/// Where the centers of a relation's labels go, in grid units. Labels the
+/// relation doesn't have (or that weren't placed) are left out.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EdgeLabels {
+    pub text: Option<Vec2>,
+    pub mult_1: Option<Vec2>,
+    pub mult_2: Option<Vec2>
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
@@ -48,6 +240,8 @@ impl Vec2 {
         }
     }
 
+    /// # Safety
+    /// The vector's length must not be zero
     pub unsafe fn normalized_unchecked(&self) -> Self {
         *self / f32::sqrt(self.squared_length())
     }
@@ -61,6 +255,8 @@ impl Vec2 {
         }
     }
 
+    /// # Safety
+    /// The vector's taxicab length must not be zero
     pub unsafe fn taxicab_normalized_unchecked(&self) -> Self {
         *self / self.taxicab_length()
     }
@@ -74,6 +270,8 @@ impl Vec2 {
         }
     }
 
+    /// # Safety
+    /// The vector's chess length must not be zero
     pub unsafe fn chess_normalized_unchecked(&self) -> Self {
         *self / self.chess_length()
     }
@@ -200,3 +398,64 @@ impl std::fmt::Display for GridPlacements {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::domain_model::{graph::test::*, parser::{parse_graph, parse_graph_recovering}, span::{Span, Spanned}};
+
+    use super::*;
+
+    #[test]
+    fn directives_are_read() {
+        let input = "layout hierarchical\nconfig iterations: 300\nJack-Jill\nconfig delta time: 5\nconfig gravity: 1\nconfig damping: 0.9\nconfig component gap: 4";
+        let (graph, diagnostics) = parse_graph_recovering(input);
+        assert!(diagnostics.is_empty());
+
+        let (directives, diagnostics) = read_directives(&graph);
+
+        assert_eq!(diagnostics.iter().map(Diagnostic::line).collect::<Vec<_>>(), vec![4, 5]);
+        assert_eq!(directives, Directives {
+            layout: Some(Layout::Hierarchical),
+            sim_config: SimConfig { iterations: 300, damping: 0.9, ..SimConfig::default() },
+            packing: PackingConfig { component_gap: 4.0, ..PackingConfig::default() }
+        });
+    }
+
+    #[test]
+    fn unknown_layouts_are_reported() {
+        let Ok(graph) = parse_graph("Jack-Jill\nlayout sideways") else { panic!("Parsing failed") };
+
+        let (directives, diagnostics) = read_directives(&graph);
+
+        assert_eq!(directives.layout, None);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].line(), 2);
+        assert_eq!(diagnostics[0].error, DirectiveError::UnknownLayout("sideways".to_string()));
+        assert_eq!(check_directives(&graph)[0].to_string(), "Line 2: Unknown layout \"sideways\". Try \"force directed\" or \"hierarchical\".");
+    }
+
+    #[test]
+    fn options_override_the_graph_layout() {
+        let mut graph = Graph::new();
+        graph.entities = vec![dummy_entity(), dummy_entity(), dummy_entity()];
+        graph.relations = vec![dummy_relation(0, 1), dummy_relation(1, 2)];
+        graph.layout = Some(Spanned::new("hierarchical".to_string(), Span::default()));
+
+        let positions = |grid: GridPlacements| grid.nodes.into_iter().map(|node| node.position).collect::<Vec<_>>();
+        let hierarchical = positions(hierarchical::place(&graph, &box_sizes(&graph, None)));
+        let force_directed = positions(force_directed::ForceDirected.place(&graph, &PlacerOptions::default()));
+
+        assert_eq!(positions(place(&graph, &PlacerOptions::default())), hierarchical);
+        assert_eq!(positions(place(&graph, &PlacerOptions { layout: Some(Layout::ForceDirected), ..PlacerOptions::default() })), force_directed);
+    }
+
+    #[test]
+    fn seeded_placements_are_identical() {
+        let Ok(graph) = parse_graph("Customer-places->Order\nOrder<#>1-contains-1..Line Item\nLine Item-Product\nProduct<|-Book\nCustomer-Address\nOrder-Address\nWarehouse-Product") else { panic!("Parsing failed") };
+        let placed = |seed: Option<u64>| serde_json::to_string(&place(&graph, &PlacerOptions { seed, ..PlacerOptions::default() })).unwrap();
+
+        assert_eq!(placed(None), placed(None));
+        assert_eq!(placed(Some(7)), placed(Some(7)));
+        assert_ne!(placed(Some(7)), placed(Some(8)));
+    }
+}
@@ -0,0 +1,99 @@
+use crate::{domain_model::graph::Entity, image_generation::placers::Vec2};
+
+pub mod svg;
+
+/// How many pixels one unit of the grid takes up
+pub const GRID_SIZE: f32 = 50.0;
+
+/// The font size of entity names and attributes, in pixels
+pub const ENTITY_FONT_SIZE: f32 = 16.0;
+/// The font size of relation labels and multiplicities, in pixels
+pub const RELATION_FONT_SIZE: f32 = 12.0;
+/// The height of one line of text in an entity's box
+pub const LINE_HEIGHT: f32 = 20.0;
+/// The space between an entity's border and its text
+pub const PADDING: f32 = 8.0;
+/// The narrowest an entity's text can be, so short names still get a decent box
+pub const MIN_TEXT_WIDTH: f32 = 80.0;
+
+/// Without a browser to measure text, every character is assumed to be this
+/// wide (relative to the font size). It's a bit generous for most fonts, so
+/// text doesn't spill out of its box.
+const CHARACTER_WIDTH: f32 = 0.6;
+
+/// Roughly how many pixels wide the text is at the given font size
+pub fn text_width(text: &str, font_size: f32) -> f32 {
+    text.chars().count() as f32 * font_size * CHARACTER_WIDTH
+}
+
+/// The lines of text in an entity's box that come after its name
+pub fn attribute_lines(entity: &Entity) -> Vec<String> {
+    entity.attributes.iter().map(|attribute| attribute.to_string()).collect()
+}
+
+/// The size of the box an entity is drawn in, in pixels
+pub fn entity_box_size(entity: &Entity) -> Vec2 {
+    let lines = attribute_lines(entity);
+
+    let text_width = std::iter::once(&entity.name)
+        .chain(lines.iter())
+        .map(|line| text_width(line, ENTITY_FONT_SIZE))
+        .fold(MIN_TEXT_WIDTH, f32::max);
+
+    let mut height = LINE_HEIGHT + 2.0 * PADDING;
+    if !lines.is_empty() {
+        // The attributes sit in their own section below the name
+        height += lines.len() as f32 * LINE_HEIGHT + PADDING;
+    }
+
+    Vec2 { x: text_width + 2.0 * PADDING, y: height }
+}
+
+/// The point where a line from the center of a box towards `target` leaves the box
+pub fn clip_to_box(center: Vec2, size: Vec2, target: Vec2) -> Vec2 {
+    let direction = target - center;
+
+    let scale_x = if direction.x.abs() < f32::EPSILON { f32::INFINITY } else { size.x / 2.0 / direction.x.abs() };
+    let scale_y = if direction.y.abs() < f32::EPSILON { f32::INFINITY } else { size.y / 2.0 / direction.y.abs() };
+    let scale = f32::min(scale_x, scale_y);
+
+    if scale.is_finite() && scale < 1.0 {
+        center + direction * scale
+    } else {
+        // The target is inside the box (or is its center), so there's nothing to clip
+        center
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain_model::graph::{Attribute, Multiplicity, test::dummy_entity};
+    use crate::domain_model::span::Span;
+
+    use super::*;
+
+    #[test]
+    fn boxes_grow_with_their_text() {
+        let mut entity = dummy_entity();
+        entity.name = "A".to_string();
+        let small = entity_box_size(&entity);
+
+        entity.name = "A Very Long Entity Name Indeed".to_string();
+        let wide = entity_box_size(&entity);
+        assert!(wide.x > small.x);
+        assert_eq!(wide.y, small.y);
+
+        entity.attributes.push(Attribute { name: "id".to_string(), ty: None, visibility: None, multiplicity: Multiplicity::None, span: Span::default() });
+        let tall = entity_box_size(&entity);
+        assert!(tall.y > wide.y);
+    }
+
+    #[test]
+    fn lines_are_clipped_at_the_border() {
+        let size = Vec2 { x: 100.0, y: 40.0 };
+
+        assert_eq!(clip_to_box(Vec2 { x: 0.0, y: 0.0 }, size, Vec2 { x: 200.0, y: 0.0 }), Vec2 { x: 50.0, y: 0.0 });
+        assert_eq!(clip_to_box(Vec2 { x: 0.0, y: 0.0 }, size, Vec2 { x: 0.0, y: -100.0 }), Vec2 { x: 0.0, y: -20.0 });
+        assert_eq!(clip_to_box(Vec2 { x: 0.0, y: 0.0 }, size, Vec2 { x: 10.0, y: 5.0 }), Vec2 { x: 0.0, y: 0.0 });
+    }
+}
\ No newline at end of file
@@ -0,0 +1,310 @@
+use std::fmt::Write;
+
+use crate::{domain_model::graph::{Arrow, Entity, Graph, Relation, Style}, image_generation::{layout::labels::{point_at, route_length}, placers::{EdgeLabels, GridPlacements, Vec2}, render::{attribute_lines, clip_to_box, entity_box_size, ENTITY_FONT_SIZE, GRID_SIZE, LINE_HEIGHT, PADDING, RELATION_FONT_SIZE}}};
+
+/// The empty space around the diagram
+const MARGIN: f32 = 20.0;
+/// How far from the end of a relation its multiplicity is drawn
+const MULTIPLICITY_OFFSET: f32 = 24.0;
+
+const ARROW_HEAD_SIZE: f32 = 5.0;
+const DIAMOND_SIZE: f32 = 8.0;
+
+/// A placed entity's box, in pixels
+struct EntityBox {
+    center: Vec2,
+    size: Vec2
+}
+
+impl EntityBox {
+    fn top_left(&self) -> Vec2 {
+        self.center - self.size / 2.0
+    }
+
+    fn bottom_right(&self) -> Vec2 {
+        self.center + self.size / 2.0
+    }
+}
+
+/// Draws the graph as a standalone SVG document.
+/// Entities that don't have a placement (and the relations to them) are left out.
+pub fn render(graph: &Graph, placements: &GridPlacements) -> String {
+    let boxes: Vec<Option<EntityBox>> = graph.entities.iter().enumerate().map(|(index, entity)| {
+        placements.position_of(index).map(|position| EntityBox {
+            center: position * GRID_SIZE,
+            size: entity_box_size(entity)
+        })
+    }).collect();
+
+    let (min, max) = {
+        let mut placed = boxes.iter().flatten().peekable();
+        if placed.peek().is_none() {
+            (Vec2 { x: 0.0, y: 0.0 }, Vec2 { x: 0.0, y: 0.0 })
+        } else {
+            // Routes can go around the outside of the boxes, so their corners count too
+            let corners = placements.edges.iter().flat_map(|edge| edge.route.iter()).map(|&point| (point * GRID_SIZE, point * GRID_SIZE));
+            placed.map(|entity_box| (entity_box.top_left(), entity_box.bottom_right())).chain(corners).fold(
+                (Vec2 { x: f32::INFINITY, y: f32::INFINITY }, Vec2 { x: f32::NEG_INFINITY, y: f32::NEG_INFINITY }),
+                |(min, max), (top_left, bottom_right)| {
+                    (
+                        Vec2 { x: min.x.min(top_left.x), y: min.y.min(top_left.y) },
+                        Vec2 { x: max.x.max(bottom_right.x), y: max.y.max(bottom_right.y) }
+                    )
+                }
+            )
+        }
+    };
+
+    let origin = min - Vec2 { x: MARGIN, y: MARGIN };
+    let size = max - min + Vec2 { x: 2.0 * MARGIN, y: 2.0 * MARGIN };
+
+    let mut svg = String::new();
+
+    // Writing to a String never fails, so the results are safe to ignore
+    let _ = writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{:.1}" height="{:.1}" viewBox="{:.1} {:.1} {:.1} {:.1}" font-family="sans-serif">"#,
+        size.x, size.y, origin.x, origin.y, size.x, size.y
+    );
+    write_markers(&mut svg);
+    let _ = writeln!(svg, r#"<rect x="{:.1}" y="{:.1}" width="{:.1}" height="{:.1}" fill="white"/>"#, origin.x, origin.y, size.x, size.y);
+
+    for (index, relation) in graph.relations.iter().enumerate() {
+        if let (Some(Some(box_1)), Some(Some(box_2))) = (boxes.get(relation.entity_1), boxes.get(relation.entity_2)) {
+            // Relations that weren't routed go straight from one box to the other
+            let (route, labels) = match placements.edges.iter().find(|edge| edge.relation == index) {
+                Some(edge) => (
+                    edge.route.iter().map(|&point| point * GRID_SIZE).collect(),
+                    EdgeLabels {
+                        text: edge.labels.text.map(|point| point * GRID_SIZE),
+                        mult_1: edge.labels.mult_1.map(|point| point * GRID_SIZE),
+                        mult_2: edge.labels.mult_2.map(|point| point * GRID_SIZE)
+                    }
+                ),
+                None => (
+                    vec![clip_to_box(box_1.center, box_1.size, box_2.center), clip_to_box(box_2.center, box_2.size, box_1.center)],
+                    EdgeLabels::default()
+                )
+            };
+            write_relation(&mut svg, relation, &route, &labels);
+        }
+    }
+
+    for (entity, entity_box) in graph.entities.iter().zip(boxes.iter()) {
+        if let Some(entity_box) = entity_box {
+            write_entity(&mut svg, entity, entity_box);
+        }
+    }
+
+    svg.push_str("</svg>\n");
+    svg
+}
+
+fn write_markers(svg: &mut String) {
+    let (a, d) = (ARROW_HEAD_SIZE, DIAMOND_SIZE);
+
+    svg.push_str("<defs>\n");
+    let _ = writeln!(
+        svg,
+        r#"<marker id="arrowhead" markerWidth="{}" markerHeight="{}" refX="{}" refY="{}" orient="auto-start-reverse"><path d="M 0 1 L {} {} L 0 {}" stroke="black" fill="none"/></marker>"#,
+        a + 2.0, a + 2.0, a, a / 2.0 + 1.0,
+        a, a / 2.0 + 1.0, a + 1.0
+    );
+    let _ = writeln!(
+        svg,
+        r#"<marker id="triangle" markerWidth="{}" markerHeight="{}" refX="{}" refY="{}" orient="auto-start-reverse"><path d="M 1 1 L {} {} L 1 {} Z" stroke="black" fill="white"/></marker>"#,
+        d + 2.0, d + 2.0, d + 1.0, d / 2.0 + 1.0,
+        d + 1.0, d / 2.0 + 1.0, d + 1.0
+    );
+    for (id, fill) in [("hollow-diamond", "white"), ("filled-diamond", "black")] {
+        let _ = writeln!(
+            svg,
+            r#"<marker id="{}" markerWidth="{}" markerHeight="{}" refX="{}" refY="{}" orient="auto-start-reverse"><path d="M 1 {} L {} 1 L {} {} L {} {} Z" stroke="black" fill="{}"/></marker>"#,
+            id, 2.0 * d + 2.0, d + 2.0, 2.0 * d + 1.0, d / 2.0 + 1.0,
+            d / 2.0 + 1.0, d + 1.0, 2.0 * d + 1.0, d / 2.0 + 1.0, d + 1.0, d + 1.0, fill
+        );
+    }
+    svg.push_str("</defs>\n");
+}
+
+/// Draws the relation along its route, which goes from the first entity's box to the second's, in pixels.
+/// Labels without an anchor go halfway along the route, or near its ends for multiplicities.
+fn write_relation(svg: &mut String, relation: &Relation, route: &[Vec2], labels: &EdgeLabels) {
+    let (Some(&start), Some(&end)) = (route.first(), route.last()) else { return };
+
+    let mut attributes = style_attributes(relation.style);
+    if let Some(marker) = marker_id(relation.arrow_1) {
+        let _ = write!(attributes, r#" marker-start="url(#{})""#, marker);
+    }
+    if let Some(marker) = marker_id(relation.arrow_2) {
+        let _ = write!(attributes, r#" marker-end="url(#{})""#, marker);
+    }
+
+    let points: Vec<String> = route.iter().map(|point| format!("{:.1},{:.1}", point.x, point.y)).collect();
+    let _ = writeln!(svg, r#"<polyline points="{}" fill="none" stroke="black"{}/>"#, points.join(" "), attributes);
+
+    if let Some(text) = &relation.text {
+        let halfway = point_at(route, route_length(route) / 2.0).map_or(start, |(point, _)| point);
+        write_relation_text(svg, labels.text.unwrap_or(halfway), text);
+    }
+
+    // The multiplicities go along the first and last stretch of the route
+    let direction = |from: Vec2, to: Vec2| (to - from).normalized().unwrap_or(Vec2 { x: 0.0, y: 0.0 });
+    let (start_direction, end_direction) = match route {
+        [first, second, ..] => (direction(*first, *second), direction(route[route.len() - 2], end)),
+        _ => (Vec2 { x: 0.0, y: 0.0 }, Vec2 { x: 0.0, y: 0.0 })
+    };
+    for (multiplicity, position) in [
+        (&relation.mult_1, labels.mult_1.unwrap_or(start + start_direction * MULTIPLICITY_OFFSET)),
+        (&relation.mult_2, labels.mult_2.unwrap_or(end - end_direction * MULTIPLICITY_OFFSET))
+    ] {
+        let text = multiplicity.to_string();
+        if !text.is_empty() {
+            write_relation_text(svg, position, &text);
+        }
+    }
+}
+
+/// Relation text sits on top of the line, so it gets a white outline to stay readable
+fn write_relation_text(svg: &mut String, position: Vec2, text: &str) {
+    let _ = writeln!(
+        svg,
+        r#"<text x="{:.1}" y="{:.1}" font-size="{}" text-anchor="middle" dominant-baseline="central" stroke="white" stroke-width="6" stroke-linejoin="round" paint-order="stroke">{}</text>"#,
+        position.x, position.y, RELATION_FONT_SIZE, escape(text)
+    );
+}
+
+fn write_entity(svg: &mut String, entity: &Entity, entity_box: &EntityBox) {
+    let top_left = entity_box.top_left();
+    let size = entity_box.size;
+
+    let _ = writeln!(
+        svg,
+        r##"<rect x="{:.1}" y="{:.1}" width="{:.1}" height="{:.1}" fill="white" stroke="#{:06x}"{}/>"##,
+        top_left.x, top_left.y, size.x, size.y, entity.color, style_attributes(entity.style)
+    );
+    let _ = writeln!(
+        svg,
+        r#"<text x="{:.1}" y="{:.1}" font-size="{}" text-anchor="middle" dominant-baseline="central">{}</text>"#,
+        entity_box.center.x, top_left.y + PADDING + LINE_HEIGHT / 2.0, ENTITY_FONT_SIZE, escape(&entity.name)
+    );
+
+    let lines = attribute_lines(entity);
+    if lines.is_empty() {
+        return;
+    }
+
+    let divider = top_left.y + LINE_HEIGHT + 2.0 * PADDING;
+    let _ = writeln!(
+        svg,
+        r##"<line x1="{:.1}" y1="{:.1}" x2="{:.1}" y2="{:.1}" stroke="#{:06x}"{}/>"##,
+        top_left.x, divider, top_left.x + size.x, divider, entity.color, style_attributes(entity.style)
+    );
+    for (index, line) in lines.iter().enumerate() {
+        let _ = writeln!(
+            svg,
+            r#"<text x="{:.1}" y="{:.1}" font-size="{}" dominant-baseline="central">{}</text>"#,
+            top_left.x + PADDING, divider + PADDING / 2.0 + LINE_HEIGHT * (index as f32 + 0.5), ENTITY_FONT_SIZE, escape(line)
+        );
+    }
+}
+
+/// The stroke attributes that draw a line in the given style
+fn style_attributes(style: Style) -> String {
+    match style {
+        Style::Regular => r#" stroke-width="2""#.to_string(),
+        Style::Dotted => r#" stroke-width="2" stroke-dasharray="2 4""#.to_string(),
+        Style::Dashed => r#" stroke-width="2" stroke-dasharray="8 6""#.to_string(),
+        Style::Bold => r#" stroke-width="4""#.to_string()
+    }
+}
+
+fn marker_id(arrow: Arrow) -> Option<&'static str> {
+    match arrow {
+        Arrow::None => None,
+        Arrow::Arrow => Some("arrowhead"),
+        Arrow::Inheritance => Some("triangle"),
+        Arrow::Aggregation => Some("hollow-diamond"),
+        Arrow::Composition => Some("filled-diamond")
+    }
+}
+
+/// Escapes the characters that have a special meaning in XML
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            c => escaped.push(c)
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain_model::parser::parse_graph;
+    use crate::image_generation::placers::{self, force_directed::{Sim, SimConfig}, PlacerOptions};
+
+    use super::*;
+
+    fn render_input(input: &str) -> String {
+        let Ok(graph) = parse_graph(input) else { panic!("Parsing failed") };
+        let mut sim = Sim::new(&graph, SimConfig::default());
+        sim.run();
+        render(&graph, &sim.build_grid())
+    }
+
+    #[test]
+    fn renders_a_standalone_document() {
+        let svg = render_input("Customer-places-1..>Order\nOrder<#>-Line\nentity Order: #3366ff dashed\nOrder { +id: Uuid }");
+
+        assert!(svg.starts_with("<svg xmlns=\"http://www.w3.org/2000/svg\""));
+        assert!(svg.trim_end().ends_with("</svg>"));
+        assert_eq!(svg.matches("<rect").count(), 4); // The background and three entities
+        assert!(svg.contains(">Customer</text>"));
+        assert!(svg.contains(">places</text>"));
+        assert!(svg.contains(">1..</text>"));
+        assert!(svg.contains(">+id: Uuid</text>"));
+        assert!(svg.contains("marker-end=\"url(#arrowhead)\""));
+        assert!(svg.contains("marker-start=\"url(#filled-diamond)\""));
+        assert!(svg.contains("stroke=\"#3366ff\" stroke-width=\"2\" stroke-dasharray=\"8 6\""));
+    }
+
+    #[test]
+    fn text_is_escaped() {
+        let svg = render_input("\"A & B\"-\"<C>\"");
+
+        assert!(svg.contains(">A &amp; B</text>"));
+        assert!(svg.contains(">&lt;C&gt;</text>"));
+    }
+
+    #[test]
+    fn relations_follow_their_routes() {
+        let Ok(graph) = parse_graph("A-knows-B\nC-D") else { panic!("Parsing failed") };
+        let grid = placers::place(&graph, &PlacerOptions::default());
+
+        let svg = render(&graph, &grid);
+
+        assert_eq!(svg.matches("<polyline").count(), 2);
+        for edge in grid.edges.iter() {
+            let start = edge.route[0] * GRID_SIZE;
+            assert!(svg.contains(&format!("points=\"{:.1},{:.1}", start.x, start.y)));
+        }
+
+        let Some(text) = grid.edges[0].labels.text.map(|point| point * GRID_SIZE) else { panic!("The text wasn't placed") };
+        assert!(svg.contains(&format!("<text x=\"{:.1}\" y=\"{:.1}\"", text.x, text.y)));
+    }
+
+    #[test]
+    fn empty_graph() {
+        let svg = render(&Graph::new(), &GridPlacements { nodes: Vec::new(), edges: Vec::new() });
+
+        assert!(svg.starts_with("<svg"));
+        assert!(!svg.contains("<text"));
+    }
+}
\ No newline at end of file
@@ -1,24 +1,199 @@
 pub mod domain_model;
 pub mod image_generation;
 
+use serde::{Deserialize, Deserializer};
 use wasm_bindgen::prelude::*;
 
-use crate::{domain_model::parser::parse_graph, image_generation::placers::force_directed};
+use crate::{domain_model::{export::{dot, mermaid, plantuml}, graph::Graph, import, parser::{formatter, parse_graph, parse_graph_recovering, Diagnostic, ParseGraphError}, span::Span}, image_generation::{layout::metrics, placers::{self, PlacerOptions}, render::svg}};
+
+/// Reads the placer options passed from JS, like `{ layout: "hierarchical", simConfig: { iterations: 500 } }`
+/// or `{ previous: { Customer: { x: 0, y: 0 } } }`.
+/// Leaving them out uses the defaults, but options that can't be read
+/// (or configs with invalid settings, or positions and sizes that aren't finite) are an error.
+fn placer_options(options: JsValue) -> Result<PlacerOptions, ParseGraphError> {
+    if options.is_undefined() || options.is_null() {
+        return Ok(PlacerOptions::default());
+    }
+
+    read_placer_options(serde_wasm_bindgen::Deserializer::from(options))
+}
+
+/// Reads and validates placer options from anything they can be deserialized from
+fn read_placer_options<'de, D: Deserializer<'de>>(options: D) -> Result<PlacerOptions, ParseGraphError> {
+    let options = PlacerOptions::deserialize(options).map_err(|e| ParseGraphError::InvalidOptions(e.to_string()))?;
+    check_placer_options(&options)?;
+    Ok(options)
+}
+
+/// Checks that the configs are in range, and that the positions and sizes are numbers
+/// the placers can work with
+fn check_placer_options(options: &PlacerOptions) -> Result<(), ParseGraphError> {
+    if let Some(sim_config) = &options.sim_config {
+        sim_config.validate().map_err(|e| ParseGraphError::InvalidOptions(e.to_string()))?;
+    }
+    if let Some(packing) = &options.packing {
+        packing.validate().map_err(|e| ParseGraphError::InvalidOptions(e.to_string()))?;
+    }
+    // A single NaN would spread to every position the placers work out
+    if let Some((name, position)) = options.previous.iter().flatten().find(|(_, position)| !position.x.is_finite() || !position.y.is_finite()) {
+        return Err(ParseGraphError::InvalidOptions(format!("\"{}\" was placed at ({}, {}), which isn't a position", name, position.x, position.y)));
+    }
+    if let Some((name, size)) = options.sizes.iter().flatten().find(|(_, size)| !(size.x.is_finite() && size.x > 0.0 && size.y.is_finite() && size.y > 0.0)) {
+        return Err(ParseGraphError::InvalidOptions(format!("\"{}\" was measured as {} by {}, which isn't a size", name, size.x, size.y)));
+    }
+    Ok(())
+}
+
+/// Parses the input to place it, so its `layout` and `config` statements have to make sense too
+fn parse_graph_to_place(input: &str) -> Result<Graph, ParseGraphError> {
+    let graph = parse_graph(input)?;
+    match placers::check_directives(&graph).into_iter().next() {
+        Some(diagnostic) => Err(diagnostic.error),
+        None => Ok(graph)
+    }
+}
 
 #[wasm_bindgen]
-pub fn generate_graph(input: &str) -> JsValue {
-    let result = match parse_graph(input) {
-        Ok(graph) => {
-            let mut sim = force_directed::Sim::new(&graph);
-            sim.run();
-            let grid = sim.build_grid();
+pub fn generate_graph(input: &str, options: JsValue) -> JsValue {
+    let result = match (parse_graph_to_place(input), placer_options(options)) {
+        (Ok(graph), Ok(options)) => {
+            let grid = placers::place(&graph, &options);
 
             Ok((graph, grid))
         },
-        Err(e) => {
+        (Err(e), _) | (_, Err(e)) => {
             Err(e)
         }
     };
 
     serde_wasm_bindgen::to_value(&result).unwrap()
+}
+
+/// Like `generate_graph`, but statements that fail to parse are skipped
+/// instead of failing the whole graph. Every error is returned alongside the
+/// graph that could be built.
+#[wasm_bindgen]
+pub fn generate_graph_recovering(input: &str, options: JsValue) -> JsValue {
+    let (graph, mut diagnostics) = parse_graph_recovering(input);
+    diagnostics.extend(placers::check_directives(&graph));
+    diagnostics.sort_by_key(|diagnostic| diagnostic.span.start.offset);
+
+    // The options aren't part of the source, so their errors don't point anywhere in it
+    let options = placer_options(options).unwrap_or_else(|e| {
+        diagnostics.push(Diagnostic::new(e, Span::default()));
+        PlacerOptions::default()
+    });
+    let grid = placers::place(&graph, &options);
+
+    serde_wasm_bindgen::to_value(&(graph, grid, diagnostics)).unwrap()
+}
+
+/// Lays out the graph and draws it as a standalone SVG document, without
+/// needing a browser to measure anything.
+#[wasm_bindgen]
+pub fn render_svg(input: &str, options: JsValue) -> JsValue {
+    let result = placer_options(options).and_then(|options| parse_graph_to_place(input).map(|graph| {
+        let grid = placers::place(&graph, &options);
+
+        svg::render(&graph, &grid)
+    }));
+
+    serde_wasm_bindgen::to_value(&result).unwrap()
+}
+
+/// Lays out the graph and measures how readable the layout is, to compare
+/// placers and their settings
+#[wasm_bindgen]
+pub fn layout_metrics(input: &str, options: JsValue) -> JsValue {
+    let result = placer_options(options).and_then(|options| parse_graph_to_place(input).map(|graph| {
+        let grid = placers::place(&graph, &options);
+
+        metrics::measure(&graph, &grid, options.sizes.as_ref())
+    }));
+
+    serde_wasm_bindgen::to_value(&result).unwrap()
+}
+
+/// Rewrites the source the canonical way, keeping its comments. Fails with every
+/// diagnostic if the source doesn't parse.
+#[wasm_bindgen]
+pub fn format_source(input: &str) -> JsValue {
+    let result = formatter::format(input);
+
+    serde_wasm_bindgen::to_value(&result).unwrap()
+}
+
+/// Writes the graph as Graphviz DOT. Pinned entities keep their positions if `pins` is set.
+#[wasm_bindgen]
+pub fn export_dot(input: &str, pins: bool) -> JsValue {
+    let result = parse_graph(input).map(|graph| dot::export(&graph, &dot::DotOptions { pins }));
+
+    serde_wasm_bindgen::to_value(&result).unwrap()
+}
+
+/// Writes the graph as a Mermaid class diagram
+#[wasm_bindgen]
+pub fn export_mermaid(input: &str) -> JsValue {
+    let result = parse_graph(input).map(|graph| mermaid::export(&graph));
+
+    serde_wasm_bindgen::to_value(&result).unwrap()
+}
+
+/// Writes the graph as a PlantUML class diagram
+#[wasm_bindgen]
+pub fn export_plantuml(input: &str) -> JsValue {
+    let result = parse_graph(input).map(|graph| plantuml::export(&graph));
+
+    serde_wasm_bindgen::to_value(&result).unwrap()
+}
+
+/// Translates a PlantUML class diagram into this tool's language. The graph's
+/// `raw` is the translated source, and lines that couldn't be translated are
+/// returned as diagnostics.
+#[wasm_bindgen]
+pub fn import_plantuml(input: &str) -> JsValue {
+    let (graph, diagnostics) = import::plantuml::import(input);
+
+    serde_wasm_bindgen::to_value(&(graph, diagnostics)).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use std::collections::HashMap;
+
+    use crate::image_generation::placers::{Layout, Vec2};
+
+    use super::*;
+
+    #[test]
+    fn placer_options_are_read() {
+        let Ok(options) = read_placer_options(json!({ "layout": "hierarchical", "seed": 7 })) else { panic!("Reading failed") };
+
+        assert_eq!(options.layout, Some(Layout::Hierarchical));
+        assert_eq!(options.seed, Some(7));
+    }
+
+    #[test]
+    fn malformed_placer_options_are_errors() {
+        for options in [json!({ "layout": "hierarchic" }), json!({ "seed": -1 }), json!({ "simConfig": { "iterations": "500" } }), json!({ "packing": { "aspectRatio": 0 } })] {
+            let result = read_placer_options(options.clone());
+
+            assert!(matches!(result, Err(ParseGraphError::InvalidOptions(_))), "{} was read", options);
+        }
+    }
+
+    #[test]
+    fn positions_and_sizes_have_to_be_numbers() {
+        let by_name = |x, y| Some(HashMap::from([("A".to_string(), Vec2 { x, y })]));
+
+        assert!(check_placer_options(&PlacerOptions { previous: by_name(-3.0, 2.0), sizes: by_name(80.0, 36.0), ..PlacerOptions::default() }).is_ok());
+        for previous in [by_name(0.0, f32::NAN), by_name(f32::INFINITY, 0.0)] {
+            assert!(matches!(check_placer_options(&PlacerOptions { previous, ..PlacerOptions::default() }), Err(ParseGraphError::InvalidOptions(_))));
+        }
+        for sizes in [by_name(0.0, 20.0), by_name(80.0, -20.0), by_name(f32::NAN, 20.0), by_name(80.0, f32::INFINITY)] {
+            assert!(matches!(check_placer_options(&PlacerOptions { sizes, ..PlacerOptions::default() }), Err(ParseGraphError::InvalidOptions(_))));
+        }
+    }
 }
\ No newline at end of file
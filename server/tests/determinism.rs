@@ -0,0 +1,25 @@
+//! Layouts have to come out the same natively and in the browser, so that diagrams
+//! rendered by `dmm` match the web app. This runs natively with `cargo test` and
+//! in wasm with `wasm-pack test --node`.
+
+use server::{domain_model::parser::parse_graph, image_generation::placers::{place, Layout, PlacerOptions, Vec2}};
+use wasm_bindgen_test::wasm_bindgen_test;
+
+#[wasm_bindgen_test]
+#[cfg_attr(not(target_arch = "wasm32"), test)]
+fn seeds_give_the_same_placements_everywhere() {
+    let Ok(graph) = parse_graph("Customer-places->Order\nOrder<#>-Line Item\nLine Item->Product\nProduct-Customer\nPayment<|-Card Payment") else { panic!("Parsing failed") };
+
+    // Placed natively, so if these change, the placers changed
+    let expected = [
+        (Layout::ForceDirected, [(1.0, 0.0), (-2.0, -3.0), (1.0, -4.0), (1.0, -2.0), (1.0, 3.0), (-1.0, 5.0)]),
+        (Layout::Hierarchical, [(-1.0, -4.0), (-3.0, -1.0), (-3.0, 2.0), (-1.0, 5.0), (4.0, -4.0), (4.0, -1.0)])
+    ];
+
+    for (layout, expected) in expected {
+        let grid = place(&graph, &PlacerOptions { layout: Some(layout), seed: Some(42), ..PlacerOptions::default() });
+        let positions: Vec<Vec2> = grid.nodes.iter().map(|node| node.position).collect();
+
+        assert_eq!(positions, expected.map(|(x, y)| Vec2 { x, y }), "{:?} placed the entities differently", layout);
+    }
+}
\ No newline at end of file